@@ -0,0 +1,176 @@
+// clipping.rs
+// Sutherland-Hodgman clipping of triangles against the view frustum in
+// homogeneous clip space, before the perspective division.
+use crate::vertex::Vertex;
+use nalgebra_glm::Vec4;
+
+#[derive(Debug, Clone, Copy)]
+enum ClipPlane {
+    Near,
+    Far,
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+// Near goes first: once it is done every remaining vertex has w > 0
+const CLIP_PLANES: [ClipPlane; 6] = [
+    ClipPlane::Near,
+    ClipPlane::Far,
+    ClipPlane::Left,
+    ClipPlane::Right,
+    ClipPlane::Bottom,
+    ClipPlane::Top,
+];
+
+impl ClipPlane {
+    // Signed distance to the plane, positive on the visible side (-w <= x,y,z <= w)
+    fn distance(self, p: &Vec4) -> f32 {
+        match self {
+            ClipPlane::Near => p.z + p.w,
+            ClipPlane::Far => p.w - p.z,
+            ClipPlane::Left => p.x + p.w,
+            ClipPlane::Right => p.w - p.x,
+            ClipPlane::Bottom => p.y + p.w,
+            ClipPlane::Top => p.w - p.y,
+        }
+    }
+}
+
+// Clips a triangle (vertices already in clip space) against all six frustum
+// planes. Returns the resulting convex polygon, which is empty when the
+// triangle is completely outside and has up to 9 vertices otherwise.
+pub fn clip_triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<Vertex> {
+    let mut polygon = vec![*v1, *v2, *v3];
+
+    // Fast path: nothing to do if the triangle is fully inside
+    let fully_inside = CLIP_PLANES.iter().all(|plane| {
+        polygon.iter().all(|v| plane.distance(&v.transformed_position) >= 0.0)
+    });
+    if fully_inside {
+        return polygon;
+    }
+
+    for plane in CLIP_PLANES {
+        polygon = clip_polygon(&polygon, plane);
+        if polygon.is_empty() {
+            break;
+        }
+    }
+
+    polygon
+}
+
+fn clip_polygon(polygon: &[Vertex], plane: ClipPlane) -> Vec<Vertex> {
+    let mut output = Vec::with_capacity(polygon.len() + 1);
+
+    for i in 0..polygon.len() {
+        let current = &polygon[i];
+        let next = &polygon[(i + 1) % polygon.len()];
+        let d_current = plane.distance(&current.transformed_position);
+        let d_next = plane.distance(&next.transformed_position);
+
+        if d_current >= 0.0 {
+            output.push(*current);
+        }
+
+        // The edge crosses the plane: emit the intersection point. An end
+        // point lying on the plane is already the intersection
+        if (d_current > 0.0 && d_next < 0.0) || (d_current < 0.0 && d_next > 0.0) {
+            let t = d_current / (d_current - d_next);
+            output.push(current.lerp(next, t));
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{Vec2, Vec3};
+    use raylib::prelude::Color;
+
+    fn vertex(clip: (f32, f32, f32, f32), tex_coords: (f32, f32), color: Color) -> Vertex {
+        let mut vertex = Vertex::new(Vec3::new(clip.0, clip.1, clip.2), Vec3::z(), color);
+        vertex.transformed_position = Vec4::new(clip.0, clip.1, clip.2, clip.3);
+        vertex.tex_coords = Vec2::new(tex_coords.0, tex_coords.1);
+        vertex
+    }
+
+    fn positions(polygon: &[Vertex]) -> Vec<Vec4> {
+        polygon.iter().map(|vertex| vertex.transformed_position).collect()
+    }
+
+    #[test]
+    fn keeps_triangles_inside_the_frustum() {
+        let v1 = vertex((-0.5, -0.5, 0.0, 1.0), (0.0, 0.0), Color::WHITE);
+        let v2 = vertex((0.5, -0.5, 0.5, 1.0), (1.0, 0.0), Color::WHITE);
+        let v3 = vertex((0.0, 0.5, -0.5, 1.0), (0.0, 1.0), Color::WHITE);
+        assert_eq!(positions(&clip_triangle(&v1, &v2, &v3)), positions(&[v1, v2, v3]));
+    }
+
+    #[test]
+    fn turns_a_triangle_across_the_near_plane_into_a_quad() {
+        // v1 is 2 behind the near plane (z = -w), v2 and v3 are 1 in front,
+        // so both cut edges are cut at 2/3 of the way from v1
+        let v1 = vertex((0.0, 0.0, -3.0, 1.0), (0.0, 0.0), Color::new(0, 0, 0, 255));
+        let v2 = vertex((0.6, 0.0, 0.0, 1.0), (1.0, 0.0), Color::new(255, 0, 0, 255));
+        let v3 = vertex((0.0, 0.6, 0.0, 1.0), (0.0, 1.0), Color::new(0, 255, 0, 255));
+        let polygon = clip_triangle(&v1, &v2, &v3);
+        assert_eq!(polygon.len(), 4);
+
+        let t = 2.0 / 3.0;
+        let expected = [
+            (Vec4::new(0.6 * t, 0.0, -3.0 + 3.0 * t, 1.0), Vec2::new(t, 0.0), Color::new(170, 0, 0, 255)),
+            (v2.transformed_position, v2.tex_coords, v2.color),
+            (v3.transformed_position, v3.tex_coords, v3.color),
+            (Vec4::new(0.0, 0.6 * t, -3.0 + 3.0 * t, 1.0), Vec2::new(0.0, t), Color::new(0, 170, 0, 255)),
+        ];
+        for (vertex, (position, tex_coords, color)) in polygon.iter().zip(expected) {
+            assert!((vertex.transformed_position - position).norm() < 1e-6, "{:?}", vertex.transformed_position);
+            assert!((vertex.tex_coords - tex_coords).norm() < 1e-6);
+            assert!((vertex.position - position.xyz()).norm() < 1e-6);
+            assert_eq!(vertex.color, color);
+        }
+        // The new vertices lie on the near plane
+        assert!(ClipPlane::Near.distance(&polygon[0].transformed_position).abs() < 1e-6);
+        assert!(ClipPlane::Near.distance(&polygon[3].transformed_position).abs() < 1e-6);
+    }
+
+    #[test]
+    fn drops_triangles_behind_the_camera() {
+        // w < 0: behind the eye, even though x/w and y/w would land on screen
+        let v1 = vertex((0.0, 0.0, 0.5, -1.0), (0.0, 0.0), Color::WHITE);
+        let v2 = vertex((0.5, 0.0, 0.5, -1.0), (1.0, 0.0), Color::WHITE);
+        let v3 = vertex((0.0, 0.5, 0.5, -2.0), (0.0, 1.0), Color::WHITE);
+        assert!(clip_triangle(&v1, &v2, &v3).is_empty());
+
+        // Beyond the far plane and off to one side
+        let far = [(0.0, 0.0, 2.0, 1.0), (0.5, 0.0, 3.0, 1.0), (0.0, 0.5, 2.0, 1.0)].map(|p| vertex(p, (0.0, 0.0), Color::WHITE));
+        assert!(clip_triangle(&far[0], &far[1], &far[2]).is_empty());
+        let left = [(-2.0, 0.0, 0.0, 1.0), (-3.0, 0.5, 0.0, 1.0), (-2.0, -0.5, 0.0, 1.0)].map(|p| vertex(p, (0.0, 0.0), Color::WHITE));
+        assert!(clip_triangle(&left[0], &left[1], &left[2]).is_empty());
+    }
+
+    #[test]
+    fn does_not_duplicate_vertices_on_a_plane() {
+        // v1 lies exactly on the near plane
+        let v1 = vertex((0.0, 0.0, -1.0, 1.0), (0.0, 0.0), Color::WHITE);
+        let inside = vertex((0.5, 0.0, 0.0, 1.0), (1.0, 0.0), Color::WHITE);
+        assert_eq!(clip_triangle(&v1, &inside, &vertex((0.0, 0.5, 0.0, 1.0), (0.0, 1.0), Color::WHITE)).len(), 3);
+
+        // With the third vertex behind the plane, only its edge to `inside` is cut
+        let behind = vertex((0.0, 0.5, -2.0, 1.0), (0.0, 1.0), Color::WHITE);
+        let polygon = clip_triangle(&v1, &inside, &behind);
+        assert_eq!(polygon.len(), 3);
+        assert_eq!(polygon[0].transformed_position, v1.transformed_position);
+        assert_eq!(polygon[1].transformed_position, inside.transformed_position);
+        assert!((polygon[2].transformed_position - Vec4::new(0.25, 0.25, -1.0, 1.0)).norm() < 1e-6);
+
+        // A triangle touching the plane from behind along one vertex is gone
+        let touching = clip_triangle(&v1, &behind, &vertex((0.5, 0.0, -3.0, 1.0), (1.0, 0.0), Color::WHITE));
+        assert!(touching.len() < 3);
+    }
+}
@@ -0,0 +1,81 @@
+// export/bmp.rs
+// Uncompressed 24-bit BMP images, for tools that open BMP but not PPM. Rows
+// are stored bottom-up in BGR order, each padded to a multiple of 4 bytes.
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+use std::path::Path;
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40; // BITMAPINFOHEADER
+const PIXELS_PER_METER: u32 = 2835; // 72 DPI
+
+// Writes the resolved color buffer (0xRRGGBB pixels) of a framebuffer
+pub fn write_bmp(framebuffer: &Framebuffer, writer: &mut impl Write) -> io::Result<()> {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    let stride = (width * 3).div_ceil(4) * 4;
+    let image_size = stride * height;
+    let file_size = FILE_HEADER_SIZE + INFO_HEADER_SIZE + image_size;
+    if file_size > u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("a BMP image cannot be {}x{}", width, height)));
+    }
+
+    let mut header = Vec::with_capacity(FILE_HEADER_SIZE + INFO_HEADER_SIZE);
+    header.extend_from_slice(b"BM");
+    header.extend_from_slice(&(file_size as u32).to_le_bytes());
+    header.extend_from_slice(&[0; 4]); // Reserved
+    header.extend_from_slice(&((FILE_HEADER_SIZE + INFO_HEADER_SIZE) as u32).to_le_bytes());
+    header.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    header.extend_from_slice(&(width as i32).to_le_bytes());
+    header.extend_from_slice(&(height as i32).to_le_bytes()); // Positive: bottom-up rows
+    header.extend_from_slice(&1u16.to_le_bytes()); // Planes
+    header.extend_from_slice(&24u16.to_le_bytes()); // Bits per pixel
+    header.extend_from_slice(&0u32.to_le_bytes()); // No compression
+    header.extend_from_slice(&(image_size as u32).to_le_bytes());
+    header.extend_from_slice(&PIXELS_PER_METER.to_le_bytes());
+    header.extend_from_slice(&PIXELS_PER_METER.to_le_bytes());
+    header.extend_from_slice(&[0; 8]); // Palette size and important colors
+    writer.write_all(&header)?;
+
+    let mut row = Vec::with_capacity(stride);
+    for pixels in framebuffer.buffer.chunks_exact(width.max(1)).rev() {
+        row.clear();
+        for &pixel in pixels {
+            row.extend_from_slice(&[pixel as u8, (pixel >> 8) as u8, (pixel >> 16) as u8]);
+        }
+        row.resize(stride, 0);
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+pub fn save_bmp(framebuffer: &Framebuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_bmp(framebuffer, &mut writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_headers_and_padded_bottom_up_rows() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.buffer.copy_from_slice(&[0x102030, 0x405060, 0x708090, 0xA0B0C0, 0xD0E0F0, 0x010203]);
+        let mut bytes = Vec::new();
+        write_bmp(&framebuffer, &mut bytes).unwrap();
+
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        // Rows of 9 bytes padded to 12
+        assert_eq!(bytes.len(), 54 + 2 * 12);
+        assert_eq!(&bytes[..2], b"BM");
+        assert_eq!((u32_at(2), u32_at(6), u32_at(10)), (78, 0, 54));
+        assert_eq!((u32_at(14), u32_at(18), u32_at(22)), (40, 3, 2));
+        assert_eq!((u16_at(26), u16_at(28), u32_at(30), u32_at(34)), (1, 24, 0, 24));
+        assert_eq!((u32_at(38), u32_at(42), u32_at(46), u32_at(50)), (2835, 2835, 0, 0));
+
+        // The bottom row comes first, in BGR order
+        assert_eq!(&bytes[54..66], &[0xC0, 0xB0, 0xA0, 0xF0, 0xE0, 0xD0, 0x03, 0x02, 0x01, 0, 0, 0]);
+        assert_eq!(&bytes[66..78], &[0x30, 0x20, 0x10, 0x60, 0x50, 0x40, 0x90, 0x80, 0x70, 0, 0, 0]);
+    }
+}
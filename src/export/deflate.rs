@@ -0,0 +1,427 @@
+// export/deflate.rs
+// zlib streams (RFC 1950/1951) for PNG, without a compression crate. Repeats
+// are found with LZ77 over hash chains and coded with the fixed Huffman
+// tables; a block that would not get smaller is stored instead. Dynamic
+// Huffman tables would squeeze out a little more, rendered frames with flat
+// backgrounds already shrink a lot with this.
+const WINDOW_SIZE: usize = 32768; // Farthest a match may look back
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64; // Earlier positions tried per match, trades speed for size
+const HASH_BITS: u32 = 15;
+const BLOCK_SIZE: usize = 65535; // Input bytes per block, the most a stored block holds
+
+// Base value and extra bits of the length codes 257..=285 and of the distance codes 0..=29
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    // Table for the reflected polynomial 0xEDB88320, built once per call: it
+    // costs 256 steps, nothing next to the image data going through it
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    !bytes.iter().fold(!0u32, |crc, &byte| table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+pub fn adler32(bytes: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b may overflow
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+// Compresses `data` into a zlib stream: header, deflate blocks, Adler-32
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { bytes: vec![0x78, 0x9C], buffer: 0, count: 0 }; // 32K window, default level
+    let mut matcher = Matcher::new(data);
+
+    if data.is_empty() {
+        write_stored_block(&mut writer, &[], true);
+    }
+    let mut start = 0;
+    while start < data.len() {
+        let end = (start + BLOCK_SIZE).min(data.len());
+        let last = end == data.len();
+        let tokens = matcher.tokens(start, end);
+        let compressed_bits = 3 + tokens.iter().map(Token::bits).sum::<usize>() + 7; // Including the end of block code
+        let stored_bits = 3 + 7 + 32 + (end - start) * 8; // Header, worst alignment, LEN and NLEN, data
+        if compressed_bits < stored_bits {
+            write_fixed_block(&mut writer, &tokens, last);
+        } else {
+            write_stored_block(&mut writer, &data[start..end], last);
+        }
+        start = end;
+    }
+
+    writer.align();
+    writer.bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    writer.bytes
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+impl Token {
+    // Bits the token takes with the fixed codes
+    fn bits(&self) -> usize {
+        match *self {
+            Token::Literal(byte) => fixed_literal_code(byte as u16).1 as usize,
+            Token::Match { length, distance } => {
+                let (code, _, extra) = length_code(length);
+                fixed_literal_code(code).1 as usize + extra as usize + 5 + distance_code(distance).2 as usize
+            }
+        }
+    }
+}
+
+// Finds earlier occurrences of the bytes at a position through chains of
+// positions with the same hash of their next three bytes
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<usize>, // Latest position + 1 per hash, 0 for none
+    previous: Vec<usize>, // Position + 1 before each position (modulo the window) with the same hash
+    inserted: usize, // Positions below this are in the chains
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Matcher { data, head: vec![0; 1 << HASH_BITS], previous: vec![0; WINDOW_SIZE], inserted: 0 }
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let bytes = &self.data[position..position + MIN_MATCH];
+        let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        (value.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert_up_to(&mut self, position: usize) {
+        while self.inserted < position {
+            let at = self.inserted;
+            if at + MIN_MATCH <= self.data.len() {
+                let hash = self.hash(at);
+                self.previous[at % WINDOW_SIZE] = self.head[hash];
+                self.head[hash] = at + 1;
+            }
+            self.inserted += 1;
+        }
+    }
+
+    // Longest earlier match for the bytes at `position`, not going past `end`
+    fn longest_match(&self, position: usize, end: usize) -> Option<(usize, usize)> {
+        let max_length = (end - position).min(MAX_MATCH);
+        if max_length < MIN_MATCH {
+            return None;
+        }
+        let target = &self.data[position..position + max_length];
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(position)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == 0 || position - (candidate - 1) > WINDOW_SIZE {
+                break;
+            }
+            let start = candidate - 1;
+            let length = target.iter().zip(&self.data[start..]).take_while(|(a, b)| a == b).count();
+            if length >= MIN_MATCH && best.is_none_or(|(best_length, _)| length > best_length) {
+                best = Some((length, position - start));
+                if length == max_length {
+                    break;
+                }
+            }
+            let next = self.previous[start % WINDOW_SIZE];
+            if next >= candidate {
+                break; // The slot was reused by a newer position, the chain ends here
+            }
+            candidate = next;
+        }
+        best
+    }
+
+    // Greedy parse of data[start..end] into literals and matches
+    fn tokens(&mut self, start: usize, end: usize) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut position = start;
+        while position < end {
+            self.insert_up_to(position);
+            match self.longest_match(position, end) {
+                Some((length, distance)) => {
+                    tokens.push(Token::Match { length: length as u16, distance: distance as u16 });
+                    position += length;
+                }
+                None => {
+                    tokens.push(Token::Literal(self.data[position]));
+                    position += 1;
+                }
+            }
+        }
+        self.insert_up_to(end);
+        tokens
+    }
+}
+
+// Deflate packs bits starting from the least significant one of each byte
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn write_code(&mut self, code: u32, bits: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write_bits(0, 8 - self.count);
+        }
+    }
+}
+
+fn write_stored_block(writer: &mut BitWriter, data: &[u8], last: bool) {
+    writer.write_bits(last as u32, 1);
+    writer.write_bits(0, 2);
+    writer.align();
+    let length = data.len() as u16;
+    writer.bytes.extend_from_slice(&length.to_le_bytes());
+    writer.bytes.extend_from_slice(&(!length).to_le_bytes());
+    writer.bytes.extend_from_slice(data);
+}
+
+fn write_fixed_block(writer: &mut BitWriter, tokens: &[Token], last: bool) {
+    writer.write_bits(last as u32, 1);
+    writer.write_bits(1, 2);
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => {
+                let (code, bits) = fixed_literal_code(byte as u16);
+                writer.write_code(code, bits);
+            }
+            Token::Match { length, distance } => {
+                let (symbol, extra_value, extra_bits) = length_code(length);
+                let (code, bits) = fixed_literal_code(symbol);
+                writer.write_code(code, bits);
+                writer.write_bits(extra_value, extra_bits);
+                let (symbol, extra_value, extra_bits) = distance_code(distance);
+                writer.write_code(symbol as u32, 5);
+                writer.write_bits(extra_value, extra_bits);
+            }
+        }
+    }
+    let (code, bits) = fixed_literal_code(256);
+    writer.write_code(code, bits);
+}
+
+// (code, bit count) of a literal/length symbol in the fixed Huffman table
+fn fixed_literal_code(symbol: u16) -> (u32, u32) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    }
+}
+
+// (symbol, extra value, extra bits) of a match length
+fn length_code(length: u16) -> (u16, u32, u32) {
+    let index = LENGTH_BASE.partition_point(|&base| base <= length) - 1;
+    (257 + index as u16, (length - LENGTH_BASE[index]) as u32, LENGTH_EXTRA[index] as u32)
+}
+
+// (symbol, extra value, extra bits) of a match distance
+fn distance_code(distance: u16) -> (u16, u32, u32) {
+    let index = DISTANCE_BASE.partition_point(|&base| base <= distance) - 1;
+    (index as u16, (distance - DISTANCE_BASE[index]) as u32, DISTANCE_EXTRA[index] as u32)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Bytes that do not compress, from a xorshift generator
+    fn noise(length: usize, mut state: u32) -> Vec<u8> {
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        position: usize, // In bits
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, i| {
+                let bit = (self.bytes[self.position / 8] >> (self.position % 8)) & 1;
+                self.position += 1;
+                value | (bit as u32) << i
+            })
+        }
+
+        // Huffman codes go most significant bit first
+        fn code(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        fn align(&mut self) {
+            self.position = self.position.div_ceil(8) * 8;
+        }
+    }
+
+    // Decodes a zlib stream with stored and fixed Huffman blocks, returning
+    // the data and the type of every block
+    pub(crate) fn inflate(stream: &[u8]) -> (Vec<u8>, Vec<u32>) {
+        assert_eq!(stream[..2], [0x78, 0x9C]);
+        let mut reader = BitReader { bytes: &stream[..stream.len() - 4], position: 16 };
+        let (mut data, mut block_types) = (Vec::new(), Vec::new());
+
+        loop {
+            let last = reader.bits(1) == 1;
+            let block_type = reader.bits(2);
+            block_types.push(block_type);
+            match block_type {
+                0 => {
+                    reader.align();
+                    let (length, complement) = (reader.bits(16), reader.bits(16));
+                    assert_eq!(length, !complement & 0xFFFF);
+                    data.extend((0..length).map(|_| reader.bits(8) as u8));
+                }
+                1 => loop {
+                    let mut code = reader.code(7);
+                    let symbol = if code <= 23 {
+                        256 + code
+                    } else {
+                        code = code << 1 | reader.bits(1);
+                        match code {
+                            0x30..=0xBF => code - 0x30,
+                            0xC0..=0xC7 => 280 + code - 0xC0,
+                            _ => 144 + (code << 1 | reader.bits(1)) - 0x190,
+                        }
+                    };
+                    match symbol {
+                        0..=255 => data.push(symbol as u8),
+                        256 => break,
+                        _ => {
+                            let index = (symbol - 257) as usize;
+                            let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32) as usize;
+                            let index = reader.code(5) as usize;
+                            let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32) as usize;
+                            assert!(distance <= WINDOW_SIZE && distance <= data.len());
+                            for _ in 0..length {
+                                data.push(data[data.len() - distance]);
+                            }
+                        }
+                    }
+                },
+                _ => panic!("unexpected block type {}", block_type),
+            }
+            if last {
+                break;
+            }
+        }
+
+        reader.align();
+        assert_eq!(reader.position / 8, stream.len() - 4, "data after the last block");
+        assert_eq!(stream[stream.len() - 4..], adler32(&data).to_be_bytes());
+        (data, block_types)
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u32> {
+        let (decoded, block_types) = inflate(&zlib_compress(data));
+        assert!(decoded == data, "decoded data differs");
+        block_types
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(adler32(b""), 1);
+
+        // Long enough for the sums to be reduced several times
+        let data = vec![0xFF; 100_000];
+        let (a, b) = data.iter().fold((1u64, 0u64), |(a, b), &byte| {
+            let a = (a + byte as u64) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(adler32(&data), (b << 16 | a) as u32);
+    }
+
+    #[test]
+    fn compresses_empty_input() {
+        assert_eq!(zlib_compress(&[]), [0x78, 0x9C, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0, 0, 0, 1]);
+        assert_eq!(round_trip(&[]), [0]);
+    }
+
+    #[test]
+    fn splits_blocks_at_block_size() {
+        assert_eq!(round_trip(&noise(BLOCK_SIZE, 1)), [0]);
+        assert_eq!(round_trip(&noise(BLOCK_SIZE + 1, 2)), [0, 1]); // A single literal is cheaper than a stored block
+        assert_eq!(round_trip(&noise(2 * BLOCK_SIZE, 3)), [0, 0]);
+        assert_eq!(round_trip(&vec![7; BLOCK_SIZE]), [1]);
+        assert_eq!(round_trip(&vec![7; 2 * BLOCK_SIZE + 1]), [1, 1, 1]);
+    }
+
+    #[test]
+    fn matches_stay_inside_the_window() {
+        // Repeats just inside the window are found...
+        let pattern = noise(WINDOW_SIZE, 4);
+        let data = [pattern.as_slice(), &pattern].concat();
+        round_trip(&data);
+        assert!(zlib_compress(&data).len() < pattern.len() + pattern.len() / 8);
+
+        // ...and ones farther back are not referenced
+        let data = [noise(40_000, 5), noise(40_000, 5), b"tail".to_vec()].concat();
+        round_trip(&data);
+    }
+
+    #[test]
+    fn round_trips_mixed_data() {
+        let mut data = Vec::new();
+        for i in 0..50u32 {
+            data.extend(noise(i as usize * 37, i + 10));
+            data.extend(std::iter::repeat_n(i as u8, i as usize * 11));
+            data.extend_from_slice(b"abcabcabcabd");
+        }
+        round_trip(&data);
+    }
+}
@@ -0,0 +1,95 @@
+// export/depth.rs
+// Dumps of the depth buffer: a 16-bit grayscale PNG to look at, and a PFM
+// with the raw floats to compare or post-process.
+use super::png::write_png_gray16;
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Depths stretched over the range of the drawn pixels, nearest white and
+// farthest almost black. Pixels nothing was drawn to are black
+pub fn write_depth_png(framebuffer: &Framebuffer, writer: &mut impl Write) -> io::Result<()> {
+    let (near, far) = framebuffer
+        .zbuffer
+        .iter()
+        .filter(|depth| depth.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(near, far), &depth| (near.min(depth), far.max(depth)));
+    let range = if far > near { far - near } else { 1.0 };
+    let samples: Vec<u16> = framebuffer
+        .zbuffer
+        .iter()
+        .map(|&depth| if depth.is_finite() { u16::MAX - ((depth - near) / range * (u16::MAX - 1) as f32).round() as u16 } else { 0 })
+        .collect();
+    write_png_gray16(framebuffer.width, framebuffer.height, &samples, writer)
+}
+
+pub fn save_depth_png(framebuffer: &Framebuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_depth_png(framebuffer, &mut writer)
+}
+
+// Grayscale PFM: a text header, then little-endian floats row by row from
+// the bottom, as the format wants. Empty pixels keep their infinite depth
+pub fn write_pfm(framebuffer: &Framebuffer, writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "Pf\n{} {}\n-1.0\n", framebuffer.width, framebuffer.height)?; // Negative scale: little-endian
+    let mut row = Vec::with_capacity(framebuffer.width * 4);
+    for depths in framebuffer.zbuffer.chunks_exact(framebuffer.width.max(1)).rev() {
+        row.clear();
+        for depth in depths {
+            row.extend_from_slice(&depth.to_le_bytes());
+        }
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+pub fn save_pfm(framebuffer: &Framebuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_pfm(framebuffer, &mut writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::png::tests::decode;
+
+    // 3x2 depths, the bottom row has a pixel nothing was drawn to
+    fn framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.zbuffer.copy_from_slice(&[0.25, 0.5, 0.75, 1.0, f32::INFINITY, 0.5]);
+        framebuffer
+    }
+
+    #[test]
+    fn writes_pfm_rows_from_the_bottom() {
+        let mut bytes = Vec::new();
+        write_pfm(&framebuffer(), &mut bytes).unwrap();
+        let header = b"Pf\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let floats: Vec<f32> = bytes[header.len()..].chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect();
+        assert_eq!(floats, [1.0, f32::INFINITY, 0.5, 0.25, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn stretches_depth_png_over_the_drawn_range() {
+        let mut bytes = Vec::new();
+        write_depth_png(&framebuffer(), &mut bytes).unwrap();
+        let decoded = decode(&bytes);
+        assert_eq!((decoded.width, decoded.height, decoded.bit_depth), (3, 2, 16));
+        let samples: Vec<u16> = decoded.pixels.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        // Nearest white, farthest 1 so it stays apart from the empty pixel
+        let third = |steps: u16| u16::MAX - ((u16::MAX - 1) as f32 * steps as f32 / 3.0).round() as u16;
+        assert_eq!(samples, [u16::MAX, third(1), third(2), 1, 0, third(1)]);
+
+        // A single depth (or none at all) does not divide by zero
+        let mut flat = Framebuffer::new(2, 1);
+        flat.zbuffer[0] = 0.5;
+        let mut bytes = Vec::new();
+        write_depth_png(&flat, &mut bytes).unwrap();
+        assert_eq!(decode(&bytes).pixels, [0xFF, 0xFF, 0, 0]);
+        bytes.clear();
+        write_depth_png(&Framebuffer::new(2, 1), &mut bytes).unwrap();
+        assert_eq!(decode(&bytes).pixels, [0, 0, 0, 0]);
+    }
+}
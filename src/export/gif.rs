@@ -0,0 +1,257 @@
+// export/gif.rs
+// Animated GIF (GIF89a) encoder. Every frame gets its own palette from
+// export/quantize.rs and is compressed with LZW. A frame is held back until
+// the next one arrives, as its delay is only known then; frames that would
+// stay on screen less than MIN_DELAY are dropped, because viewers stretch
+// such short delays to a tenth of a second.
+use super::quantize::{map_to_palette, median_cut};
+use super::sink::FrameSink;
+use crate::framebuffer::Framebuffer;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+const MAX_COLORS: usize = 256;
+const MIN_DELAY: u32 = 2; // Centiseconds
+const MAX_CODE_BITS: u32 = 12;
+const SUB_BLOCK_SIZE: usize = 255;
+
+pub struct GifEncoder<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    dither: bool,
+    pending: Option<(u32, Vec<u8>)>, // Start in centiseconds and encoded image of the frame not written yet
+}
+
+impl<W: Write> GifEncoder<W> {
+    // Writes the header of an animation that loops forever
+    pub fn new(mut writer: W, width: usize, height: usize, dither: bool) -> io::Result<Self> {
+        if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("a GIF image cannot be {}x{}", width, height)));
+        }
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&(width as u16).to_le_bytes())?;
+        writer.write_all(&(height as u16).to_le_bytes())?;
+        writer.write_all(&[0, 0, 0])?; // No global color table, background color, square pixels
+        writer.write_all(&[0x21, 0xFF, 11])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[3, 1, 0, 0, 0])?; // Loop count 0: forever
+        Ok(GifEncoder { writer, width, height, dither, pending: None })
+    }
+
+    fn write_pending(&mut self, end: u32) -> io::Result<()> {
+        let Some((start, image)) = self.pending.take() else {
+            return Ok(());
+        };
+        let delay = (end - start).min(u16::MAX as u32) as u16;
+        // Graphic control extension: keep the frame under the next one, no transparency
+        self.writer.write_all(&[0x21, 0xF9, 4, 0x04])?;
+        self.writer.write_all(&delay.to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+        self.writer.write_all(&image)
+    }
+
+    // Image descriptor, local color table and LZW data of a frame
+    fn encode_image(&self, pixels: &[u32]) -> Vec<u8> {
+        let palette = median_cut(pixels, MAX_COLORS);
+        let indices = map_to_palette(pixels, self.width, &palette, self.dither);
+        let table_bits = (palette.len().next_power_of_two().trailing_zeros()).max(1);
+
+        let mut image = vec![0x2C, 0, 0, 0, 0];
+        image.extend_from_slice(&(self.width as u16).to_le_bytes());
+        image.extend_from_slice(&(self.height as u16).to_le_bytes());
+        image.push(0x80 | (table_bits - 1) as u8); // Local color table of 2^table_bits colors
+        for i in 0..1 << table_bits {
+            image.extend_from_slice(&palette.get(i).copied().unwrap_or([0; 3]));
+        }
+
+        let min_code_size = table_bits.max(2); // The smallest LZW allows
+        image.push(min_code_size as u8);
+        for block in lzw_compress(&indices, min_code_size).chunks(SUB_BLOCK_SIZE) {
+            image.push(block.len() as u8);
+            image.extend_from_slice(block);
+        }
+        image.push(0);
+        image
+    }
+}
+
+impl<W: Write> FrameSink for GifEncoder<W> {
+    fn write_frame(&mut self, framebuffer: &Framebuffer, time: f32) -> io::Result<()> {
+        if framebuffer.width != self.width || framebuffer.height != self.height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the frame size changed during the animation"));
+        }
+        let start = centiseconds(time);
+        if let Some((pending_start, _)) = &self.pending {
+            if start < pending_start + MIN_DELAY {
+                return Ok(()); // Too soon after the previous frame
+            }
+            self.write_pending(start)?;
+        }
+        self.pending = Some((start, self.encode_image(&framebuffer.buffer)));
+        Ok(())
+    }
+
+    // Writes the last frame, shown until `end_time`, and the trailer
+    fn finish(&mut self, end_time: f32) -> io::Result<()> {
+        if let Some((start, _)) = &self.pending {
+            let end = centiseconds(end_time).max(start + MIN_DELAY);
+            self.write_pending(end)?;
+        }
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()
+    }
+}
+
+fn centiseconds(time: f32) -> u32 {
+    (time.max(0.0) * 100.0).round() as u32
+}
+
+// Variable width LZW as GIF uses it: codes start one bit wider than the
+// pixel indices and grow up to 12 bits, then the table is cleared
+fn lzw_compress(indices: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut writer = CodeWriter { bytes: Vec::new(), buffer: 0, count: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut code_bits = min_code_size + 1;
+
+    writer.write(clear, code_bits);
+    let Some((&first, rest)) = indices.split_first() else {
+        writer.write(end, code_bits);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        writer.write(prefix, code_bits);
+        if next_code < 1 << MAX_CODE_BITS {
+            table.insert((prefix, index), next_code);
+            // The decoder adds each entry one code later than here, so the
+            // first code that may need the extra bit is the one after this
+            if next_code == 1 << code_bits && code_bits < MAX_CODE_BITS {
+                code_bits += 1;
+            }
+            next_code += 1;
+        } else {
+            writer.write(clear, code_bits);
+            table.clear();
+            next_code = end + 1;
+            code_bits = min_code_size + 1;
+        }
+        prefix = index as u16;
+    }
+    writer.write(prefix, code_bits);
+    writer.write(end, code_bits);
+    writer.finish()
+}
+
+// Packs codes from the least significant bit of each byte
+struct CodeWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl CodeWriter {
+    fn write(&mut self, code: u16, bits: u32) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Standard GIF LZW decoder, returning the indices and how many clear
+    // codes the stream had
+    fn lzw_decompress(bytes: &[u8], min_code_size: u32) -> (Vec<u8>, usize) {
+        let (clear, end) = (1usize << min_code_size, (1usize << min_code_size) + 1);
+        let initial_table = || (0..=end).map(|code| vec![code as u8]).collect::<Vec<_>>();
+        let (mut table, mut code_bits) = (initial_table(), min_code_size + 1);
+        let (mut indices, mut clears, mut previous): (Vec<u8>, usize, Option<Vec<u8>>) = (Vec::new(), 0, None);
+        let mut position = 0; // In bits
+
+        loop {
+            let code = (0..code_bits).fold(0usize, |code, i| {
+                let bit = (bytes[(position + i as usize) / 8] >> ((position + i as usize) % 8)) & 1;
+                code | (bit as usize) << i
+            });
+            position += code_bits as usize;
+
+            if code == clear {
+                (table, code_bits, previous) = (initial_table(), min_code_size + 1, None);
+                clears += 1;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let entry = match (&previous, code.cmp(&table.len())) {
+                (_, std::cmp::Ordering::Less) => table[code].clone(),
+                (Some(previous), std::cmp::Ordering::Equal) => [previous.as_slice(), &previous[..1]].concat(),
+                _ => panic!("code {} is not in the table of {} entries", code, table.len()),
+            };
+            indices.extend_from_slice(&entry);
+            if let Some(previous) = previous
+                && table.len() < 1 << MAX_CODE_BITS
+            {
+                table.push([previous.as_slice(), &entry[..1]].concat());
+                if table.len() == 1 << code_bits && code_bits < MAX_CODE_BITS {
+                    code_bits += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+
+        assert_eq!(position.div_ceil(8), bytes.len(), "data after the end code");
+        (indices, clears)
+    }
+
+    // Indices below 1 << bits that repeat little, from a xorshift generator
+    fn noise(length: usize, bits: u32) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> (32 - bits)) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lzw_round_trips_through_table_resets() {
+        for (indices, min_code_size) in [(noise(20_000, 8), 8), (noise(60_000, 2), 2), (noise(50_000, 4), 4)] {
+            let (decoded, clears) = lzw_decompress(&lzw_compress(&indices, min_code_size), min_code_size);
+            assert!(decoded == indices, "decoded indices differ with {}-bit codes", min_code_size);
+            assert!(clears > 1, "the table never filled up with {}-bit codes", min_code_size);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips_short_and_repetitive_input() {
+        for indices in [Vec::new(), vec![3], vec![1, 1], vec![0; 100_000], [0, 1, 2, 3].repeat(5000)] {
+            let (decoded, _) = lzw_decompress(&lzw_compress(&indices, 2), 2);
+            assert_eq!(decoded, indices);
+        }
+    }
+}
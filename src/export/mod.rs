@@ -0,0 +1,52 @@
+// export/mod.rs
+// Image and video files for rendered frames, for headless rendering and
+// screenshots.
+pub mod bmp;
+pub mod deflate;
+pub mod depth;
+pub mod gif;
+pub mod png;
+pub mod ppm;
+pub mod quantize;
+pub mod sink;
+pub mod y4m;
+
+use crate::framebuffer::Framebuffer;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Bmp,
+    Png,
+}
+
+impl ImageFormat {
+    // Format named by a file extension, e.g. "png"
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "bmp" => Some(ImageFormat::Bmp),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+// Saves the color buffer of a framebuffer in the given format
+pub fn save_image(framebuffer: &Framebuffer, format: ImageFormat, path: impl AsRef<Path>) -> io::Result<()> {
+    match format {
+        ImageFormat::Ppm => ppm::save_ppm(framebuffer, path),
+        ImageFormat::Bmp => bmp::save_bmp(framebuffer, path),
+        ImageFormat::Png => png::save_png(framebuffer, path),
+    }
+}
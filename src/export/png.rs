@@ -0,0 +1,264 @@
+// export/png.rs
+// PNG images: RGB for the color buffer and 16-bit grayscale for depth. Each
+// row is filtered with whichever PNG filter leaves the smallest differences,
+// then the whole image is compressed by export/deflate.rs.
+use super::deflate::{crc32, zlib_compress};
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const IDAT_SIZE: usize = 1 << 16; // Compressed bytes per IDAT chunk
+
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+
+// Writes the resolved color buffer (0xRRGGBB pixels) of a framebuffer
+pub fn write_png(framebuffer: &Framebuffer, writer: &mut impl Write) -> io::Result<()> {
+    let mut pixels = Vec::with_capacity(framebuffer.buffer.len() * 3);
+    for &pixel in &framebuffer.buffer {
+        pixels.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+    }
+    write_image(writer, framebuffer.width, framebuffer.height, COLOR_TYPE_RGB, 8, 3, &pixels)
+}
+
+pub fn save_png(framebuffer: &Framebuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_png(framebuffer, &mut writer)
+}
+
+// Writes a 16-bit grayscale image, `samples` row by row from the top
+pub fn write_png_gray16(width: usize, height: usize, samples: &[u16], writer: &mut impl Write) -> io::Result<()> {
+    let pixels: Vec<u8> = samples.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+    write_image(writer, width, height, COLOR_TYPE_GRAY, 16, 2, &pixels)
+}
+
+// Signature, header, compressed rows and end chunk. `pixels` are the
+// unfiltered rows, `bytes_per_pixel` wide per pixel
+fn write_image(
+    writer: &mut impl Write,
+    width: usize,
+    height: usize,
+    color_type: u8,
+    bit_depth: u8,
+    bytes_per_pixel: usize,
+    pixels: &[u8],
+) -> io::Result<()> {
+    if width == 0 || height == 0 || width > i32::MAX as usize || height > i32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("a PNG image cannot be {}x{}", width, height)));
+    }
+    writer.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]); // Deflate, adaptive filtering, not interlaced
+    write_chunk(writer, b"IHDR", &header)?;
+
+    let compressed = zlib_compress(&filter_rows(pixels, width * bytes_per_pixel, bytes_per_pixel));
+    for data in compressed.chunks(IDAT_SIZE) {
+        write_chunk(writer, b"IDAT", data)?;
+    }
+    write_chunk(writer, b"IEND", &[])?;
+    writer.flush()
+}
+
+// Length, type, data and the CRC of type and data
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut checked = Vec::with_capacity(data.len() + 4);
+    checked.extend_from_slice(kind);
+    checked.extend_from_slice(data);
+    writer.write_all(&checked)?;
+    writer.write_all(&crc32(&checked).to_be_bytes())
+}
+
+// Prefixes every row with the filter type that suits it best, picked by the
+// usual heuristic: the smallest sum of the filtered bytes taken as signed
+fn filter_rows(pixels: &[u8], stride: usize, bytes_per_pixel: usize) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity(pixels.len() + pixels.len() / stride);
+    let zero_row = vec![0; stride];
+    let mut candidates: [Vec<u8>; 5] = Default::default();
+    for (y, row) in pixels.chunks_exact(stride).enumerate() {
+        let above = if y == 0 { &zero_row[..] } else { &pixels[(y - 1) * stride..y * stride] };
+        for (filter, candidate) in candidates.iter_mut().enumerate() {
+            candidate.clear();
+            candidate.extend((0..stride).map(|i| {
+                let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+                let upper_left = if i >= bytes_per_pixel { above[i - bytes_per_pixel] } else { 0 };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => above[i],
+                    3 => ((left as u16 + above[i] as u16) / 2) as u8,
+                    _ => paeth(left, above[i], upper_left),
+                };
+                row[i].wrapping_sub(predicted)
+            }));
+        }
+        let cost = |candidate: &Vec<u8>| candidate.iter().map(|&byte| (byte as i8).unsigned_abs() as u32).sum::<u32>();
+        let (filter, best) = candidates.iter().enumerate().min_by_key(|(_, candidate)| cost(candidate)).unwrap();
+        filtered.push(filter as u8);
+        filtered.extend_from_slice(best);
+    }
+    filtered
+}
+
+// Of left, above and upper left, the one closest to left + above - upper left
+fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let (to_left, to_above, to_upper_left) =
+        ((estimate - left as i16).abs(), (estimate - above as i16).abs(), (estimate - upper_left as i16).abs());
+    if to_left <= to_above && to_left <= to_upper_left {
+        left
+    } else if to_above <= to_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::export::deflate::tests::inflate;
+
+    // A decoded image: IHDR fields, the filter type of every row and the unfiltered rows
+    pub(crate) struct Decoded {
+        pub width: usize,
+        pub height: usize,
+        pub bit_depth: u8,
+        pub color_type: u8,
+        pub filters: Vec<u8>,
+        pub pixels: Vec<u8>,
+    }
+
+    // Checks the chunk CRCs, inflates the IDAT data and undoes the row filters
+    pub(crate) fn decode(bytes: &[u8]) -> Decoded {
+        assert_eq!(bytes[..8], SIGNATURE);
+        let (mut header, mut data, mut position) = (Vec::new(), Vec::new(), 8);
+        let mut kinds = Vec::new();
+        while position < bytes.len() {
+            let length = u32::from_be_bytes(bytes[position..position + 4].try_into().unwrap()) as usize;
+            let checked = &bytes[position + 4..position + 8 + length];
+            let crc = u32::from_be_bytes(bytes[position + 8 + length..position + 12 + length].try_into().unwrap());
+            assert_eq!(crc32(checked), crc);
+            match &checked[..4] {
+                b"IHDR" => header = checked[4..].to_vec(),
+                b"IDAT" => data.extend_from_slice(&checked[4..]),
+                _ => {}
+            }
+            kinds.push(checked[..4].to_vec());
+            position += 12 + length;
+        }
+        assert_eq!(kinds.first().map(Vec::as_slice), Some(&b"IHDR"[..]));
+        assert_eq!(kinds.last().map(Vec::as_slice), Some(&b"IEND"[..]));
+
+        let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        let (bit_depth, color_type) = (header[8], header[9]);
+        assert_eq!(header[10..], [0, 0, 0]);
+        let bytes_per_pixel = match color_type {
+            COLOR_TYPE_RGB => 3,
+            _ => 1,
+        } * bit_depth as usize / 8;
+
+        let (filtered, _) = inflate(&data);
+        let stride = width * bytes_per_pixel;
+        assert_eq!(filtered.len(), height * (stride + 1));
+        let (mut filters, mut pixels) = (Vec::new(), Vec::<u8>::with_capacity(height * stride));
+        for (y, row) in filtered.chunks_exact(stride + 1).enumerate() {
+            filters.push(row[0]);
+            for (i, &byte) in row[1..].iter().enumerate() {
+                let left = if i >= bytes_per_pixel { pixels[y * stride + i - bytes_per_pixel] } else { 0 };
+                let above = if y > 0 { pixels[(y - 1) * stride + i] } else { 0 };
+                let upper_left = if y > 0 && i >= bytes_per_pixel { pixels[(y - 1) * stride + i - bytes_per_pixel] } else { 0 };
+                let predicted = match row[0] {
+                    0 => 0,
+                    1 => left,
+                    2 => above,
+                    3 => ((left as u16 + above as u16) / 2) as u8,
+                    4 => paeth(left, above, upper_left),
+                    filter => panic!("unknown filter {}", filter),
+                };
+                pixels.push(byte.wrapping_add(predicted));
+            }
+        }
+        Decoded { width, height, bit_depth, color_type, filters, pixels }
+    }
+
+    fn framebuffer(width: usize, height: usize, mut pixel: impl FnMut(usize, usize) -> u32) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                framebuffer.buffer[y * width + x] = pixel(x, y);
+            }
+        }
+        framebuffer
+    }
+
+    // Encodes and decodes a framebuffer, returning the filter of every row
+    fn round_trip(framebuffer: &Framebuffer) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_png(framebuffer, &mut bytes).unwrap();
+        let decoded = decode(&bytes);
+        assert_eq!((decoded.width, decoded.height), (framebuffer.width, framebuffer.height));
+        assert_eq!((decoded.bit_depth, decoded.color_type), (8, COLOR_TYPE_RGB));
+        let expected: Vec<u8> = framebuffer.buffer.iter().flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]).collect();
+        assert!(decoded.pixels == expected, "decoded pixels differ");
+        decoded.filters
+    }
+
+    fn gray(value: u8) -> u32 {
+        (value as u32) * 0x01_01_01
+    }
+
+    #[test]
+    fn round_trips_every_filter_type() {
+        // A diagonal gradient is predicted exactly by Paeth below the first row
+        let gradient = framebuffer(40, 6, |x, y| ((x * 5 + y * 3) as u32) << 16 | ((x * 2 + y * 7) as u32) << 8 | (x + y) as u32);
+        assert_eq!(round_trip(&gradient), [1, 4, 4, 4, 4, 4]);
+
+        // Black rows need no filter, repeated rows are the row above, and a
+        // row that ramps up along x is its left neighbor plus a constant
+        let rows = framebuffer(16, 4, |x, y| if y == 0 { 0 } else { gray(x as u8 * 16 + 3) });
+        assert_eq!(round_trip(&rows), [0, 1, 2, 2]);
+
+        // Every pixel the mean of its left and upper neighbors
+        let mut values = [[0u8; 12]; 3];
+        for y in 0..3 {
+            for x in 0..12 {
+                values[y][x] = match (x, y) {
+                    (_, 0) => 200 - x as u8 * 13,
+                    (0, _) => 90,
+                    _ => ((values[y][x - 1] as u16 + values[y - 1][x] as u16) / 2) as u8,
+                };
+            }
+        }
+        let averages = framebuffer(12, 3, |x, y| gray(values[y][x]));
+        assert_eq!(round_trip(&averages)[1..], [3, 3]);
+
+        // Odd sizes, and data spread over several IDAT chunks
+        let mut state = 1u32;
+        round_trip(&framebuffer(257, 129, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state & 0xFF_FF_FF
+        }));
+        round_trip(&framebuffer(1, 1, |_, _| 0x12_34_56));
+    }
+
+    #[test]
+    fn writes_16_bit_grayscale() {
+        let samples = [0, 1, 0x1234, 0xFFFF, 0x8000, 0x00FF];
+        let mut bytes = Vec::new();
+        write_png_gray16(3, 2, &samples, &mut bytes).unwrap();
+        let decoded = decode(&bytes);
+        assert_eq!((decoded.width, decoded.height, decoded.bit_depth, decoded.color_type), (3, 2, 16, COLOR_TYPE_GRAY));
+        let expected: Vec<u8> = samples.iter().flat_map(|sample: &u16| sample.to_be_bytes()).collect();
+        assert_eq!(decoded.pixels, expected);
+
+        assert!(write_png(&Framebuffer::new(0, 4), &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+// export/ppm.rs
+// Binary PPM (P6) images: a short text header and the raw RGB bytes. No
+// compression, but nothing to get wrong and readable by most image tools.
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Writes the resolved color buffer (0xRRGGBB pixels) of a framebuffer
+pub fn write_ppm(framebuffer: &Framebuffer, writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", framebuffer.width, framebuffer.height)?;
+    let mut row = Vec::with_capacity(framebuffer.width * 3);
+    for pixels in framebuffer.buffer.chunks_exact(framebuffer.width.max(1)) {
+        row.clear();
+        for &pixel in pixels {
+            row.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+        }
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+pub fn save_ppm(framebuffer: &Framebuffer, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_ppm(framebuffer, &mut writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_rows_from_the_top_in_rgb_order() {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.buffer.copy_from_slice(&[0x102030, 0x405060, 0x708090, 0xA0B0C0]);
+        let mut bytes = Vec::new();
+        write_ppm(&framebuffer, &mut bytes).unwrap();
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], &[0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80, 0x90, 0xA0, 0xB0, 0xC0]);
+    }
+}
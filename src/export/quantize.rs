@@ -0,0 +1,185 @@
+// export/quantize.rs
+// Color reduction for palette images (GIF): median cut picks the palette,
+// then pixels are mapped to their nearest palette color, optionally with
+// Floyd-Steinberg dithering. Colors are looked at with 5 bits per channel,
+// which keeps the histogram and the nearest color lookup at 32768 entries.
+const CHANNEL_BITS: u32 = 5;
+const LEVELS: usize = 1 << CHANNEL_BITS;
+const UNKNOWN: u16 = u16::MAX; // Lookup entry whose nearest color was not searched yet
+
+// Colors of one 5-bit cell of the histogram, summed at full precision
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+    cell: [u8; 3],
+    count: u64,
+    sum: [u64; 3],
+}
+
+// Palette of at most `max_colors` colors for 0xRRGGBB pixels. The color
+// space is split into boxes, always cutting the box with the most pixels
+// times extent at the median of its longest side; each box then gives the
+// average of its pixels
+pub fn median_cut(pixels: &[u32], max_colors: usize) -> Vec<[u8; 3]> {
+    let mut histogram = vec![Bin { cell: [0; 3], count: 0, sum: [0; 3] }; LEVELS * LEVELS * LEVELS];
+    for &pixel in pixels {
+        let rgb = [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8];
+        let bin = &mut histogram[cell_index(rgb)];
+        bin.cell = rgb.map(|channel| channel >> (8 - CHANNEL_BITS));
+        bin.count += 1;
+        for (sum, channel) in bin.sum.iter_mut().zip(rgb) {
+            *sum += channel as u64;
+        }
+    }
+    let mut bins: Vec<Bin> = histogram.into_iter().filter(|bin| bin.count > 0).collect();
+
+    // Boxes are ranges of `bins`, which gets reordered as they are cut
+    let mut boxes = Vec::with_capacity(max_colors);
+    boxes.push(0..bins.len());
+    while boxes.len() < max_colors {
+        let widest = |range: &std::ops::Range<usize>| {
+            (0..3)
+                .map(|axis| {
+                    let values = bins[range.clone()].iter().map(|bin| bin.cell[axis]);
+                    (values.clone().max().unwrap_or(0) - values.min().unwrap_or(0), axis)
+                })
+                .max()
+                .unwrap()
+        };
+        let candidate = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.len() > 1)
+            .map(|(i, range)| {
+                let count: u64 = bins[range.clone()].iter().map(|bin| bin.count).sum();
+                let (extent, axis) = widest(range);
+                (count * (extent as u64 + 1), i, axis)
+            })
+            .max();
+        let Some((_, index, axis)) = candidate else {
+            break; // Every box is a single cell
+        };
+
+        let range = boxes[index].clone();
+        let cut = &mut bins[range.clone()];
+        cut.sort_unstable_by_key(|bin| bin.cell[axis]);
+        let total: u64 = cut.iter().map(|bin| bin.count).sum();
+        let mut below = 0;
+        let median = cut
+            .iter()
+            .position(|bin| {
+                below += bin.count;
+                below * 2 >= total
+            })
+            .unwrap();
+        let split = range.start + (median + 1).clamp(1, range.len() - 1);
+        boxes[index] = range.start..split;
+        boxes.push(split..range.end);
+    }
+
+    boxes
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let count: u64 = bins[range.clone()].iter().map(|bin| bin.count).sum();
+            let mut color = [0; 3];
+            for (axis, channel) in color.iter_mut().enumerate() {
+                let sum: u64 = bins[range.clone()].iter().map(|bin| bin.sum[axis]).sum();
+                *channel = ((sum + count / 2) / count) as u8;
+            }
+            color
+        })
+        .collect()
+}
+
+// Palette index of every pixel, rows of `width` pixels. With dithering the
+// error of each pixel is carried over to its unvisited neighbors (7/16 to the
+// right, 3/16, 5/16 and 1/16 to the row below)
+pub fn map_to_palette(pixels: &[u32], width: usize, palette: &[[u8; 3]], dither: bool) -> Vec<u8> {
+    let mut lookup = vec![UNKNOWN; LEVELS * LEVELS * LEVELS];
+    let mut nearest = |rgb: [u8; 3]| {
+        let entry = &mut lookup[cell_index(rgb)];
+        if *entry == UNKNOWN {
+            let center = rgb.map(|channel| (channel >> (8 - CHANNEL_BITS) << (8 - CHANNEL_BITS)) | (1 << (7 - CHANNEL_BITS)));
+            *entry = (0..palette.len()).min_by_key(|&i| distance(palette[i], center)).unwrap() as u16;
+        }
+        *entry as u8
+    };
+
+    let mut indices = Vec::with_capacity(pixels.len());
+    if !dither {
+        indices.extend(pixels.iter().map(|&pixel| nearest([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])));
+        return indices;
+    }
+
+    // Errors of this row and the next, with a pixel of margin on both sides
+    let mut errors = vec![[0.0f32; 3]; width + 2];
+    let mut next_errors = vec![[0.0f32; 3]; width + 2];
+    for row in pixels.chunks_exact(width.max(1)) {
+        for (x, &pixel) in row.iter().enumerate() {
+            let original = [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8];
+            let wanted: [f32; 3] = std::array::from_fn(|axis| (original[axis] as f32 + errors[x + 1][axis]).clamp(0.0, 255.0));
+            let index = nearest(wanted.map(|channel| channel.round() as u8));
+            indices.push(index);
+
+            let chosen = palette[index as usize];
+            for axis in 0..3 {
+                let error = wanted[axis] - chosen[axis] as f32;
+                errors[x + 2][axis] += error * 7.0 / 16.0;
+                next_errors[x][axis] += error * 3.0 / 16.0;
+                next_errors[x + 1][axis] += error * 5.0 / 16.0;
+                next_errors[x + 2][axis] += error * 1.0 / 16.0;
+            }
+        }
+        std::mem::swap(&mut errors, &mut next_errors);
+        next_errors.fill([0.0; 3]);
+    }
+    indices
+}
+
+fn cell_index(rgb: [u8; 3]) -> usize {
+    let [r, g, b] = rgb.map(|channel| (channel >> (8 - CHANNEL_BITS)) as usize);
+    (r * LEVELS + g) * LEVELS + b
+}
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    (0..3).map(|axis| (a[axis] as i32 - b[axis] as i32).pow(2) as u32).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_color_gives_that_color() {
+        let pixels = vec![0x33_66_99; 1000];
+        assert_eq!(median_cut(&pixels, 256), [[0x33, 0x66, 0x99]]);
+        assert_eq!(map_to_palette(&pixels, 10, &[[0x33, 0x66, 0x99]], true), vec![0; 1000]);
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let pixels = [0xFF_00_00, 0x00_FF_00, 0x00_00_FF, 0xFF_00_00];
+        let mut palette = median_cut(&pixels, 256);
+        palette.sort();
+        assert_eq!(palette, [[0, 0, 255], [0, 255, 0], [255, 0, 0]]);
+        assert!(median_cut(&[], 256).is_empty());
+    }
+
+    #[test]
+    fn many_colors_fill_the_palette() {
+        // 4096 colors, each in its own histogram cell
+        let pixels: Vec<u32> = (0..4096u32).map(|i| (i & 0xF) << 20 | (i >> 4 & 0xF) << 12 | (i >> 8) << 4).collect();
+        let palette = median_cut(&pixels, 256);
+        assert_eq!(palette.len(), 256);
+        let mut distinct = palette.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 256);
+
+        for dither in [false, true] {
+            let indices = map_to_palette(&pixels, 64, &palette, dither);
+            assert_eq!(indices.len(), pixels.len());
+            assert!(indices.iter().all(|&index| (index as usize) < palette.len()));
+        }
+    }
+}
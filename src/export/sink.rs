@@ -0,0 +1,138 @@
+// export/sink.rs
+// Destinations for rendered frames. The headless render loop hands every
+// frame to each of its sinks, so numbered images, depth dumps, an animated GIF
+// and a video stream can all be written from the same run.
+use super::ImageFormat;
+use crate::framebuffer::Framebuffer;
+use std::io;
+use std::path::PathBuf;
+
+pub trait FrameSink {
+    // Takes the frame shown from `time` seconds into the animation
+    fn write_frame(&mut self, framebuffer: &Framebuffer, time: f32) -> io::Result<()>;
+
+    // Completes the output once the last frame is written; it is shown
+    // until `end_time`. Called once, nothing is written afterwards
+    fn finish(&mut self, _end_time: f32) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Numbered image files frame_00000.ppm, frame_00001.ppm, ... in a directory
+pub struct ImageSequence {
+    directory: PathBuf,
+    format: ImageFormat,
+    frame: usize,
+}
+
+impl ImageSequence {
+    pub fn new(directory: impl Into<PathBuf>, format: ImageFormat) -> io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(ImageSequence { directory, format, frame: 0 })
+    }
+}
+
+impl FrameSink for ImageSequence {
+    fn write_frame(&mut self, framebuffer: &Framebuffer, _time: f32) -> io::Result<()> {
+        let name = format!("frame_{:05}.{}", self.frame, self.format.extension());
+        super::save_image(framebuffer, self.format, self.directory.join(&name)).map_err(|err| named_error(&name, err))?;
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+// Numbered PFM dumps of the depth buffer, frame_00000_depth.pfm, ...
+pub struct DepthSequence {
+    directory: PathBuf,
+    frame: usize,
+}
+
+impl DepthSequence {
+    pub fn new(directory: impl Into<PathBuf>) -> io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(DepthSequence { directory, frame: 0 })
+    }
+}
+
+impl FrameSink for DepthSequence {
+    fn write_frame(&mut self, framebuffer: &Framebuffer, _time: f32) -> io::Result<()> {
+        let name = format!("frame_{:05}_depth.pfm", self.frame);
+        super::depth::save_pfm(framebuffer, self.directory.join(&name)).map_err(|err| named_error(&name, err))?;
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+fn named_error(name: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", name, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::y4m::{Chroma, Y4mWriter};
+    use std::fs::File;
+    use std::path::Path;
+
+    // Fresh directory under the system temporary directory
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("sink_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn file_names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(directory).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn numbers_frames_and_finishes_every_sink() {
+        let directory = temporary_directory("numbers");
+        let video_path = directory.join("video.y4m");
+        let mut sinks: Vec<Box<dyn FrameSink>> = vec![
+            Box::new(ImageSequence::new(&directory, ImageFormat::Bmp).unwrap()),
+            Box::new(DepthSequence::new(&directory).unwrap()),
+        ];
+        sinks.push(Box::new(Y4mWriter::new(File::create(&video_path).unwrap(), 4, 2, (60, 1), Chroma::Subsampled420).unwrap()));
+
+        let framebuffer = Framebuffer::new(4, 2);
+        for frame in 0..3 {
+            for sink in sinks.iter_mut() {
+                sink.write_frame(&framebuffer, frame as f32 / 60.0).unwrap();
+            }
+        }
+        for sink in sinks.iter_mut() {
+            sink.finish(3.0 / 60.0).unwrap();
+        }
+        drop(sinks);
+
+        let frames: Vec<String> = (0..3).flat_map(|frame| [format!("frame_{:05}.bmp", frame), format!("frame_{:05}_depth.pfm", frame)]).collect();
+        assert_eq!(file_names(&directory), [frames, vec!["video.y4m".to_string()]].concat());
+        let video = std::fs::read(&video_path).unwrap();
+        let header = video.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        assert_eq!(video.len(), header + 3 * (b"FRAME\n".len() + 4 * 2 + 2 * 2));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn names_the_file_that_failed() {
+        let directory = temporary_directory("errors");
+        let mut images = ImageSequence::new(&directory, ImageFormat::Ppm).unwrap();
+        // A directory where the second frame should go cannot be written over
+        std::fs::create_dir(directory.join("frame_00001.ppm")).unwrap();
+        let framebuffer = Framebuffer::new(2, 2);
+        images.write_frame(&framebuffer, 0.0).unwrap();
+        let err = images.write_frame(&framebuffer, 0.1).unwrap_err();
+        assert!(err.to_string().starts_with("frame_00001.ppm: "), "{}", err);
+
+        let mut depths = DepthSequence::new(&directory).unwrap();
+        std::fs::create_dir(directory.join("frame_00000_depth.pfm")).unwrap();
+        let err = depths.write_frame(&framebuffer, 0.0).unwrap_err();
+        assert!(err.to_string().starts_with("frame_00000_depth.pfm: "), "{}", err);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+// export/y4m.rs
+// Uncompressed YUV4MPEG2 video, which video tools (ffmpeg, x264, mpv) read
+// from a file or a pipe. Colors are converted with BT.601 to limited range
+// YCbCr; 4:2:0 averages the chroma of every 2x2 block, 4:4:4 keeps all of it.
+use super::sink::FrameSink;
+use crate::framebuffer::Framebuffer;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chroma {
+    Subsampled420,
+    Full444,
+}
+
+impl Chroma {
+    // From the usual names, "420" or "444"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "420" => Some(Chroma::Subsampled420),
+            "444" => Some(Chroma::Full444),
+            _ => None,
+        }
+    }
+
+    fn header_tag(self) -> &'static str {
+        match self {
+            Chroma::Subsampled420 => "C420jpeg", // Chroma sited at the center of each 2x2 block
+            Chroma::Full444 => "C444",
+        }
+    }
+}
+
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    chroma: Chroma,
+    planes: Vec<u8>, // Y, Cb and Cr of the frame being written, reused between frames
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // Writes the stream header; `frame_rate` is frames per second as a fraction
+    pub fn new(mut writer: W, width: usize, height: usize, frame_rate: (u32, u32), chroma: Chroma) -> io::Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("a video cannot be {}x{}", width, height)));
+        }
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 {} XCOLORRANGE=LIMITED",
+            width,
+            height,
+            frame_rate.0,
+            frame_rate.1,
+            chroma.header_tag()
+        )?;
+        Ok(Y4mWriter { writer, width, height, chroma, planes: Vec::new() })
+    }
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn write_frame(&mut self, framebuffer: &Framebuffer, _time: f32) -> io::Result<()> {
+        if framebuffer.width != self.width || framebuffer.height != self.height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the frame size changed during the video"));
+        }
+        let (width, height) = (self.width, self.height);
+        let pixels = &framebuffer.buffer;
+        self.planes.clear();
+        self.planes.extend(pixels.iter().map(|&pixel| {
+            let (r, g, b) = channels(pixel);
+            luma(r, g, b)
+        }));
+
+        match self.chroma {
+            Chroma::Full444 => {
+                self.planes.extend(pixels.iter().map(|&pixel| {
+                    let (r, g, b) = channels(pixel);
+                    chroma_blue(r, g, b)
+                }));
+                self.planes.extend(pixels.iter().map(|&pixel| {
+                    let (r, g, b) = channels(pixel);
+                    chroma_red(r, g, b)
+                }));
+            }
+            Chroma::Subsampled420 => {
+                // Average color of each 2x2 block; blocks on an odd edge have fewer pixels
+                let mut averages = Vec::with_capacity(width.div_ceil(2) * height.div_ceil(2));
+                for block_y in (0..height).step_by(2) {
+                    for block_x in (0..width).step_by(2) {
+                        let (mut r, mut g, mut b, mut count) = (0, 0, 0, 0);
+                        for y in block_y..(block_y + 2).min(height) {
+                            for x in block_x..(block_x + 2).min(width) {
+                                let (pr, pg, pb) = channels(pixels[y * width + x]);
+                                (r, g, b, count) = (r + pr, g + pg, b + pb, count + 1);
+                            }
+                        }
+                        averages.push(((r + count / 2) / count, (g + count / 2) / count, (b + count / 2) / count));
+                    }
+                }
+                self.planes.extend(averages.iter().map(|&(r, g, b)| chroma_blue(r, g, b)));
+                self.planes.extend(averages.iter().map(|&(r, g, b)| chroma_red(r, g, b)));
+            }
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.planes)
+    }
+
+    fn finish(&mut self, _end_time: f32) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn channels(pixel: u32) -> (i32, i32, i32) {
+    (((pixel >> 16) & 0xFF) as i32, ((pixel >> 8) & 0xFF) as i32, (pixel & 0xFF) as i32)
+}
+
+// BT.601 in 8-bit fixed point, luma in 16..=235 and chroma in 16..=240
+fn luma(r: i32, g: i32, b: i32) -> u8 {
+    (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+}
+
+fn chroma_blue(r: i32, g: i32, b: i32) -> u8 {
+    (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8
+}
+
+fn chroma_red(r: i32, g: i32, b: i32) -> u8 {
+    (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: u32 = 0xFF_FF_FF;
+    const RED: u32 = 0xFF_00_00;
+
+    fn frame(width: usize, height: usize, pixels: &[u32]) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(width, height);
+        framebuffer.buffer.copy_from_slice(pixels);
+        framebuffer
+    }
+
+    // The stream header line, and the frames after it
+    fn encode(framebuffers: &[Framebuffer], chroma: Chroma) -> (String, Vec<u8>) {
+        let (width, height) = (framebuffers[0].width, framebuffers[0].height);
+        let mut video = Y4mWriter::new(Vec::new(), width, height, (30000, 1001), chroma).unwrap();
+        for framebuffer in framebuffers {
+            video.write_frame(framebuffer, 0.0).unwrap();
+        }
+        video.finish(0.0).unwrap();
+        let end = video.writer.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        (String::from_utf8(video.writer[..end].to_vec()).unwrap(), video.writer[end..].to_vec())
+    }
+
+    #[test]
+    fn writes_stream_and_frame_headers() {
+        let (header, _) = encode(&[frame(4, 2, &[0; 8])], Chroma::Subsampled420);
+        assert_eq!(header, "YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n");
+
+        let (header, frames) = encode(&[frame(4, 2, &[0; 8]), frame(4, 2, &[WHITE; 8])], Chroma::Full444);
+        assert_eq!(header, "YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C444 XCOLORRANGE=LIMITED\n");
+        let size = 6 + 3 * 8;
+        assert_eq!(frames.len(), 2 * size);
+        assert_eq!(&frames[..6], b"FRAME\n");
+        assert_eq!(&frames[size..size + 6], b"FRAME\n");
+        assert_eq!(frames[size + 6], 235); // Luma of the white frame
+
+        assert!(Y4mWriter::new(Vec::new(), 0, 2, (60, 1), Chroma::Full444).is_err());
+        let mut video = Y4mWriter::new(Vec::new(), 4, 2, (60, 1), Chroma::Full444).unwrap();
+        assert!(video.write_frame(&frame(2, 2, &[0; 4]), 0.0).is_err());
+    }
+
+    #[test]
+    fn rounds_chroma_planes_up_for_odd_sizes() {
+        for (width, height, chroma_size) in [(3, 3, 4), (5, 2, 3), (1, 1, 1), (4, 4, 4)] {
+            let (_, frames) = encode(&[frame(width, height, &vec![RED; width * height])], Chroma::Subsampled420);
+            assert_eq!(frames.len(), 6 + width * height + 2 * chroma_size, "{}x{}", width, height);
+            // Partial blocks on the edges average the pixels they have
+            assert!(frames[6 + width * height..].chunks(chroma_size).zip([90, 240]).all(|(plane, value)| plane.iter().all(|&byte| byte == value)));
+        }
+    }
+
+    #[test]
+    fn converts_with_bt601_limited_range() {
+        assert_eq!((luma(255, 255, 255), chroma_blue(255, 255, 255), chroma_red(255, 255, 255)), (235, 128, 128));
+        assert_eq!((luma(0, 0, 0), chroma_blue(0, 0, 0), chroma_red(0, 0, 0)), (16, 128, 128));
+        assert_eq!((luma(255, 0, 0), chroma_blue(255, 0, 0), chroma_red(255, 0, 0)), (82, 90, 240));
+
+        // White, black and red pixels keep their own chroma in 4:4:4
+        let (_, frames) = encode(&[frame(3, 1, &[WHITE, 0, RED])], Chroma::Full444);
+        assert_eq!(&frames[6..], &[235, 16, 82, 128, 128, 90, 128, 128, 240]);
+
+        // and share the average of their block in 4:2:0
+        let (_, frames) = encode(&[frame(2, 2, &[WHITE, 0, RED, RED])], Chroma::Subsampled420);
+        let average = ((3 * 255 + 2) / 4, (255 + 2) / 4, (255 + 2) / 4); // Rounded means of the four pixels
+        assert_eq!(&frames[6..], &[235, 16, 82, 82, chroma_blue(average.0, average.1, average.2), chroma_red(average.0, average.1, average.2)]);
+    }
+}
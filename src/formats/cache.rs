@@ -0,0 +1,481 @@
+// formats/cache.rs
+// Binary cache of processed meshes: the levels of detail of a model, after
+// normal/tangent generation and simplification, with their bounds. It is
+// written next to the source file and reused while the FNV-1a hashes of the
+// source and of the processing settings still match, so large models skip
+// parsing and processing.
+//
+// Layout, little-endian: magic "MSHC", format version (u32), source hash
+// (u64), settings hash (u64), bounding sphere (4 f32), bounding box (6 f32), level count (u32),
+// then every level: vertices, indices, sub-meshes and materials, each as a
+// u32 count followed by the items. Strings are a u32 length and UTF-8 bytes.
+use crate::mesh::bounds::{Aabb, BoundingSphere};
+use crate::mesh::lod::{LodMesh, MAX_LOD_LEVELS, MIN_LOD_TRIANGLES};
+use crate::mesh::{Material, Mesh, SubMesh};
+use crate::vertex::Vertex;
+use nalgebra_glm::{Vec2, Vec3, Vec4};
+use raylib::prelude::Color;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"MSHC";
+// Bump whenever the layout or the processing code of the cached meshes
+// changes. Processing parameters are covered by the settings hash instead
+pub const CACHE_VERSION: u32 = 2;
+const VERTEX_SIZE: usize = 12 * 4 + 4; // Position, normal, texture coordinates, tangent and the color
+
+#[derive(Debug)]
+pub enum CacheErrorKind {
+    Io(io::Error),
+    NotACache,
+    Version(u32),
+    SourceChanged,
+    SettingsChanged,
+    Truncated,
+    Invalid(String),
+}
+
+#[derive(Debug)]
+pub struct CacheError {
+    pub file: String,
+    pub kind: CacheErrorKind,
+}
+
+impl fmt::Display for CacheErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheErrorKind::Io(err) => write!(f, "could not read file: {}", err),
+            CacheErrorKind::NotACache => write!(f, "not a mesh cache"),
+            CacheErrorKind::Version(version) => write!(f, "cache version {}, expected {}", version, CACHE_VERSION),
+            CacheErrorKind::SourceChanged => write!(f, "the source file changed since the cache was written"),
+            CacheErrorKind::SettingsChanged => write!(f, "the processing settings changed since the cache was written"),
+            CacheErrorKind::Truncated => write!(f, "file is truncated"),
+            CacheErrorKind::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.kind)
+    }
+}
+
+impl std::error::Error for CacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            CacheErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// What a cache was built from: the source file and the parameters the meshes
+// were processed with. A cache is only reused when both match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey {
+    pub source: u64,
+    pub settings: u64,
+}
+
+// 64-bit FNV-1a hash, continuing from `hash` (start with FNV_OFFSET)
+pub const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+// Hash of a model file. OBJ files also hash the material libraries they
+// name, as editing those changes the loaded mesh too
+pub fn source_hash(path: &Path) -> io::Result<u64> {
+    let bytes = std::fs::read(path)?;
+    let mut hash = fnv1a(FNV_OFFSET, &bytes);
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("obj")) {
+        let base = path.parent().unwrap_or(Path::new(""));
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            if let Some(libraries) = line.trim_start().strip_prefix("mtllib ") {
+                for library in super::obj::library_names(libraries) {
+                    hash = fnv1a(hash, library.as_bytes());
+                    if let Ok(contents) = std::fs::read(base.join(library)) {
+                        hash = fnv1a(hash, &contents);
+                    }
+                }
+            }
+        }
+    }
+    Ok(hash)
+}
+
+// Hash of the parameters baked into the cached meshes: the crease angle of
+// the generated normals and the limits of the level of detail chain
+pub fn settings_hash(crease_angle: f32) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET, &crease_angle.to_le_bytes());
+    for limit in [MAX_LOD_LEVELS, MIN_LOD_TRIANGLES] {
+        hash = fnv1a(hash, &(limit as u64).to_le_bytes());
+    }
+    hash
+}
+
+// Where the cache of a model file goes: next to it, "model.obj.meshcache"
+pub fn cache_path(source: &Path) -> PathBuf {
+    let mut name = source.as_os_str().to_os_string();
+    name.push(".meshcache");
+    PathBuf::from(name)
+}
+
+// Reads a cache, rejecting it when it was written for another source or
+// other settings
+pub fn load_cache(path: impl AsRef<Path>, key: CacheKey) -> Result<LodMesh, CacheError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let bytes = std::fs::read(path).map_err(|err| CacheError { file: file.clone(), kind: CacheErrorKind::Io(err) })?;
+    parse_cache(&bytes, key).map_err(|kind| CacheError { file, kind })
+}
+
+pub fn parse_cache(bytes: &[u8], key: CacheKey) -> Result<LodMesh, CacheErrorKind> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(4).ok() != Some(MAGIC.as_slice()) {
+        return Err(CacheErrorKind::NotACache);
+    }
+    let version = reader.u32()?;
+    if version != CACHE_VERSION {
+        return Err(CacheErrorKind::Version(version));
+    }
+    if reader.u64()? != key.source {
+        return Err(CacheErrorKind::SourceChanged);
+    }
+    if reader.u64()? != key.settings {
+        return Err(CacheErrorKind::SettingsChanged);
+    }
+
+    let bounds = BoundingSphere { center: reader.vec3()?, radius: reader.f32()? };
+    let bounding_box = Aabb { min: reader.vec3()?, max: reader.vec3()? };
+    let level_count = reader.count(1)?;
+    if level_count == 0 {
+        return Err(CacheErrorKind::Invalid("no mesh levels".to_string()));
+    }
+    if level_count > MAX_LOD_LEVELS {
+        return Err(CacheErrorKind::Invalid(format!("{} mesh levels, at most {} are drawn", level_count, MAX_LOD_LEVELS)));
+    }
+    let levels = (0..level_count).map(|_| reader.mesh()).collect::<Result<Vec<_>, _>>()?;
+    if reader.offset != bytes.len() {
+        return Err(CacheErrorKind::Invalid("unexpected data after the last mesh".to_string()));
+    }
+
+    Ok(LodMesh::from_parts(levels, bounds, bounding_box))
+}
+
+pub fn write_cache(lod: &LodMesh, key: CacheKey, writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&CACHE_VERSION.to_le_bytes())?;
+    writer.write_all(&key.source.to_le_bytes())?;
+    writer.write_all(&key.settings.to_le_bytes())?;
+    write_floats(writer, &[lod.bounds.center.x, lod.bounds.center.y, lod.bounds.center.z, lod.bounds.radius])?;
+    let (min, max) = (lod.bounding_box.min, lod.bounding_box.max);
+    write_floats(writer, &[min.x, min.y, min.z, max.x, max.y, max.z])?;
+
+    write_count(writer, lod.levels.len())?;
+    for mesh in &lod.levels {
+        write_count(writer, mesh.vertices.len())?;
+        for v in &mesh.vertices {
+            write_floats(writer, &[v.position.x, v.position.y, v.position.z, v.normal.x, v.normal.y, v.normal.z])?;
+            write_floats(writer, &[v.tex_coords.x, v.tex_coords.y, v.tangent.x, v.tangent.y, v.tangent.z, v.tangent.w])?;
+            writer.write_all(&[v.color.r, v.color.g, v.color.b, v.color.a])?;
+        }
+
+        write_count(writer, mesh.indices.len())?;
+        for index in &mesh.indices {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+
+        write_count(writer, mesh.submeshes.len())?;
+        for submesh in &mesh.submeshes {
+            write_string(writer, &submesh.name)?;
+            write_count(writer, submesh.first_index)?;
+            write_count(writer, submesh.index_count)?;
+            writer.write_all(&submesh.material.map_or(u32::MAX, |material| material as u32).to_le_bytes())?;
+        }
+
+        write_count(writer, mesh.materials.len())?;
+        for material in &mesh.materials {
+            write_string(writer, &material.name)?;
+            let [diffuse, specular, emissive] = [material.diffuse, material.specular, material.emissive];
+            write_floats(writer, &[diffuse.x, diffuse.y, diffuse.z, specular.x, specular.y, specular.z, emissive.x, emissive.y, emissive.z])?;
+            write_floats(writer, &[material.shininess, material.opacity, material.metallic, material.roughness])?;
+            for map in [&material.diffuse_map, &material.bump_map] {
+                write_string(writer, &map.as_ref().map_or(String::new(), |path| path.to_string_lossy().into_owned()))?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+// Writes through a temporary file, so a crash never leaves a half-written cache
+pub fn save_cache(lod: &LodMesh, key: CacheKey, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    let mut writer = io::BufWriter::new(std::fs::File::create(&temporary)?);
+    write_cache(lod, key, &mut writer)?;
+    drop(writer);
+    std::fs::rename(&temporary, path)
+}
+
+fn write_floats(writer: &mut impl Write, values: &[f32]) -> io::Result<()> {
+    values.iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+}
+
+fn write_count(writer: &mut impl Write, count: usize) -> io::Result<()> {
+    let count = u32::try_from(count).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "mesh too large for the cache"))?;
+    writer.write_all(&count.to_le_bytes())
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    write_count(writer, value.len())?;
+    writer.write_all(value.as_bytes())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], CacheErrorKind> {
+        let end = self.offset.checked_add(length).filter(|&end| end <= self.bytes.len()).ok_or(CacheErrorKind::Truncated)?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, CacheErrorKind> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, CacheErrorKind> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, CacheErrorKind> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, CacheErrorKind> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    // Item count, checked against the bytes left so a corrupt count fails
+    // instead of allocating a huge buffer
+    fn count(&mut self, item_size: usize) -> Result<usize, CacheErrorKind> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(item_size) > self.bytes.len() - self.offset {
+            return Err(CacheErrorKind::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, CacheErrorKind> {
+        let length = self.count(1)?;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| CacheErrorKind::Invalid("invalid UTF-8 in a name".to_string()))
+    }
+
+    fn mesh(&mut self) -> Result<Mesh, CacheErrorKind> {
+        let invalid = |message: &str| CacheErrorKind::Invalid(message.to_string());
+
+        let vertex_count = self.count(VERTEX_SIZE)?;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        for _ in 0..vertex_count {
+            let (position, normal) = (self.vec3()?, self.vec3()?);
+            let tex_coords = Vec2::new(self.f32()?, self.f32()?);
+            let tangent = Vec4::new(self.f32()?, self.f32()?, self.f32()?, self.f32()?);
+            let color = self.take(4)?;
+            let mut vertex = Vertex::new(position, normal, Color::new(color[0], color[1], color[2], color[3]));
+            vertex.tex_coords = tex_coords;
+            vertex.tangent = tangent;
+            vertices.push(vertex);
+        }
+
+        let index_count = self.count(4)?;
+        let indices = (0..index_count).map(|_| self.u32()).collect::<Result<Vec<_>, _>>()?;
+        if index_count % 3 != 0 || indices.iter().any(|&index| index as usize >= vertex_count) {
+            return Err(invalid("index out of range"));
+        }
+
+        let submesh_count = self.count(16)?;
+        let mut submeshes = Vec::with_capacity(submesh_count);
+        for _ in 0..submesh_count {
+            let name = self.string()?;
+            let (first_index, index_count) = (self.u32()? as usize, self.u32()? as usize);
+            let material = match self.u32()? {
+                u32::MAX => None,
+                material => Some(material as usize),
+            };
+            submeshes.push(SubMesh { name, first_index, index_count, material });
+        }
+
+        let material_count = self.count(4 + 13 * 4 + 8)?;
+        let mut materials = Vec::with_capacity(material_count);
+        for _ in 0..material_count {
+            let mut material = Material::new(&self.string()?);
+            (material.diffuse, material.specular, material.emissive) = (self.vec3()?, self.vec3()?, self.vec3()?);
+            (material.shininess, material.opacity, material.metallic, material.roughness) = (self.f32()?, self.f32()?, self.f32()?, self.f32()?);
+            let mut map = || self.string().map(|path| (!path.is_empty()).then(|| PathBuf::from(path)));
+            (material.diffuse_map, material.bump_map) = (map()?, map()?);
+            materials.push(material);
+        }
+
+        for submesh in &submeshes {
+            if submesh.first_index + submesh.index_count > indices.len() || submesh.material.is_some_and(|material| material >= materials.len()) {
+                return Err(invalid(&format!("sub-mesh '{}' out of range", submesh.name)));
+            }
+        }
+
+        let mut mesh = Mesh::new(vertices, indices);
+        mesh.submeshes = submeshes;
+        mesh.materials = materials;
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: CacheKey = CacheKey { source: 0x0123_4567_89ab_cdef, settings: 0xfedc_ba98_7654_3210 };
+
+    // A textured quad in two sub-meshes, one of them with a material
+    fn quad() -> Mesh {
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let vertices = corners
+            .iter()
+            .map(|&(x, y)| {
+                let mut vertex = Vertex::new(Vec3::new(x, y, 0.5), Vec3::z(), Color::new(10, 20, 30, 255));
+                vertex.tex_coords = Vec2::new(x, 1.0 - y);
+                vertex.tangent = Vec4::new(1.0, 0.0, 0.0, -1.0);
+                vertex
+            })
+            .collect();
+        let mut mesh = Mesh::new(vertices, vec![0, 1, 2, 0, 2, 3]);
+        mesh.submeshes = vec![
+            SubMesh { name: "front".to_string(), first_index: 0, index_count: 3, material: Some(0) },
+            SubMesh { name: "back".to_string(), first_index: 3, index_count: 3, material: None },
+        ];
+        let mut material = Material::new("brick");
+        material.diffuse = Vec3::new(0.8, 0.3, 0.2);
+        material.shininess = 12.0;
+        material.diffuse_map = Some(PathBuf::from("textures/brick.png"));
+        mesh.materials = vec![material];
+        mesh
+    }
+
+    fn lod(levels: Vec<Mesh>) -> LodMesh {
+        let bounds = BoundingSphere { center: Vec3::new(0.5, 0.5, 0.5), radius: 0.75 };
+        let bounding_box = Aabb { min: Vec3::new(0.0, 0.0, 0.5), max: Vec3::new(1.0, 1.0, 0.5) };
+        LodMesh::from_parts(levels, bounds, bounding_box)
+    }
+
+    fn write(lod: &LodMesh) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_cache(lod, KEY, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut coarse = quad();
+        coarse.indices.truncate(3);
+        coarse.submeshes.truncate(1);
+        let original = lod(vec![quad(), coarse]);
+        let bytes = write(&original);
+
+        let loaded = parse_cache(&bytes, KEY).unwrap();
+        assert_eq!(loaded.bounds, original.bounds);
+        assert_eq!(loaded.bounding_box, original.bounding_box);
+        assert_eq!(loaded.levels.len(), 2);
+        for (loaded, original) in loaded.levels.iter().zip(&original.levels) {
+            assert_eq!(loaded.indices, original.indices);
+            assert_eq!(loaded.submeshes, original.submeshes);
+            assert_eq!(loaded.materials, original.materials);
+            for (a, b) in loaded.vertices.iter().zip(&original.vertices) {
+                assert_eq!((a.position, a.normal, a.tex_coords, a.tangent), (b.position, b.normal, b.tex_coords, b.tangent));
+                assert_eq!(a.color, b.color);
+            }
+            assert_eq!(loaded.vertices.len(), original.vertices.len());
+        }
+        assert_eq!(write(&loaded), bytes);
+
+        // Vertex data right at the end of the file is not mistaken for a truncation
+        let points = Mesh::new(quad().vertices.repeat(8), Vec::new());
+        assert_eq!(parse_cache(&write(&lod(vec![points])), KEY).unwrap().levels[0].vertices.len(), 32);
+    }
+
+    #[test]
+    fn rejects_other_files_versions_sources_and_settings() {
+        let bytes = write(&lod(vec![quad()]));
+
+        let mut other = bytes.clone();
+        other[..4].copy_from_slice(b"MSHX");
+        assert!(matches!(parse_cache(&other, KEY), Err(CacheErrorKind::NotACache)));
+
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+        assert!(matches!(parse_cache(&newer, KEY), Err(CacheErrorKind::Version(version)) if version == CACHE_VERSION + 1));
+
+        assert!(matches!(parse_cache(&bytes, CacheKey { source: KEY.source ^ 1, ..KEY }), Err(CacheErrorKind::SourceChanged)));
+        assert!(matches!(parse_cache(&bytes, CacheKey { settings: KEY.settings ^ 1, ..KEY }), Err(CacheErrorKind::SettingsChanged)));
+        assert_ne!(settings_hash(60f32.to_radians()), settings_hash(45f32.to_radians()));
+    }
+
+    #[test]
+    fn rejects_every_truncation() {
+        let bytes = write(&lod(vec![quad()]));
+        for length in 0..bytes.len() {
+            match parse_cache(&bytes[..length], KEY) {
+                Err(CacheErrorKind::NotACache) => assert!(length < MAGIC.len()),
+                Err(CacheErrorKind::Truncated) => assert!(length >= MAGIC.len()),
+                other => panic!("prefix of {} bytes gave {:?}", length, other.map(|_| ())),
+            }
+        }
+
+        let mut longer = bytes;
+        longer.push(0);
+        assert!(matches!(parse_cache(&longer, KEY), Err(CacheErrorKind::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_too_many_levels() {
+        let most = write(&lod(vec![quad(); MAX_LOD_LEVELS]));
+        assert_eq!(parse_cache(&most, KEY).unwrap().levels.len(), MAX_LOD_LEVELS);
+
+        // One level more than the renderer has counters for, as a stale or edited cache could hold
+        let bytes = write(&lod(vec![quad(); MAX_LOD_LEVELS + 1]));
+        assert!(matches!(parse_cache(&bytes, KEY), Err(CacheErrorKind::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_out_of_range_references() {
+        let bytes = write(&lod(vec![quad()]));
+        let invalid = |bytes: &[u8]| matches!(parse_cache(bytes, KEY), Err(CacheErrorKind::Invalid(_)));
+        let patched = |offset: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            bytes
+        };
+
+        // Offsets into the single level, which starts after the 68-byte header
+        let indices = 68 + 4 + 4 * VERTEX_SIZE + 4;
+        let back = indices + 6 * 4 + 4 + (4 + "front".len() + 12) + 4 + "back".len();
+        assert!(invalid(&patched(indices + 4 * 4, 4))); // Fifth index, past the four vertices
+        assert!(invalid(&patched(back + 4, 6))); // Index count of "back", past the index buffer
+        assert!(invalid(&patched(back + 8, 1))); // Material of "back", there is only one
+        assert!(!invalid(&patched(back + 8, 0)));
+
+        let mut mesh = quad();
+        mesh.indices.pop();
+        mesh.submeshes.clear();
+        assert!(invalid(&write(&lod(vec![mesh]))));
+
+        assert!(invalid(&write(&lod(Vec::new()))));
+    }
+}
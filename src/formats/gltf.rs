@@ -0,0 +1,1208 @@
+// formats/gltf.rs
+// glTF 2.0 importer for .gltf (external buffers or base64 data URIs) and .glb
+// files. Reads the node hierarchy of the default scene, triangle meshes (one
+// sub-mesh per primitive), metallic-roughness material factors and
+// translation/rotation/scale animations. Skins and morph targets are ignored.
+use super::json::{parse_json, Json, JsonError};
+use crate::mesh::{Material, Mesh, SubMesh};
+use crate::vertex::Vertex;
+use nalgebra_glm::{self as glm, Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use raylib::prelude::Color;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Largest accessor without a bufferView that is filled with zeros (16M values)
+const MAX_UNBACKED_VALUES: usize = 1 << 24;
+
+#[derive(Debug)]
+pub enum GltfErrorKind {
+    Io(std::io::Error),
+    Buffer { uri: String, error: std::io::Error },
+    Json(JsonError),
+    InvalidGlb(&'static str),
+    Invalid(String),
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct GltfError {
+    pub file: String,
+    pub kind: GltfErrorKind,
+}
+
+impl fmt::Display for GltfErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfErrorKind::Io(err) => write!(f, "could not read file: {}", err),
+            GltfErrorKind::Buffer { uri, error } => write!(f, "could not read buffer '{}': {}", uri, error),
+            GltfErrorKind::Json(err) => write!(f, "{}", err),
+            GltfErrorKind::InvalidGlb(message) => write!(f, "invalid GLB container: {}", message),
+            GltfErrorKind::Invalid(message) => write!(f, "invalid glTF: {}", message),
+            GltfErrorKind::Unsupported(message) => write!(f, "unsupported glTF feature: {}", message),
+        }
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.kind)
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            GltfErrorKind::Io(err) | GltfErrorKind::Buffer { error: err, .. } => Some(err),
+            GltfErrorKind::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<usize>, // Index into `Scene::meshes`
+    pub children: Vec<usize>,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Node {
+    // Transform from the node space to its parent's
+    pub fn local_matrix(&self) -> Mat4 {
+        glm::translation(&self.translation) * glm::quat_to_mat4(&self.rotation) * glm::scaling(&self.scale)
+    }
+}
+
+// Position, uniform scale and rotation angles in the form `create_model_matrix`
+// takes them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub position: Vec3,
+    pub scale: f32,
+    pub rotation: Vec3,
+}
+
+impl Placement {
+    // Decomposes an affine transform. `create_model_matrix` only has a uniform
+    // scale, so a non-uniform one is replaced by its geometric mean (and a
+    // mirroring can not be represented); use the matrix itself in those cases
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let position = Vec3::new(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+        let linear: Mat3 = glm::mat4_to_mat3(matrix);
+        let scales = Vec3::new(linear.column(0).norm(), linear.column(1).norm(), linear.column(2).norm());
+        let scale = (scales.x * scales.y * scales.z).cbrt();
+
+        // create_model_matrix rotates with Rx * Ry * Rz
+        let mut r = linear;
+        for (axis, &length) in scales.iter().enumerate() {
+            if length > 0.0 {
+                r.set_column(axis, &(r.column(axis) / length));
+            }
+        }
+        let sin_y = r[(0, 2)].clamp(-1.0, 1.0);
+        let rotation = if sin_y.abs() < 0.9999 {
+            Vec3::new((-r[(1, 2)]).atan2(r[(2, 2)]), sin_y.asin(), (-r[(0, 1)]).atan2(r[(0, 0)]))
+        } else {
+            // Gimbal lock: x and z rotate around the same axis, keep it all in x
+            Vec3::new(r[(2, 1)].atan2(r[(1, 1)]), sin_y.asin(), 0.0)
+        };
+
+        Placement { position, scale, rotation }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimatedProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyframeInterpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+// Keyframes driving one property of one node. Values are vectors (w unused)
+// or quaternions (x, y, z, w); cubic splines store in-tangent, value and
+// out-tangent for every keyframe
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: usize,
+    pub property: AnimatedProperty,
+    pub interpolation: KeyframeInterpolation,
+    pub times: Vec<f32>,
+    pub values: Vec<Vec4>,
+}
+
+impl Channel {
+    // Value at a time in seconds, holding the first/last keyframe outside them
+    pub fn sample(&self, time: f32) -> Vec4 {
+        let key = |i: usize| match self.interpolation {
+            KeyframeInterpolation::CubicSpline => self.values[i * 3 + 1],
+            _ => self.values[i],
+        };
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return key(0);
+        }
+        if time >= self.times[last] {
+            return key(last);
+        }
+
+        let next = self.times.partition_point(|&t| t <= time);
+        let previous = next - 1;
+        let duration = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / duration;
+        let rotation = self.property == AnimatedProperty::Rotation;
+
+        match self.interpolation {
+            KeyframeInterpolation::Step => key(previous),
+            KeyframeInterpolation::Linear if rotation => slerp(key(previous), key(next), t),
+            KeyframeInterpolation::Linear => key(previous).lerp(&key(next), t),
+            KeyframeInterpolation::CubicSpline => {
+                // Hermite spline, tangents are per second
+                let (t2, t3) = (t * t, t * t * t);
+                let out_tangent = self.values[previous * 3 + 2] * duration;
+                let in_tangent = self.values[next * 3] * duration;
+                let value = key(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + key(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2);
+                if rotation { value.normalize() } else { value }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    pub duration: f32, // Last keyframe time, in seconds
+}
+
+// Mesh drawn at a node, with the node's model matrix
+#[derive(Debug, Clone, Copy)]
+pub struct MeshInstance {
+    pub node: usize,
+    pub mesh: usize,
+    pub model_matrix: Mat4,
+}
+
+impl MeshInstance {
+    pub fn placement(&self) -> Placement {
+        Placement::from_matrix(&self.model_matrix)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub meshes: Vec<Mesh>,
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>, // Top-level nodes of the displayed scene
+    pub animations: Vec<Animation>,
+}
+
+impl Scene {
+    // Scene with a single mesh at the origin, for models from other sources
+    pub fn from_mesh(mesh: Mesh) -> Self {
+        let node = Node {
+            name: String::new(),
+            mesh: Some(0),
+            children: Vec::new(),
+            translation: Vec3::zeros(),
+            rotation: glm::quat(0.0, 0.0, 0.0, 1.0),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        };
+        Scene { meshes: vec![mesh], nodes: vec![node], roots: vec![0], animations: Vec::new() }
+    }
+
+    // Poses the nodes as the animation is at `time` seconds, looping it
+    pub fn animate(&mut self, animation: usize, time: f32) {
+        let animation = &self.animations[animation];
+        let time = if animation.duration > 0.0 { time.rem_euclid(animation.duration) } else { 0.0 };
+
+        for channel in &animation.channels {
+            let value = channel.sample(time);
+            let node = &mut self.nodes[channel.node];
+            match channel.property {
+                AnimatedProperty::Translation => node.translation = value.xyz(),
+                AnimatedProperty::Rotation => node.rotation = glm::quat(value.x, value.y, value.z, value.w),
+                AnimatedProperty::Scale => node.scale = value.xyz(),
+            }
+        }
+    }
+
+    // Every mesh of the scene with its model matrix (the node transforms
+    // accumulated from the root), ready for `Uniforms::model_matrix`
+    pub fn instances(&self) -> Vec<MeshInstance> {
+        let mut instances = Vec::new();
+        let mut stack: Vec<(usize, Mat4)> = self.roots.iter().rev().map(|&root| (root, Mat4::identity())).collect();
+
+        while let Some((index, parent_matrix)) = stack.pop() {
+            let node = &self.nodes[index];
+            let model_matrix = parent_matrix * node.local_matrix();
+            if let Some(mesh) = node.mesh {
+                instances.push(MeshInstance { node: index, mesh, model_matrix });
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, model_matrix)));
+        }
+
+        instances
+    }
+}
+
+// Loads a .gltf or .glb file. External buffers are resolved relative to it
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<Scene, GltfError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let bytes = std::fs::read(path).map_err(|err| GltfError { file: file.clone(), kind: GltfErrorKind::Io(err) })?;
+    parse_gltf(&bytes, &file)
+}
+
+// Parses the contents of a .gltf or .glb file. `file` is used in error
+// messages and to find external buffers and images
+pub fn parse_gltf(bytes: &[u8], file: &str) -> Result<Scene, GltfError> {
+    let base_dir = Path::new(file).parent().map(Path::to_path_buf).unwrap_or_default();
+    let error = |kind| GltfError { file: file.to_string(), kind };
+
+    let (json, binary_chunk) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes).map_err(error)?
+    } else {
+        (bytes, None)
+    };
+    let json = std::str::from_utf8(json).map_err(|_| error(GltfErrorKind::Invalid("the JSON is not UTF-8".to_string())))?;
+    let document = parse_json(json.trim_start_matches('\u{feff}')).map_err(|err| error(GltfErrorKind::Json(err)))?;
+
+    Importer::new(&document, binary_chunk, base_dir).and_then(|importer| importer.scene()).map_err(error)
+}
+
+// Splits a GLB container into its JSON and binary chunks
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfErrorKind> {
+    let read_u32 = |offset: usize| bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+    if read_u32(4) != Some(2) {
+        return Err(GltfErrorKind::InvalidGlb("only version 2 is supported"));
+    }
+    let length = read_u32(8).ok_or(GltfErrorKind::InvalidGlb("truncated header"))?;
+    if length > bytes.len() {
+        return Err(GltfErrorKind::InvalidGlb("file shorter than its header says"));
+    }
+
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(offset).ok_or(GltfErrorKind::InvalidGlb("truncated chunk"))?;
+        let chunk_type = read_u32(offset + 4).ok_or(GltfErrorKind::InvalidGlb("truncated chunk"))?;
+        let data = bytes.get(offset + 8..offset + 8 + chunk_length).ok_or(GltfErrorKind::InvalidGlb("truncated chunk"))?;
+        match chunk_type {
+            0x4E4F_534A if json.is_none() => json = Some(data),   // "JSON"
+            0x004E_4942 if binary.is_none() => binary = Some(data), // "BIN\0"
+            _ => {} // Unknown chunks must be skipped
+        }
+        offset += 8 + chunk_length.div_ceil(4) * 4;
+    }
+
+    Ok((json.ok_or(GltfErrorKind::InvalidGlb("missing JSON chunk"))?, binary))
+}
+
+fn invalid(message: String) -> GltfErrorKind {
+    GltfErrorKind::Invalid(message)
+}
+
+// Element `index` of a top-level array such as "accessors"
+fn item<'a>(document: &'a Json, array: &str, index: usize) -> Result<&'a Json, GltfErrorKind> {
+    document
+        .get(array)
+        .and_then(Json::as_array)
+        .and_then(|items| items.get(index))
+        .ok_or_else(|| invalid(format!("{}[{}] does not exist", array, index)))
+}
+
+fn items<'a>(document: &'a Json, array: &str) -> &'a [Json] {
+    document.get(array).and_then(Json::as_array).unwrap_or(&[])
+}
+
+fn index_field(object: &Json, key: &str, context: &str) -> Result<Option<usize>, GltfErrorKind> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value.as_usize().map(Some).ok_or_else(|| invalid(format!("{}.{} is not an index", context, key))),
+    }
+}
+
+fn float_array<const N: usize>(object: &Json, key: &str, default: [f32; N]) -> Result<[f32; N], GltfErrorKind> {
+    let Some(value) = object.get(key) else {
+        return Ok(default);
+    };
+    let values = value.as_array().filter(|values| values.len() == N);
+    let mut result = default;
+    for (i, slot) in result.iter_mut().enumerate() {
+        *slot = values
+            .and_then(|values| values[i].as_f32())
+            .ok_or_else(|| invalid(format!("{} must be an array of {} numbers", key, N)))?;
+    }
+    Ok(result)
+}
+
+// How the elements of an accessor are stored
+#[derive(Clone, Copy)]
+struct ElementFormat {
+    components: usize,
+    component_type: usize,
+    normalized: bool,
+}
+
+// Data of an accessor: `count` elements of `components` values each
+struct AccessorData {
+    components: usize,
+    values: Vec<f64>,
+}
+
+impl AccessorData {
+    fn count(&self) -> usize {
+        self.values.len() / self.components
+    }
+
+    fn element(&self, index: usize) -> &[f64] {
+        &self.values[index * self.components..(index + 1) * self.components]
+    }
+
+    fn vec4(&self, index: usize, default_w: f32) -> Vec4 {
+        let e = self.element(index);
+        let get = |i: usize, default: f32| e.get(i).map_or(default, |&v| v as f32);
+        Vec4::new(get(0, 0.0), get(1, 0.0), get(2, 0.0), get(3, default_w))
+    }
+}
+
+struct Importer<'a> {
+    document: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    base_dir: PathBuf,
+}
+
+impl<'a> Importer<'a> {
+    fn new(document: &'a Json, binary_chunk: Option<&[u8]>, base_dir: PathBuf) -> Result<Self, GltfErrorKind> {
+        let version = document.get("asset").and_then(|asset| asset.get("version")).and_then(Json::as_str);
+        if !version.is_some_and(|version| version.starts_with("2.")) {
+            return Err(GltfErrorKind::Unsupported(format!("asset version {}", version.unwrap_or("(missing)"))));
+        }
+        if let Some(required) = items(document, "extensionsRequired").first() {
+            return Err(GltfErrorKind::Unsupported(format!("required extension {}", required.as_str().unwrap_or("?"))));
+        }
+
+        let mut buffers = Vec::new();
+        for (index, buffer) in items(document, "buffers").iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) if uri.starts_with("data:") => {
+                    let (_, payload) = uri
+                        .split_once(";base64,")
+                        .ok_or_else(|| GltfErrorKind::Unsupported(format!("buffers[{}] data URI without base64", index)))?;
+                    decode_base64(payload).ok_or_else(|| invalid(format!("buffers[{}] has invalid base64 data", index)))?
+                }
+                Some(uri) => std::fs::read(base_dir.join(decode_uri(uri)))
+                    .map_err(|error| GltfErrorKind::Buffer { uri: uri.to_string(), error })?,
+                // Only the first buffer may refer to the GLB binary chunk
+                None if index == 0 => binary_chunk
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| invalid("buffers[0] has no uri and there is no GLB binary chunk".to_string()))?,
+                None => return Err(invalid(format!("buffers[{}] has no uri", index))),
+            };
+
+            let byte_length = buffer.get("byteLength").and_then(Json::as_usize).unwrap_or(0);
+            if data.len() < byte_length {
+                return Err(invalid(format!("buffers[{}] is shorter than its byteLength", index)));
+            }
+            buffers.push(data);
+        }
+
+        Ok(Importer { document, buffers, base_dir })
+    }
+
+    fn scene(&self) -> Result<Scene, GltfErrorKind> {
+        let nodes = self.nodes()?;
+        let roots = self.roots(&nodes)?;
+        let meshes = items(self.document, "meshes")
+            .iter()
+            .enumerate()
+            .map(|(index, mesh)| self.mesh(index, mesh))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(node) = nodes.iter().find(|node| node.mesh.is_some_and(|mesh| mesh >= meshes.len())) {
+            return Err(invalid(format!("node '{}' refers to a missing mesh", node.name)));
+        }
+        let animations = items(self.document, "animations")
+            .iter()
+            .enumerate()
+            .map(|(index, animation)| self.animation(index, animation, nodes.len()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Scene { meshes, nodes, roots, animations })
+    }
+
+    fn nodes(&self) -> Result<Vec<Node>, GltfErrorKind> {
+        let json_nodes = items(self.document, "nodes");
+        let mut nodes = Vec::with_capacity(json_nodes.len());
+
+        for (index, json) in json_nodes.iter().enumerate() {
+            let context = format!("nodes[{}]", index);
+            let children = match json.get("children") {
+                None => Vec::new(),
+                Some(children) => children
+                    .as_array()
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|child| child.as_usize().filter(|&child| child < json_nodes.len()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| invalid(format!("{}.children has an invalid node", context)))?,
+            };
+
+            let (translation, rotation, scale) = match json.get("matrix") {
+                Some(_) => {
+                    let matrix = Mat4::from_column_slice(&float_array::<16>(json, "matrix", [0.0; 16])?);
+                    decompose(&matrix)
+                }
+                None => {
+                    let [tx, ty, tz] = float_array(json, "translation", [0.0, 0.0, 0.0])?;
+                    let [x, y, z, w] = float_array(json, "rotation", [0.0, 0.0, 0.0, 1.0])?;
+                    let [sx, sy, sz] = float_array(json, "scale", [1.0, 1.0, 1.0])?;
+                    (Vec3::new(tx, ty, tz), glm::quat(x, y, z, w), Vec3::new(sx, sy, sz))
+                }
+            };
+
+            nodes.push(Node {
+                name: json.get("name").and_then(Json::as_str).unwrap_or_default().to_string(),
+                mesh: index_field(json, "mesh", &context)?,
+                children,
+                translation,
+                rotation,
+                scale,
+            });
+        }
+
+        Ok(nodes)
+    }
+
+    // Root nodes of the default scene (or of the first one, or every node
+    // without a parent when the file has no scenes). The hierarchy must be a
+    // forest, so walking it always terminates
+    fn roots(&self, nodes: &[Node]) -> Result<Vec<usize>, GltfErrorKind> {
+        let mut has_parent = vec![false; nodes.len()];
+        for child in nodes.iter().flat_map(|node| &node.children) {
+            if std::mem::replace(&mut has_parent[*child], true) {
+                return Err(invalid(format!("nodes[{}] has more than one parent", child)));
+            }
+        }
+
+        // With at most one parent each, nodes that can not be reached from a
+        // parentless one form a cycle
+        let mut reachable = 0;
+        let mut stack: Vec<usize> = (0..nodes.len()).filter(|&node| !has_parent[node]).collect();
+        while let Some(node) = stack.pop() {
+            reachable += 1;
+            stack.extend(&nodes[node].children);
+        }
+        if reachable != nodes.len() {
+            return Err(invalid("the node hierarchy has a cycle".to_string()));
+        }
+
+        let scenes = items(self.document, "scenes");
+        if scenes.is_empty() {
+            return Ok((0..nodes.len()).filter(|&node| !has_parent[node]).collect());
+        }
+        let scene = index_field(self.document, "scene", "document")?.unwrap_or(0);
+        let roots: Vec<usize> = item(self.document, "scenes", scene)?
+            .get("nodes")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .map(|node| node.as_usize().filter(|&node| node < nodes.len() && !has_parent[node]))
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid(format!("scenes[{}].nodes must be existing root nodes", scene)))?;
+        Ok(roots)
+    }
+
+    fn mesh(&self, index: usize, json: &Json) -> Result<Mesh, GltfErrorKind> {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut submeshes = Vec::new();
+        // Materials used by this mesh, with their index in the document
+        let mut materials: Vec<(usize, Material)> = Vec::new();
+        let mesh_name = json.get("name").and_then(Json::as_str).unwrap_or_default();
+
+        for (primitive_index, primitive) in items(json, "primitives").iter().enumerate() {
+            let context = format!("meshes[{}].primitives[{}]", index, primitive_index);
+            let mode = primitive.get("mode").and_then(Json::as_usize).unwrap_or(4);
+            if !(4..=6).contains(&mode) {
+                continue; // Points and lines have no area to rasterize
+            }
+
+            let material = match index_field(primitive, "material", &context)? {
+                Some(document_index) => Some(match materials.iter().position(|(i, _)| *i == document_index) {
+                    Some(local) => local,
+                    None => {
+                        materials.push((document_index, self.material(document_index)?));
+                        materials.len() - 1
+                    }
+                }),
+                None => None,
+            };
+            let base_color = material.map_or(Color::WHITE, |m| materials[m].1.diffuse_color());
+
+            let attributes = primitive.get("attributes").ok_or_else(|| invalid(format!("{} has no attributes", context)))?;
+            let attribute = |name: &str| -> Result<Option<AccessorData>, GltfErrorKind> {
+                index_field(attributes, name, &context)?.map(|accessor| self.accessor(accessor)).transpose()
+            };
+            let positions = attribute("POSITION")?.ok_or_else(|| invalid(format!("{} has no POSITION", context)))?;
+            let normals = attribute("NORMAL")?;
+            let tangents = attribute("TANGENT")?;
+            let tex_coords = attribute("TEXCOORD_0")?;
+            let colors = attribute("COLOR_0")?;
+            let count = positions.count();
+            for data in [&normals, &tangents, &tex_coords, &colors].into_iter().flatten() {
+                if data.count() != count {
+                    return Err(invalid(format!("{} has attributes of different lengths", context)));
+                }
+            }
+
+            let first_vertex = vertices.len() as u32;
+            for i in 0..count {
+                // Missing normals and tangents are left at zero, they can be generated afterwards
+                let position = positions.vec4(i, 0.0).xyz();
+                let normal = normals.as_ref().map_or(Vec3::zeros(), |n| n.vec4(i, 0.0).xyz());
+                let color = match &colors {
+                    Some(colors) => {
+                        let c = colors.vec4(i, 1.0);
+                        let scale = |channel: u8, factor: f32| (channel as f32 * factor.clamp(0.0, 1.0)).round() as u8;
+                        Color::new(scale(base_color.r, c.x), scale(base_color.g, c.y), scale(base_color.b, c.z), scale(base_color.a, c.w))
+                    }
+                    None => base_color,
+                };
+                let mut vertex = Vertex::new(position, normal, color);
+                if let Some(tangents) = &tangents {
+                    vertex.tangent = tangents.vec4(i, 1.0);
+                }
+                if let Some(tex_coords) = &tex_coords {
+                    let uv = tex_coords.vec4(i, 0.0);
+                    vertex.tex_coords = Vec2::new(uv.x, uv.y);
+                }
+                vertices.push(vertex);
+            }
+
+            let primitive_indices: Vec<u32> = match index_field(primitive, "indices", &context)? {
+                Some(accessor) => self.accessor(accessor)?.values.iter().map(|&i| i as u32).collect(),
+                None => (0..count as u32).collect(),
+            };
+            if primitive_indices.iter().any(|&i| i as usize >= count) {
+                return Err(invalid(format!("{} has an index out of range", context)));
+            }
+
+            let first_index = indices.len();
+            for triangle in triangle_list(&primitive_indices, mode) {
+                indices.extend(triangle.iter().map(|&i| first_vertex + i));
+            }
+            submeshes.push(SubMesh {
+                name: if mesh_name.is_empty() { context } else { format!("{}.{}", mesh_name, primitive_index) },
+                first_index,
+                index_count: indices.len() - first_index,
+                material,
+            });
+        }
+
+        let mut mesh = Mesh::new(vertices, indices);
+        mesh.submeshes = submeshes;
+        mesh.materials = materials.into_iter().map(|(_, material)| material).collect();
+        Ok(mesh)
+    }
+
+    fn material(&self, index: usize) -> Result<Material, GltfErrorKind> {
+        let json = item(self.document, "materials", index)?;
+        let name = json.get("name").and_then(Json::as_str).map_or_else(|| format!("materials[{}]", index), str::to_string);
+        let mut material = Material::new(&name);
+
+        // glTF defaults: white base color, fully metallic and rough
+        let pbr = json.get("pbrMetallicRoughness").unwrap_or(&Json::Null);
+        let [r, g, b, a] = float_array(pbr, "baseColorFactor", [1.0, 1.0, 1.0, 1.0])?;
+        material.diffuse = Vec3::new(r, g, b);
+        material.opacity = a;
+        material.metallic = pbr.get("metallicFactor").and_then(Json::as_f32).unwrap_or(1.0);
+        material.roughness = pbr.get("roughnessFactor").and_then(Json::as_f32).unwrap_or(1.0);
+        let [er, eg, eb] = float_array(json, "emissiveFactor", [0.0, 0.0, 0.0])?;
+        material.emissive = Vec3::new(er, eg, eb);
+
+        material.diffuse_map = self.texture_path(pbr.get("baseColorTexture"))?;
+        material.bump_map = self.texture_path(json.get("normalTexture"))?;
+        Ok(material)
+    }
+
+    // File of a texture reference; images embedded in buffers have none
+    fn texture_path(&self, texture_info: Option<&Json>) -> Result<Option<PathBuf>, GltfErrorKind> {
+        let Some(texture) = texture_info.and_then(|info| info.get("index")).and_then(Json::as_usize) else {
+            return Ok(None);
+        };
+        let Some(source) = item(self.document, "textures", texture)?.get("source").and_then(Json::as_usize) else {
+            return Ok(None);
+        };
+        let uri = item(self.document, "images", source)?.get("uri").and_then(Json::as_str);
+        Ok(uri.filter(|uri| !uri.starts_with("data:")).map(|uri| self.base_dir.join(decode_uri(uri))))
+    }
+
+    fn animation(&self, index: usize, json: &Json, node_count: usize) -> Result<Animation, GltfErrorKind> {
+        let samplers = items(json, "samplers");
+        let mut channels = Vec::new();
+
+        for (channel_index, channel) in items(json, "channels").iter().enumerate() {
+            let context = format!("animations[{}].channels[{}]", index, channel_index);
+            let target = channel.get("target").ok_or_else(|| invalid(format!("{} has no target", context)))?;
+            let property = match target.get("path").and_then(Json::as_str) {
+                Some("translation") => AnimatedProperty::Translation,
+                Some("rotation") => AnimatedProperty::Rotation,
+                Some("scale") => AnimatedProperty::Scale,
+                _ => continue, // Morph target weights and extension paths
+            };
+            let Some(node) = index_field(target, "node", &context)? else {
+                continue;
+            };
+            if node >= node_count {
+                return Err(invalid(format!("{} targets a missing node", context)));
+            }
+
+            let sampler = index_field(channel, "sampler", &context)?
+                .and_then(|sampler| samplers.get(sampler))
+                .ok_or_else(|| invalid(format!("{} has an invalid sampler", context)))?;
+            let interpolation = match sampler.get("interpolation").and_then(Json::as_str).unwrap_or("LINEAR") {
+                "STEP" => KeyframeInterpolation::Step,
+                "LINEAR" => KeyframeInterpolation::Linear,
+                "CUBICSPLINE" => KeyframeInterpolation::CubicSpline,
+                other => return Err(GltfErrorKind::Unsupported(format!("{} interpolation", other))),
+            };
+            let input = index_field(sampler, "input", &context)?.ok_or_else(|| invalid(format!("{} sampler has no input", context)))?;
+            let output = index_field(sampler, "output", &context)?.ok_or_else(|| invalid(format!("{} sampler has no output", context)))?;
+
+            let times: Vec<f32> = self.accessor(input)?.values.iter().map(|&t| t as f32).collect();
+            let output = self.accessor(output)?;
+            let values: Vec<Vec4> = (0..output.count()).map(|i| output.vec4(i, 0.0)).collect();
+            let values_per_key = if interpolation == KeyframeInterpolation::CubicSpline { 3 } else { 1 };
+            if times.is_empty() || values.len() != times.len() * values_per_key || times.windows(2).any(|w| w[0] > w[1]) {
+                return Err(invalid(format!("{} has mismatched or unsorted keyframes", context)));
+            }
+
+            channels.push(Channel { node, property, interpolation, times, values });
+        }
+
+        Ok(Animation {
+            name: json.get("name").and_then(Json::as_str).unwrap_or_default().to_string(),
+            duration: channels.iter().filter_map(|channel| channel.times.last().copied()).fold(0.0, f32::max),
+            channels,
+        })
+    }
+
+    // Reads an accessor as floats, applying the normalization of integer
+    // components and sparse substitution
+    fn accessor(&self, index: usize) -> Result<AccessorData, GltfErrorKind> {
+        let json = item(self.document, "accessors", index)?;
+        let context = format!("accessors[{}]", index);
+        let count = json.get("count").and_then(Json::as_usize).ok_or_else(|| invalid(format!("{} has no count", context)))?;
+        let component_type = json.get("componentType").and_then(Json::as_usize).unwrap_or(0);
+        let normalized = json.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+        let components = match json.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            other => return Err(GltfErrorKind::Unsupported(format!("{} type {}", context, other.unwrap_or("(missing)")))),
+        };
+
+        let format = ElementFormat { components, component_type, normalized };
+
+        let mut values = match index_field(json, "bufferView", &context)? {
+            Some(view) => {
+                let offset = json.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+                self.read_view(view, offset, false, count, format)?
+            }
+            // Only sparse values, or all zeros. Nothing in the file backs the
+            // count, so it is capped before allocating
+            None => {
+                let len = count.checked_mul(components).filter(|&len| len <= MAX_UNBACKED_VALUES);
+                vec![0.0; len.ok_or_else(|| invalid(format!("{} has too many elements without a bufferView", context)))?]
+            }
+        };
+
+        if let Some(sparse) = json.get("sparse") {
+            let sparse_count = sparse.get("count").and_then(Json::as_usize).unwrap_or(0);
+            let part = |key: &str| sparse.get(key).ok_or_else(|| invalid(format!("{}.sparse has no {}", context, key)));
+            let (sparse_indices, sparse_values) = (part("indices")?, part("values")?);
+            let read = |part: &Json, format: ElementFormat| {
+                let view = index_field(part, "bufferView", &context)?.ok_or_else(|| invalid(format!("{}.sparse has no bufferView", context)))?;
+                let offset = part.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+                self.read_view(view, offset, true, sparse_count, format)
+            };
+            let index_type = sparse_indices.get("componentType").and_then(Json::as_usize).unwrap_or(0);
+            let targets = read(sparse_indices, ElementFormat { components: 1, component_type: index_type, normalized: false })?;
+            let replacements = read(sparse_values, format)?;
+
+            for (i, &target) in targets.iter().enumerate() {
+                let target = target as usize;
+                if target >= count {
+                    return Err(invalid(format!("{}.sparse has an index out of range", context)));
+                }
+                values[target * components..(target + 1) * components].copy_from_slice(&replacements[i * components..(i + 1) * components]);
+            }
+        }
+
+        Ok(AccessorData { components, values })
+    }
+
+    // Reads `count` elements from a buffer view. `packed` ignores the view's
+    // byteStride, as sparse data is always tightly packed
+    fn read_view(&self, view: usize, offset: usize, packed: bool, count: usize, format: ElementFormat) -> Result<Vec<f64>, GltfErrorKind> {
+        let ElementFormat { components, component_type, normalized } = format;
+        let context = format!("bufferViews[{}]", view);
+        let json = item(self.document, "bufferViews", view)?;
+        let buffer = index_field(json, "buffer", &context)?
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| invalid(format!("{} has an invalid buffer", context)))?;
+        let view_offset = json.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let view_length = json.get("byteLength").and_then(Json::as_usize).unwrap_or(0);
+        let view_bytes = view_offset
+            .checked_add(view_length)
+            .and_then(|view_end| buffer.get(view_offset..view_end))
+            .ok_or_else(|| invalid(format!("{} is outside its buffer", context)))?;
+
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(invalid(format!("{} has unknown component type {}", context, component_type))),
+        };
+        let element_size = component_size * components;
+        let stride = match packed {
+            true => element_size,
+            false => json.get("byteStride").and_then(Json::as_usize).unwrap_or(element_size).max(element_size),
+        };
+        if count > 0 {
+            let end = stride.checked_mul(count - 1).and_then(|end| end.checked_add(offset)).and_then(|end| end.checked_add(element_size));
+            if end.is_none_or(|end| end > view_bytes.len()) {
+                return Err(invalid(format!("accessor reads past the end of {}", context)));
+            }
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * component_size;
+                let b = &view_bytes[start..start + component_size];
+                let value = match component_type {
+                    5120 => {
+                        let v = b[0] as i8 as f64;
+                        if normalized { (v / 127.0).max(-1.0) } else { v }
+                    }
+                    5121 => {
+                        let v = b[0] as f64;
+                        if normalized { v / 255.0 } else { v }
+                    }
+                    5122 => {
+                        let v = i16::from_le_bytes([b[0], b[1]]) as f64;
+                        if normalized { (v / 32767.0).max(-1.0) } else { v }
+                    }
+                    5123 => {
+                        let v = u16::from_le_bytes([b[0], b[1]]) as f64;
+                        if normalized { v / 65535.0 } else { v }
+                    }
+                    5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                };
+                values.push(value);
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+// Converts triangle lists (4), strips (5) and fans (6) to a list, keeping
+// every triangle's winding
+fn triangle_list(indices: &[u32], mode: usize) -> Vec<[u32; 3]> {
+    match mode {
+        5 => (0..indices.len().saturating_sub(2))
+            .map(|i| if i % 2 == 0 { [indices[i], indices[i + 1], indices[i + 2]] } else { [indices[i + 1], indices[i], indices[i + 2]] })
+            .collect(),
+        6 => (1..indices.len().saturating_sub(1)).map(|i| [indices[0], indices[i], indices[i + 1]]).collect(),
+        _ => indices.chunks_exact(3).map(|tri| [tri[0], tri[1], tri[2]]).collect(),
+    }
+}
+
+// Splits a node matrix into translation, rotation and scale
+fn decompose(matrix: &Mat4) -> (Vec3, Quat, Vec3) {
+    let translation = Vec3::new(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+    let mut linear: Mat3 = glm::mat4_to_mat3(matrix);
+    let mut scale = Vec3::new(linear.column(0).norm(), linear.column(1).norm(), linear.column(2).norm());
+    if linear.determinant() < 0.0 {
+        scale.x = -scale.x; // A mirroring is kept as a negative scale
+    }
+    for axis in 0..3 {
+        if scale[axis] != 0.0 {
+            linear.set_column(axis, &(linear.column(axis) / scale[axis]));
+        }
+    }
+    (translation, glm::mat3_to_quat(&linear), scale)
+}
+
+// Spherical interpolation of quaternions stored as (x, y, z, w), along the
+// shortest arc
+fn slerp(a: Vec4, b: Vec4, t: f32) -> Vec4 {
+    let mut dot = a.dot(&b);
+    let b = if dot < 0.0 {
+        dot = -dot;
+        -b
+    } else {
+        b
+    };
+    if dot > 0.9995 {
+        return a.lerp(&b, t).normalize(); // Nearly parallel, avoid dividing by ~0
+    }
+    let angle = dot.acos();
+    (a * ((1.0 - t) * angle).sin() + b * (t * angle).sin()) / angle.sin()
+}
+
+// Undoes percent-encoding in relative URIs ("my%20model.bin")
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Standard base64 (RFC 4648), padding optional. None on invalid characters
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let sextet = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for &c in text {
+        bits = (bits << 6) | sextet(c)? as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+            for i in 0..=chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            }
+        }
+        text
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    // A document with one embedded buffer; `rest` holds the other top-level members
+    fn document(buffer: &[u8], rest: &str) -> String {
+        format!(
+            r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}], {}}}"#,
+            buffer.len(),
+            encode_base64(buffer),
+            rest
+        )
+    }
+
+    fn accessor(source: &str, index: usize) -> Result<AccessorData, GltfErrorKind> {
+        let json = parse_json(source).unwrap();
+        Importer::new(&json, None, PathBuf::new())?.accessor(index)
+    }
+
+    #[test]
+    fn decodes_base64() {
+        for text in ["", "a", "ab", "abc", "abcd", "glTF binary"] {
+            assert_eq!(decode_base64(&encode_base64(text.as_bytes())).as_deref(), Some(text.as_bytes()));
+        }
+        assert_eq!(decode_base64("Z2xURg=="), Some(b"glTF".to_vec()));
+    }
+
+    #[test]
+    fn loads_embedded_triangle() {
+        let mut buffer = floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        buffer.extend([0u16, 1, 2].iter().flat_map(|index| index.to_le_bytes()));
+        let source = document(
+            &buffer,
+            r#""bufferViews": [{"buffer": 0, "byteLength": 36}, {"buffer": 0, "byteOffset": 36, "byteLength": 6}],
+               "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+                             {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}],
+               "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+               "nodes": [{"mesh": 0}],
+               "scenes": [{"nodes": [0]}]"#,
+        );
+
+        let scene = parse_gltf(source.as_bytes(), "triangle.gltf").unwrap();
+        assert_eq!(scene.meshes.len(), 1);
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        let positions: Vec<Vec3> = mesh.vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, [Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]);
+        assert_eq!(scene.roots, [0]);
+    }
+
+    #[test]
+    fn applies_sparse_values() {
+        // Index 1 (one byte, padded to 4) followed by its replacement
+        let mut buffer = vec![1, 0, 0, 0];
+        buffer.extend(floats(&[4.0, 5.0, 6.0]));
+        let source = document(
+            &buffer,
+            r#""bufferViews": [{"buffer": 0, "byteLength": 1}, {"buffer": 0, "byteOffset": 4, "byteLength": 12}],
+               "accessors": [{"componentType": 5126, "count": 3, "type": "VEC3",
+                              "sparse": {"count": 1, "indices": {"bufferView": 0, "componentType": 5121},
+                                         "values": {"bufferView": 1}}}]"#,
+        );
+
+        let data = accessor(&source, 0).unwrap();
+        assert_eq!(data.components, 3);
+        assert_eq!(data.values, [0.0, 0.0, 0.0, 4.0, 5.0, 6.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_sparse_index_out_of_range() {
+        let mut buffer = vec![3, 0, 0, 0];
+        buffer.extend(floats(&[1.0]));
+        let source = document(
+            &buffer,
+            r#""bufferViews": [{"buffer": 0, "byteLength": 1}, {"buffer": 0, "byteOffset": 4, "byteLength": 4}],
+               "accessors": [{"componentType": 5126, "count": 3, "type": "SCALAR",
+                              "sparse": {"count": 1, "indices": {"bufferView": 0, "componentType": 5121},
+                                         "values": {"bufferView": 1}}}]"#,
+        );
+        assert!(matches!(accessor(&source, 0), Err(GltfErrorKind::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_huge_offsets_and_counts() {
+        let buffer = floats(&[1.0, 2.0, 3.0]);
+        let views = r#""bufferViews": [{"buffer": 0, "byteLength": 12}, {"buffer": 0, "byteOffset": 1e30, "byteLength": 1e30}]"#;
+        for accessors in [
+            r#"{"bufferView": 1, "componentType": 5126, "count": 1, "type": "SCALAR"}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 1e30, "type": "SCALAR"}"#,
+            r#"{"bufferView": 0, "byteOffset": 1e30, "componentType": 5126, "count": 2, "type": "SCALAR"}"#,
+            r#"{"bufferView": 0, "componentType": 5126, "count": 4, "type": "SCALAR"}"#,
+            r#"{"componentType": 5126, "count": 1e30, "type": "VEC4"}"#,
+        ] {
+            let source = document(&buffer, &format!(r#"{}, "accessors": [{}]"#, views, accessors));
+            assert!(matches!(accessor(&source, 0), Err(GltfErrorKind::Invalid(_))), "{}", accessors);
+        }
+    }
+
+    #[test]
+    fn keeps_strip_and_fan_winding() {
+        assert_eq!(triangle_list(&[0, 1, 2, 3, 4], 5), [[0, 1, 2], [2, 1, 3], [2, 3, 4]]);
+        assert_eq!(triangle_list(&[0, 1, 2, 3], 6), [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(triangle_list(&[0, 1, 2, 3, 4, 5, 6], 4), [[0, 1, 2], [3, 4, 5]]);
+        assert!(triangle_list(&[0, 1], 5).is_empty());
+    }
+
+    // Triangle positions and indices, and the document using them from buffer 0
+    fn triangle_buffer() -> Vec<u8> {
+        let mut buffer = floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        buffer.extend([0u16, 1, 2].iter().flat_map(|index| index.to_le_bytes()));
+        buffer
+    }
+
+    const TRIANGLE_MESH: &str = r#""bufferViews": [{"buffer": 0, "byteLength": 36}, {"buffer": 0, "byteOffset": 36, "byteLength": 6}],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+                      {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}]"#;
+
+    // GLB container: header, then each chunk (type, data) padded to 4 bytes
+    fn glb(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (kind, data) in chunks {
+            let padding = if kind == &b"JSON" { b' ' } else { 0 };
+            let padded = data.len().div_ceil(4) * 4;
+            body.extend_from_slice(&(padded as u32).to_le_bytes());
+            body.extend_from_slice(*kind);
+            body.extend_from_slice(data);
+            body.resize(body.len() + padded - data.len(), padding);
+        }
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(12 + body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn reads_glb_containers() {
+        let buffer = triangle_buffer();
+        let json = format!(r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": {}}}], {}, "nodes": [{{"mesh": 0}}]}}"#, buffer.len(), TRIANGLE_MESH);
+        // Chunks of unknown types are skipped
+        let bytes = glb(&[(b"JSON", json.as_bytes()), (b"XTRA", b"ignored"), (b"BIN\0", &buffer)]);
+        let scene = parse_gltf(&bytes, "triangle.glb").unwrap();
+        assert_eq!(scene.meshes[0].indices, [0, 1, 2]);
+        assert_eq!(scene.meshes[0].vertices[2].position, Vec3::z());
+
+        let glb_error = |bytes: &[u8]| match parse_gltf(bytes, "bad.glb") {
+            Err(GltfError { kind: GltfErrorKind::InvalidGlb(message), .. }) => message,
+            other => panic!("expected an invalid GLB, got {:?}", other.map(|scene| scene.meshes.len())),
+        };
+        let mut longer = bytes.clone();
+        longer[8] += 4;
+        assert_eq!(glb_error(&longer), "file shorter than its header says");
+        let mut version = bytes.clone();
+        version[4] = 1;
+        assert_eq!(glb_error(&version), "only version 2 is supported");
+        let mut cut_chunk = bytes.clone();
+        cut_chunk.truncate(bytes.len() - 4);
+        let length = cut_chunk.len() as u32;
+        cut_chunk[8..12].copy_from_slice(&length.to_le_bytes());
+        assert_eq!(glb_error(&cut_chunk), "truncated chunk");
+        assert_eq!(glb_error(&glb(&[(b"BIN\0", &buffer)])), "missing JSON chunk");
+
+        // Without the glTF magic the bytes are not taken for a container
+        let mut magic = bytes.clone();
+        magic[3] = b'X';
+        assert!(parse_gltf(&magic, "bad.glb").is_err());
+
+        // buffers[0] without a uri needs the BIN chunk
+        let missing = parse_gltf(&glb(&[(b"JSON", json.as_bytes())]), "bad.glb");
+        assert!(matches!(missing, Err(GltfError { kind: GltfErrorKind::Invalid(_), .. })));
+    }
+
+    #[test]
+    fn composes_node_transforms() {
+        // The parent turns a quarter around z, scales by 2 and moves to x = 1;
+        // the child stretches y by 3 and moves up by 1 inside it
+        let (sin, cos) = std::f32::consts::FRAC_PI_4.sin_cos();
+        let nodes = format!(
+            r#""nodes": [{{"children": [1], "translation": [1, 0, 0], "rotation": [0, 0, {}, {}], "scale": [2, 2, 2]}},
+                         {{"mesh": 0, "translation": [0, 1, 0], "scale": [1, 3, 1]}}],
+               "scenes": [{{"nodes": [0]}}]"#,
+            sin, cos
+        );
+        let source = document(&triangle_buffer(), &format!("{}, {}", TRIANGLE_MESH, nodes));
+        let scene = parse_gltf(source.as_bytes(), "nodes.gltf").unwrap();
+        let instances = scene.instances();
+        assert_eq!(instances.len(), 1);
+        assert_eq!((instances[0].node, instances[0].mesh), (1, 0));
+
+        let world: Vec<Vec3> = scene.meshes[0]
+            .vertices
+            .iter()
+            .map(|vertex| (instances[0].model_matrix * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0)).xyz())
+            .collect();
+        for (position, expected) in world.iter().zip([Vec3::new(-1.0, 2.0, 0.0), Vec3::new(-7.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 2.0)]) {
+            assert!((position - expected).norm() < 1e-5, "{:?} instead of {:?}", position, expected);
+        }
+    }
+
+    fn channel(property: AnimatedProperty, interpolation: KeyframeInterpolation, times: &[f32], values: &[[f32; 4]]) -> Channel {
+        let values = values.iter().map(|&[x, y, z, w]| Vec4::new(x, y, z, w)).collect();
+        Channel { node: 0, property, interpolation, times: times.to_vec(), values }
+    }
+
+    fn close(a: Vec4, b: Vec4) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    // Rotation by `angle` around z, as (x, y, z, w)
+    fn about_z(angle: f32) -> [f32; 4] {
+        [0.0, 0.0, (angle / 2.0).sin(), (angle / 2.0).cos()]
+    }
+
+    #[test]
+    fn samples_linear_and_step_keyframes() {
+        let linear = channel(AnimatedProperty::Translation, KeyframeInterpolation::Linear, &[1.0, 3.0], &[[0.0; 4], [4.0, 8.0, 0.0, 0.0]]);
+        assert!(close(linear.sample(1.5), Vec4::new(1.0, 2.0, 0.0, 0.0)));
+        // The first and last keyframes hold outside them
+        assert!(close(linear.sample(0.0), Vec4::zeros()));
+        assert!(close(linear.sample(9.0), Vec4::new(4.0, 8.0, 0.0, 0.0)));
+
+        let step = channel(AnimatedProperty::Scale, KeyframeInterpolation::Step, &[0.0, 1.0, 2.0], &[[1.0; 4], [2.0; 4], [3.0; 4]]);
+        assert_eq!(step.sample(0.99), Vec4::repeat(1.0));
+        assert_eq!(step.sample(1.0), Vec4::repeat(2.0));
+        assert_eq!(step.sample(1.5), Vec4::repeat(2.0));
+
+        let quarter = std::f32::consts::FRAC_PI_2;
+        let rotation = channel(AnimatedProperty::Rotation, KeyframeInterpolation::Linear, &[0.0, 1.0], &[about_z(0.0), about_z(quarter)]);
+        let halfway = rotation.sample(0.5);
+        assert!(close(halfway, Vec4::from(about_z(quarter / 2.0))), "{:?}", halfway);
+    }
+
+    #[test]
+    fn samples_cubic_splines() {
+        // In-tangent, value and out-tangent per key, tangents per second
+        let spline = |tangent: f32| {
+            channel(
+                AnimatedProperty::Translation,
+                KeyframeInterpolation::CubicSpline,
+                &[0.0, 2.0],
+                &[[0.0; 4], [0.0; 4], [tangent, 0.0, 0.0, 0.0], [tangent, 0.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0], [0.0; 4]],
+            )
+        };
+        // Flat tangents ease in and out: 3t^2 - 2t^3 at a quarter of the way
+        assert!(close(spline(0.0).sample(0.5), Vec4::new(0.15625, 0.0, 0.0, 0.0)));
+        // Tangents matching the slope of the straight line give the line
+        assert!(close(spline(0.5).sample(0.5), Vec4::new(0.25, 0.0, 0.0, 0.0)));
+        assert!(close(spline(0.5).sample(2.0), Vec4::new(1.0, 0.0, 0.0, 0.0)));
+
+        // Rotations stay unit quaternions
+        let quarter = std::f32::consts::FRAC_PI_2;
+        let rotation = channel(AnimatedProperty::Rotation, KeyframeInterpolation::CubicSpline, &[0.0, 1.0], &[[0.0; 4], about_z(0.0), [0.0; 4], [0.0; 4], about_z(quarter), [0.0; 4]]);
+        assert!((rotation.sample(0.3).norm() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn slerps_along_the_shortest_arc() {
+        let quarter = std::f32::consts::FRAC_PI_2;
+        let identity = Vec4::from(about_z(0.0));
+        let turned = Vec4::from(about_z(quarter));
+        // -q is the same rotation as q, but a quarter turn the other way round
+        // would pass through three eighths of a turn
+        for target in [turned, -turned] {
+            let halfway = slerp(identity, target, 0.5);
+            assert!(close(halfway, Vec4::from(about_z(quarter / 2.0))), "{:?}", halfway);
+        }
+        assert!(close(slerp(identity, -turned, 1.0), turned));
+        // Nearly equal rotations fall back to a normalized lerp
+        let nearby = Vec4::from(about_z(0.001));
+        assert!((slerp(identity, nearby, 0.5).norm() - 1.0).abs() < 1e-6);
+    }
+}
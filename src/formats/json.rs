@@ -0,0 +1,365 @@
+// formats/json.rs
+// Minimal JSON reader, enough for glTF documents. Objects keep their keys in
+// file order; lookups are linear, which is fine for the small objects glTF uses.
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    pub offset: usize, // Byte offset in the source
+    pub message: &'static str,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON at byte {}: {}", self.offset, self.message)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl Json {
+    // Value of a key, None for missing keys and for non-objects
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        self.as_f64().map(|value| value as f32)
+    }
+
+    // Non-negative integers only, as used for indices and counts
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64().filter(|value| *value >= 0.0 && value.fract() == 0.0).map(|value| value as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_json(source: &str) -> Result<Json, JsonError> {
+    let mut parser = Parser { bytes: source.as_bytes(), position: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.position != parser.bytes.len() {
+        return Err(parser.error("unexpected data after the document"));
+    }
+    Ok(value)
+}
+
+// Nesting limit, so a malicious file cannot overflow the stack
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> JsonError {
+        JsonError { offset: self.position, message }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.bytes.get(self.position), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8, message: &'static str) -> Result<(), JsonError> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(message));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, text: &str, value: Json) -> Result<Json, JsonError> {
+        if !self.bytes[self.position..].starts_with(text.as_bytes()) {
+            return Err(self.error("invalid literal"));
+        }
+        self.position += text.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+
+        match self.peek() {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of the document")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json, JsonError> {
+        self.position += 1; // {
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.expect(b':', "expected ':'")?;
+            members.push((key, self.value(depth + 1)?));
+
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json, JsonError> {
+        self.position += 1; // [
+        let mut values = Vec::new();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value(depth + 1)?);
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    // -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?, which is stricter than
+    // what str::parse accepts (it takes "1." and "01")
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.position;
+        let error = JsonError { offset: start, message: "invalid number" };
+        self.skip(b"-");
+        match self.bytes.get(self.position) {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(error),
+        }
+        if self.skip(b".") {
+            if !self.bytes.get(self.position).is_some_and(u8::is_ascii_digit) {
+                return Err(error);
+            }
+            self.digits();
+        }
+        if self.skip(b"eE") {
+            self.skip(b"+-");
+            if !self.bytes.get(self.position).is_some_and(u8::is_ascii_digit) {
+                return Err(error);
+            }
+            self.digits();
+        }
+        if self.bytes.get(self.position).is_some_and(|byte| byte.is_ascii_digit() || b"+-.eE".contains(byte)) {
+            return Err(error); // Such as the 1 in "01"
+        }
+
+        // The source is a &str and the bytes taken are ASCII, so this cannot fail
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap_or("");
+        text.parse().map(Json::Number).map_err(|_| error)
+    }
+
+    // Moves past one byte if it is one of `bytes`
+    fn skip(&mut self, bytes: &[u8]) -> bool {
+        let found = self.bytes.get(self.position).is_some_and(|byte| bytes.contains(byte));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn digits(&mut self) {
+        while self.bytes.get(self.position).is_some_and(u8::is_ascii_digit) {
+            self.position += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.position += 1; // "
+        let mut bytes = Vec::new();
+
+        loop {
+            let Some(&byte) = self.bytes.get(self.position) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.position) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.position += 1;
+                    let decoded = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    bytes.extend_from_slice(decoded.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0..=0x1F => return Err(self.error("control character in string")),
+                _ => bytes.push(byte),
+            }
+        }
+
+        // Only whole UTF-8 sequences from the source and encoded chars were copied
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+
+    // \uXXXX, combining surrogate pairs into one character
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.bytes[self.position..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.position += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.bytes.get(self.position..self.position + 4).ok_or_else(|| self.error("invalid unicode escape"))?;
+        // Only hex digits, so a sign as in "\u+123" is rejected
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(self.error("invalid unicode escape"));
+        }
+        let value = digits.iter().fold(0, |value, &digit| (value << 4) | (digit as char).to_digit(16).unwrap_or(0));
+        self.position += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> &'static str {
+        parse_json(source).expect_err(source).message
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        let json = parse_json(r#""\"\\\/\b\f\n\r\té😀""#).unwrap();
+        assert_eq!(json.as_str(), Some("\"\\/\u{8}\u{c}\n\r\t\u{e9}\u{1f600}"));
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert_eq!(error(r#""\x""#), "invalid escape");
+        assert_eq!(error(r#""\u12""#), "invalid unicode escape");
+        assert_eq!(error(r#""\u+123""#), "invalid unicode escape");
+        assert_eq!(error(r#""\ud83d""#), "unpaired surrogate");
+        assert_eq!(error(r#""\ud83dA""#), "unpaired surrogate");
+        assert_eq!(error(r#""\ude00""#), "invalid unicode escape");
+        assert_eq!(error("\"a\nb\""), "control character in string");
+    }
+
+    #[test]
+    fn parses_numbers() {
+        for (source, value) in [("0", 0.0), ("-0", 0.0), ("12", 12.0), ("-1.5", -1.5), ("0.25", 0.25), ("1e3", 1000.0), ("2E-2", 0.02), ("-3.5e+1", -35.0)] {
+            assert_eq!(parse_json(source).unwrap().as_f64(), Some(value), "{}", source);
+        }
+        assert_eq!(parse_json("[1,-2]").unwrap().as_array().map(<[Json]>::len), Some(2));
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        for source in ["1.", "-01", "00", "-", "1e", "1e+", "1.e3", "--1", "1.5.2"] {
+            assert_eq!(error(source), "invalid number", "{}", source);
+        }
+        assert_eq!(error("+1"), "expected a value");
+        assert_eq!(error(".5"), "expected a value");
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse_json(&nested(MAX_DEPTH + 1)).is_ok());
+        assert_eq!(error(&nested(MAX_DEPTH + 2)), "nested too deeply");
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        assert_eq!(error("{} {}"), "unexpected data after the document");
+    }
+}
@@ -0,0 +1,7 @@
+pub mod obj;
+pub mod mtl;
+pub mod json;
+pub mod gltf;
+pub mod ply;
+pub mod stl;
+pub mod cache;
@@ -0,0 +1,161 @@
+// formats/mtl.rs
+// Wavefront MTL material libraries, as referenced by OBJ files through mtllib.
+// Reads the colors (Kd, Ks, Ke), the specular exponent (Ns), the opacity (d or
+// Tr), the PBR extension factors (Pm, Pr) and the diffuse and bump maps; other
+// statements are ignored.
+use super::obj::{missing, parse_float, parse_floats, statements, ObjError, ObjErrorKind};
+use crate::mesh::Material;
+use nalgebra_glm::Vec3;
+use std::path::{Path, PathBuf};
+
+// Loads every material of an MTL file. Texture paths are resolved relative to it
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<Vec<Material>, ObjError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path).map_err(|err| ObjError { file: file.clone(), line: 0, kind: ObjErrorKind::Io(err) })?;
+    parse_mtl(&source, &file)
+}
+
+// Parses MTL source text. `file` is used in error messages and to resolve texture paths
+pub fn parse_mtl(source: &str, file: &str) -> Result<Vec<Material>, ObjError> {
+    let base_dir = Path::new(file).parent().unwrap_or(Path::new(""));
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (line, statement) in statements(source) {
+        parse_statement(&statement, base_dir, &mut materials).map_err(|kind| ObjError { file: file.to_string(), line, kind })?;
+    }
+
+    Ok(materials)
+}
+
+fn parse_statement(line: &str, base_dir: &Path, materials: &mut Vec<Material>) -> Result<(), ObjErrorKind> {
+    let line = line.split('#').next().unwrap_or("");
+    let mut parts = line.split_whitespace();
+    let Some(keyword) = parts.next() else {
+        return Ok(());
+    };
+
+    if keyword == "newmtl" {
+        let name = parts.collect::<Vec<_>>().join(" ");
+        materials.push(Material::new(&name));
+        return Ok(());
+    }
+    // Statements before the first newmtl have nothing to apply to
+    let Some(material) = materials.last_mut() else {
+        return Ok(());
+    };
+
+    match keyword {
+        "Kd" => material.diffuse = parse_color(keyword, &mut parts)?,
+        "Ks" => material.specular = parse_color(keyword, &mut parts)?,
+        "Ke" => material.emissive = parse_color(keyword, &mut parts)?,
+        "Ns" => material.shininess = parse_float(parts.next().ok_or_else(|| missing(keyword, 1))?)?,
+        "d" => material.opacity = parse_float(parts.next().ok_or_else(|| missing(keyword, 1))?)?,
+        "Tr" => material.opacity = 1.0 - parse_float(parts.next().ok_or_else(|| missing(keyword, 1))?)?,
+        "Pm" => material.metallic = parse_float(parts.next().ok_or_else(|| missing(keyword, 1))?)?,
+        "Pr" => material.roughness = parse_float(parts.next().ok_or_else(|| missing(keyword, 1))?)?,
+        "map_Kd" => material.diffuse_map = Some(parse_map(keyword, parts, base_dir)?),
+        "map_Bump" | "map_bump" | "bump" => material.bump_map = Some(parse_map(keyword, parts, base_dir)?),
+        _ => {} // Ignore other statements (Ka, illum, Ni, other maps...)
+    }
+
+    Ok(())
+}
+
+// Colors have one value (gray) or three (RGB)
+fn parse_color<'a>(keyword: &str, parts: &mut impl Iterator<Item = &'a str>) -> Result<Vec3, ObjErrorKind> {
+    let values: Vec<&str> = parts.collect();
+    match values.len() {
+        0 => Err(missing(keyword, 1)),
+        1 | 2 => {
+            let gray = parse_float(values[0])?;
+            Ok(Vec3::new(gray, gray, gray))
+        }
+        _ => {
+            let [r, g, b] = parse_floats(keyword, &mut values.into_iter())?;
+            Ok(Vec3::new(r, g, b))
+        }
+    }
+}
+
+// Texture statements may start with options (-bm 1.0, -s 2 2 1, -clamp on...);
+// the file name is what follows the last option
+fn parse_map<'a>(keyword: &str, parts: impl Iterator<Item = &'a str>, base_dir: &Path) -> Result<PathBuf, ObjErrorKind> {
+    let values: Vec<&str> = parts.collect();
+    let mut first = 0;
+    while first < values.len() && values[first].starts_with('-') {
+        first += 1 + option_arguments(values[first], &values[first + 1..]);
+    }
+
+    if first >= values.len() {
+        return Err(missing(keyword, 1));
+    }
+    Ok(base_dir.join(values[first..].join(" ")))
+}
+
+// Number of values a texture option takes from the ones following it
+fn option_arguments(option: &str, following: &[&str]) -> usize {
+    match option {
+        // Offset, scale and turbulence take u and optionally v and w
+        "-o" | "-s" | "-t" => following.iter().take(3).take_while(|value| value.parse::<f32>().is_ok()).count(),
+        "-mm" => 2,
+        _ => 1, // -bm, -blendu, -blendv, -boost, -cc, -clamp, -imfchan, -texres
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Vec<Material> {
+        parse_mtl(source, "models/test.mtl").unwrap()
+    }
+
+    #[test]
+    fn reads_colors_and_factors() {
+        let materials = parse("Kd 0.1 0.2 0.3\nnewmtl brick\nKd 0.8 0.3 0.2\nKs 0.5\nKe 0 0 1\nNs 96\nd 0.75\nillum 2\n\nnewmtl glass\nTr 0.9\nPm 1\nPr 0.25\n");
+        assert_eq!(materials.len(), 2); // Kd before newmtl applies to nothing
+
+        let brick = &materials[0];
+        assert_eq!(brick.name, "brick");
+        assert_eq!((brick.diffuse, brick.specular, brick.emissive), (Vec3::new(0.8, 0.3, 0.2), Vec3::repeat(0.5), Vec3::z()));
+        assert_eq!((brick.shininess, brick.opacity), (96.0, 0.75));
+
+        let glass = &materials[1];
+        assert_eq!(glass.diffuse, Vec3::repeat(1.0));
+        assert!((glass.opacity - 0.1).abs() < 1e-6);
+        assert_eq!((glass.metallic, glass.roughness), (1.0, 0.25));
+    }
+
+    #[test]
+    fn reads_maps_after_their_options() {
+        let map = |statement: &str| parse(&format!("newmtl m\n{}\n", statement))[0].diffuse_map.clone().unwrap();
+        let expected = Path::new("models").join("brick.png");
+        for statement in [
+            "map_Kd brick.png",
+            "map_Kd -s 2 brick.png",
+            "map_Kd -s 2 2 brick.png",
+            "map_Kd -o 0.5 0.5 0 -s 2 2 1 -t 0.1 brick.png",
+            "map_Kd -clamp on -mm 0 1 -bm 0.5 brick.png",
+            "map_Kd -blendu off -s 1.5 -imfchan r brick.png",
+        ] {
+            assert_eq!(map(statement), expected, "{}", statement);
+        }
+        assert_eq!(map("map_Kd textures/old brick.png"), Path::new("models").join("textures/old brick.png"));
+
+        let materials = parse("newmtl m\nmap_Bump -bm 0.3 normal.png\n");
+        assert_eq!(materials[0].bump_map, Some(Path::new("models").join("normal.png")));
+        assert_eq!(parse("newmtl m\nbump -s 4 bumps.png\n")[0].bump_map, Some(Path::new("models").join("bumps.png")));
+    }
+
+    #[test]
+    fn reports_missing_and_invalid_values() {
+        let error = |source: &str| parse_mtl(source, "test.mtl").expect_err(source);
+        assert!(matches!(error("newmtl m\nKd\n").kind, ObjErrorKind::MissingValues { .. }));
+        assert!(matches!(error("newmtl m\nNs\n").kind, ObjErrorKind::MissingValues { .. }));
+        assert!(matches!(error("newmtl m\nmap_Kd -s 2 2\n").kind, ObjErrorKind::MissingValues { .. }));
+        let invalid = error("newmtl m\n\nd half\n");
+        assert_eq!(invalid.line, 3);
+        assert!(matches!(invalid.kind, ObjErrorKind::InvalidNumber(value) if value == "half"));
+    }
+}
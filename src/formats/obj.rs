@@ -0,0 +1,598 @@
+// formats/obj.rs
+// Wavefront OBJ loader. Supports positions, texture coordinates and normals in
+// every face form (v, v/vt, v//vn, v/vt/vn), negative (relative) indices,
+// polygons of any size (ear clipping triangulation), o/g groups and usemtl
+// ranges, which become sub-meshes, and MTL material libraries. Problems are
+// reported with the file name and line number.
+use super::mtl::load_mtl;
+use crate::mesh::{Material, Mesh, SubMesh};
+use crate::vertex::Vertex;
+use nalgebra_glm::{Vec2, Vec3};
+use raylib::prelude::Color;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjErrorKind {
+    Io(std::io::Error),
+    MissingValues { keyword: String, expected: usize },
+    InvalidNumber(String),
+    InvalidIndex(String),
+    IndexOutOfRange { attribute: &'static str, index: i64, count: usize },
+    FaceTooSmall(usize),
+    MaterialLibrary(Box<ObjError>),
+}
+
+// Error while loading an OBJ file. `line` is 1-based, 0 when the file itself
+// could not be read
+#[derive(Debug)]
+pub struct ObjError {
+    pub file: String,
+    pub line: usize,
+    pub kind: ObjErrorKind,
+}
+
+impl fmt::Display for ObjErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjErrorKind::Io(err) => write!(f, "could not read file: {}", err),
+            ObjErrorKind::MissingValues { keyword, expected } => write!(f, "'{}' needs at least {} values", keyword, expected),
+            ObjErrorKind::InvalidNumber(value) => write!(f, "invalid number '{}'", value),
+            ObjErrorKind::InvalidIndex(value) => write!(f, "invalid face vertex '{}'", value),
+            ObjErrorKind::IndexOutOfRange { attribute, index, count } => {
+                write!(f, "{} index {} out of range ({} defined so far)", attribute, index, count)
+            }
+            ObjErrorKind::FaceTooSmall(count) => write!(f, "face with {} vertices, at least 3 are needed", count),
+            ObjErrorKind::MaterialLibrary(err) => write!(f, "in material library: {}", err),
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.kind)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.kind)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ObjErrorKind::Io(err) => Some(err),
+            ObjErrorKind::MaterialLibrary(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// Loads an OBJ file as an indexed mesh. Also returns the problems that did not
+// stop the mesh from loading: material libraries that could not be found
+pub fn load_obj(path: impl AsRef<Path>) -> Result<(Mesh, Vec<ObjError>), ObjError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path).map_err(|err| ObjError { file: file.clone(), line: 0, kind: ObjErrorKind::Io(err) })?;
+    parse_obj(&source, &file)
+}
+
+// Position, texture coordinate and normal indices of a face corner, 0-based
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// Parses OBJ source text. `file` is used in error messages and to find the
+// material libraries, which are looked up next to it
+pub fn parse_obj(source: &str, file: &str) -> Result<(Mesh, Vec<ObjError>), ObjError> {
+    let mut parser = ObjParser {
+        base_dir: Path::new(file).parent().map(Path::to_path_buf).unwrap_or_default(),
+        ..ObjParser::default()
+    };
+    let mut warnings = Vec::new();
+
+    for (line, statement) in statements(source) {
+        parser.parse_line(&statement).map_err(|kind| ObjError { file: file.to_string(), line, kind })?;
+        warnings.extend(parser.warnings.drain(..).map(|kind| ObjError { file: file.to_string(), line, kind }));
+    }
+
+    Ok((parser.finish(), warnings))
+}
+
+// Splits source text into statements with their (1-based) line numbers. A
+// trailing backslash continues a statement on the next line
+pub(super) fn statements(source: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    let mut start = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        if statement.is_empty() {
+            start = number + 1;
+        }
+        match line.strip_suffix('\\') {
+            Some(continued) => {
+                statement.push_str(continued);
+                statement.push(' ');
+            }
+            None => {
+                statement.push_str(line);
+                statements.push((start, std::mem::take(&mut statement)));
+            }
+        }
+    }
+    if !statement.is_empty() {
+        statements.push((start, statement));
+    }
+
+    statements
+}
+
+#[derive(Default)]
+struct ObjParser {
+    positions: Vec<Vec3>,
+    tex_coords: Vec<Vec2>,
+    normals: Vec<Vec3>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    submeshes: Vec<SubMesh>,
+    // Each distinct corner becomes one vertex of the mesh, per material since
+    // the vertex color comes from it
+    vertex_lookup: HashMap<(Corner, Option<usize>), u32>,
+    group_name: String,
+    group_start: usize,
+    base_dir: PathBuf,
+    materials: Vec<Material>,
+    current_material: Option<usize>,
+    warnings: Vec<ObjErrorKind>, // Problems of the current statement that do not stop the parsing
+}
+
+impl ObjParser {
+    fn parse_line(&mut self, line: &str) -> Result<(), ObjErrorKind> {
+        // Everything after '#' is a comment
+        let line = line.split('#').next().unwrap_or("");
+        let mut parts = line.split_whitespace();
+        let Some(keyword) = parts.next() else {
+            return Ok(());
+        };
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(keyword, &mut parts)?;
+                self.positions.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                // v and w are optional
+                let u = parse_float(parts.next().ok_or_else(|| missing(keyword, 1))?)?;
+                let v = parts.next().map(parse_float).transpose()?.unwrap_or(0.0);
+                self.tex_coords.push(Vec2::new(u, v));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(keyword, &mut parts)?;
+                self.normals.push(Vec3::new(x, y, z));
+            }
+            "f" => {
+                let corners = parts.map(|part| self.parse_corner(part)).collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(ObjErrorKind::FaceTooSmall(corners.len()));
+                }
+                self.add_face(&corners);
+            }
+            "o" | "g" => {
+                let name = parts.collect::<Vec<_>>().join(" ");
+                self.start_group(name);
+            }
+            "mtllib" => {
+                let rest = line.trim_start().strip_prefix(keyword).unwrap_or("");
+                for library in library_names(rest) {
+                    self.load_library(library)?;
+                }
+            }
+            "usemtl" => {
+                let name = parts.collect::<Vec<_>>().join(" ");
+                self.use_material(&name);
+            }
+            _ => {} // Ignore other statements (s, l, p...)
+        }
+
+        Ok(())
+    }
+
+    // Parses one face corner (v, v/vt, v//vn or v/vt/vn)
+    fn parse_corner(&self, part: &str) -> Result<Corner, ObjErrorKind> {
+        let mut fields = part.split('/');
+        let position = match fields.next() {
+            Some(field) if !field.is_empty() => resolve_index(field, "position", self.positions.len())?,
+            _ => return Err(ObjErrorKind::InvalidIndex(part.to_string())),
+        };
+        let tex_coord = match fields.next() {
+            Some(field) if !field.is_empty() => Some(resolve_index(field, "texture coordinate", self.tex_coords.len())?),
+            _ => None,
+        };
+        let normal = match fields.next() {
+            Some(field) if !field.is_empty() => Some(resolve_index(field, "normal", self.normals.len())?),
+            _ => None,
+        };
+        if fields.next().is_some() {
+            return Err(ObjErrorKind::InvalidIndex(part.to_string()));
+        }
+
+        Ok((position, tex_coord, normal))
+    }
+
+    fn add_face(&mut self, corners: &[Corner]) {
+        let polygon: Vec<Vec3> = corners.iter().map(|corner| self.positions[corner.0]).collect();
+        for triangle in triangulate(&polygon) {
+            for corner in triangle {
+                let index = self.vertex_index(corners[corner]);
+                self.indices.push(index);
+            }
+        }
+    }
+
+    fn vertex_index(&mut self, corner: Corner) -> u32 {
+        let key = (corner, self.current_material);
+        if let Some(&index) = self.vertex_lookup.get(&key) {
+            return index;
+        }
+
+        let (position, tex_coord, normal) = corner;
+        // Without a normal the vertex gets a zero one, it can be generated afterwards
+        let mut vertex = Vertex::new(
+            self.positions[position],
+            normal.map(|n| self.normals[n]).unwrap_or_else(Vec3::zeros),
+            self.current_material.map_or(Color::WHITE, |m| self.materials[m].diffuse_color()),
+        );
+        if let Some(t) = tex_coord {
+            vertex.tex_coords = self.tex_coords[t];
+        }
+
+        let index = self.vertices.len() as u32;
+        self.vertices.push(vertex);
+        self.vertex_lookup.insert(key, index);
+        index
+    }
+
+    fn start_group(&mut self, name: String) {
+        self.close_group();
+        self.group_name = name;
+        self.group_start = self.indices.len();
+    }
+
+    // Records the faces since the last o/g/usemtl statement as a sub-mesh
+    fn close_group(&mut self) {
+        if self.indices.len() > self.group_start {
+            self.submeshes.push(SubMesh {
+                name: self.group_name.clone(),
+                first_index: self.group_start,
+                index_count: self.indices.len() - self.group_start,
+                material: self.current_material,
+            });
+        }
+    }
+
+    // Adds the materials of a library, resolved relative to the OBJ file. A
+    // missing library is only a warning: exporters often reference one that
+    // was never shipped, and the mesh is still usable with default materials
+    fn load_library(&mut self, name: &str) -> Result<(), ObjErrorKind> {
+        match load_mtl(self.base_dir.join(name)) {
+            Ok(materials) => {
+                self.materials.extend(materials);
+                Ok(())
+            }
+            Err(err) if matches!(&err.kind, ObjErrorKind::Io(io) if io.kind() == std::io::ErrorKind::NotFound) => {
+                self.warnings.push(ObjErrorKind::MaterialLibrary(Box::new(err)));
+                Ok(())
+            }
+            Err(err) => Err(ObjErrorKind::MaterialLibrary(Box::new(err))),
+        }
+    }
+
+    // Faces after usemtl form a new sub-mesh with that material. An unknown
+    // name gets a default material so the range keeps its name
+    fn use_material(&mut self, name: &str) {
+        let material = match self.materials.iter().position(|m| m.name == name) {
+            Some(index) => index,
+            None => {
+                self.materials.push(Material::new(name));
+                self.materials.len() - 1
+            }
+        };
+
+        self.close_group();
+        self.group_start = self.indices.len();
+        self.current_material = Some(material);
+    }
+
+    fn finish(mut self) -> Mesh {
+        self.close_group();
+        let mut mesh = Mesh::new(self.vertices, self.indices);
+        mesh.submeshes = self.submeshes;
+        mesh.materials = self.materials;
+        mesh
+    }
+}
+
+// File names of an mtllib statement. Several libraries are separated by
+// spaces, but file names may contain spaces too: the rest of the line is one
+// name unless every word of it ends in ".mtl"
+pub fn library_names(rest: &str) -> Vec<&str> {
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    if words.iter().all(|word| word.to_ascii_lowercase().ends_with(".mtl")) {
+        words
+    } else {
+        vec![rest.trim()]
+    }
+}
+
+pub(super) fn missing(keyword: &str, expected: usize) -> ObjErrorKind {
+    ObjErrorKind::MissingValues { keyword: keyword.to_string(), expected }
+}
+
+pub(super) fn parse_float(value: &str) -> Result<f32, ObjErrorKind> {
+    value.parse().map_err(|_| ObjErrorKind::InvalidNumber(value.to_string()))
+}
+
+pub(super) fn parse_floats<'a, const N: usize>(keyword: &str, parts: &mut impl Iterator<Item = &'a str>) -> Result<[f32; N], ObjErrorKind> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = parse_float(parts.next().ok_or_else(|| missing(keyword, N))?)?;
+    }
+    Ok(values)
+}
+
+// Converts a 1-based OBJ index (negative ones count back from the last
+// element defined so far) into a 0-based one
+fn resolve_index(value: &str, attribute: &'static str, count: usize) -> Result<usize, ObjErrorKind> {
+    let index: i64 = value.parse().map_err(|_| ObjErrorKind::InvalidIndex(value.to_string()))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjErrorKind::IndexOutOfRange { attribute, index, count });
+    }
+    Ok(resolved as usize)
+}
+
+// Splits a polygon into triangles (indices into `polygon`) by ear clipping, so
+// concave faces are handled too. Falls back to a fan for degenerate polygons
+pub(super) fn triangulate(polygon: &[Vec3]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect::<Vec<_>>();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method gives the polygon normal; drop its dominant axis to work in 2D
+    let mut normal = Vec3::zeros();
+    for i in 0..n {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        normal += Vec3::new((a.y - b.y) * (a.z + b.z), (a.z - b.z) * (a.x + b.x), (a.x - b.x) * (a.y + b.y));
+    }
+    let (ax, ay, az) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    if ax + ay + az <= f32::EPSILON {
+        return fan();
+    }
+    let project = |p: Vec3| {
+        if az >= ax && az >= ay {
+            Vec2::new(p.x, p.y * normal.z.signum())
+        } else if ax >= ay {
+            Vec2::new(p.y, p.z * normal.x.signum())
+        } else {
+            Vec2::new(p.z, p.x * normal.y.signum())
+        }
+    };
+    let points: Vec<Vec2> = polygon.iter().map(|&p| project(p)).collect();
+
+    // The projection keeps the polygon counter-clockwise, so ears are convex corners
+    let cross = |a: Vec2, b: Vec2, c: Vec2| (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (prev, curr, next) = (remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]);
+            let (a, b, c) = (points[prev], points[curr], points[next]);
+            if cross(a, b, c) <= 0.0 {
+                return false;
+            }
+            // No other corner may lie inside the ear
+            remaining.iter().all(|&other| {
+                other == prev || other == curr || other == next || {
+                    let p = points[other];
+                    cross(a, b, p) < 0.0 || cross(b, c, p) < 0.0 || cross(c, a, p) < 0.0
+                }
+            })
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]]);
+                remaining.remove(i);
+            }
+            None => return fan(),
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    fn parse(source: &str) -> Mesh {
+        let (mesh, warnings) = parse_obj(source, "test.obj").unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        mesh
+    }
+
+    fn error(source: &str) -> ObjError {
+        parse_obj(source, "test.obj").expect_err(source)
+    }
+
+    // Vertex at every index of the mesh
+    fn corners(mesh: &Mesh) -> Vec<Vertex> {
+        mesh.indices.iter().map(|&index| mesh.vertices[index as usize]).collect()
+    }
+
+    #[test]
+    fn reads_every_face_form() {
+        let source = format!("{}vt 0.5 0.25\nvn 0 0 1\nf 1 2 3\nf 1/1 2/1 3/1\nf 1//1 2//1 3//1\nf 1/1/1 2/1/1 3/1/1\n", TRIANGLE);
+        let mesh = parse(&source);
+        assert_eq!(mesh.indices.len(), 12);
+        assert_eq!(mesh.vertices.len(), 12); // Every face uses other attributes, so no corner is shared
+
+        let corners = corners(&mesh);
+        let positions = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        for (face, (tex_coords, normal)) in [(Vec2::zeros(), Vec3::zeros()), (Vec2::new(0.5, 0.25), Vec3::zeros()), (Vec2::zeros(), Vec3::z()), (Vec2::new(0.5, 0.25), Vec3::z())]
+            .into_iter()
+            .enumerate()
+        {
+            for (corner, position) in corners[face * 3..face * 3 + 3].iter().zip(positions) {
+                assert_eq!((corner.position, corner.tex_coords, corner.normal), (position, tex_coords, normal), "face {}", face + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_negative_indices() {
+        let mesh = parse(&format!("{}f -3 -2 -1\nf 1 2 3\nv 5 5 5\nf 1 2 -1\n", TRIANGLE));
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 1, 2, 0, 1, 3]);
+        assert_eq!(mesh.vertices[3].position, Vec3::new(5.0, 5.0, 5.0));
+
+        let mesh = parse(&format!("{}vt 0 0\nvt 1 1\nvn 1 0 0\nf -1/-1/-1 -2/-2/-1 -3/-1/-1\n", TRIANGLE));
+        let corners = corners(&mesh);
+        assert_eq!(corners[0].position, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(corners[0].tex_coords, Vec2::new(1.0, 1.0));
+        assert_eq!(corners[1].tex_coords, Vec2::new(0.0, 0.0));
+        assert_eq!(corners[2].normal, Vec3::x());
+    }
+
+    #[test]
+    fn reports_indices_out_of_range_with_their_line() {
+        let err = error(&format!("{}\nf 1 2 4\n", TRIANGLE));
+        assert_eq!(err.line, 5);
+        assert!(matches!(err.kind, ObjErrorKind::IndexOutOfRange { attribute: "position", index: 4, count: 3 }));
+        assert_eq!(err.to_string(), "test.obj:5: position index 4 out of range (3 defined so far)");
+
+        // Only elements defined before the face count, and 0 is never valid
+        assert_eq!(error(&format!("f 1 2 3\n{}", TRIANGLE)).line, 1);
+        assert!(matches!(error(&format!("{}f 0 1 2\n", TRIANGLE)).kind, ObjErrorKind::IndexOutOfRange { index: 0, .. }));
+        assert!(matches!(error(&format!("{}f -4 1 2\n", TRIANGLE)).kind, ObjErrorKind::IndexOutOfRange { index: -4, .. }));
+        let err = error(&format!("{}vt 0 0\nf 1/1 2/2 3/1\n", TRIANGLE));
+        assert!(matches!(err.kind, ObjErrorKind::IndexOutOfRange { attribute: "texture coordinate", index: 2, count: 1 }));
+        let err = error(&format!("{}vn 0 0 1\nf 1//1 2//1 3//-2\n", TRIANGLE));
+        assert!(matches!(err.kind, ObjErrorKind::IndexOutOfRange { attribute: "normal", index: -2, count: 1 }));
+    }
+
+    #[test]
+    fn rejects_malformed_faces() {
+        assert!(matches!(error(&format!("{}f 1 2\n", TRIANGLE)).kind, ObjErrorKind::FaceTooSmall(2)));
+        assert!(matches!(error(&format!("{}vt 0 0\nvn 0 0 1\nf 1/1/1/1 2 3\n", TRIANGLE)).kind, ObjErrorKind::InvalidIndex(_)));
+        assert!(matches!(error(&format!("{}f a 2 3\n", TRIANGLE)).kind, ObjErrorKind::InvalidIndex(_)));
+        assert!(matches!(error("v 1 2\n").kind, ObjErrorKind::MissingValues { expected: 3, .. }));
+        assert!(matches!(error("v 1 x 2\n").kind, ObjErrorKind::InvalidNumber(_)));
+    }
+
+    // Twice the signed area of every triangle of the mesh in the xy plane
+    fn doubled_areas(mesh: &Mesh) -> Vec<f32> {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position);
+                (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn triangulates_polygons() {
+        let quad = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n");
+        assert_eq!(quad.indices.len(), 6);
+        assert_eq!(doubled_areas(&quad).iter().sum::<f32>(), 2.0);
+
+        // An L shape listed from a corner that cannot see the whole polygon,
+        // so a fan from it would create a triangle facing the wrong way
+        let l_shape = parse("v 2 0 0\nv 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nv 0 0 0\nf 1 2 3 4 5 6\n");
+        let areas = doubled_areas(&l_shape);
+        assert_eq!(areas.len(), 4);
+        assert!(areas.iter().all(|&area| area > 0.0), "{:?}", areas);
+        assert_eq!(areas.iter().sum::<f32>(), 6.0);
+
+        // Triangles keep the polygon's winding in any plane
+        let vertical = parse("v 0 0 0\nv 0 0 1\nv 0 1 1\nv 0 1 0\nf 4 3 2 1\n");
+        for triangle in vertical.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertical.vertices[triangle[i] as usize].position);
+            assert!((b - a).cross(&(c - a)).x > 0.0);
+        }
+    }
+
+    #[test]
+    fn groups_faces_into_submeshes() {
+        let source = format!("{}o first\nf 1 2 3\ng empty\ng second\nusemtl red\nf 1 2 3\nf 3 2 1\nusemtl blue\nf 1 2 3\n", TRIANGLE);
+        let mesh = parse(&source);
+        let submeshes: Vec<_> = mesh
+            .submeshes
+            .iter()
+            .map(|s| (s.name.as_str(), s.first_index, s.index_count, s.material.map(|m| mesh.materials[m].name.as_str())))
+            .collect();
+        assert_eq!(submeshes, [("first", 0, 3, None), ("second", 3, 6, Some("red")), ("second", 9, 3, Some("blue"))]);
+
+        // The same corner becomes a separate vertex per material
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(mesh.materials[0], Material::new("red"));
+    }
+
+    #[test]
+    fn defaults_missing_attributes() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.75\nf 1/1 2/1 3/1\n");
+        // Faces before any o, g or usemtl form one unnamed sub-mesh without a material
+        assert_eq!(mesh.submeshes, [SubMesh { name: String::new(), first_index: 0, index_count: 3, material: None }]);
+        assert!(mesh.materials.is_empty());
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.tex_coords, Vec2::new(0.75, 0.0));
+            assert_eq!(vertex.normal, Vec3::zeros());
+            assert_eq!(vertex.color, Color::WHITE);
+        }
+
+        let empty = parse("# nothing but comments\n\ns off\nv 1 2 3\n");
+        assert!(empty.vertices.is_empty() && empty.indices.is_empty());
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let mesh = parse(&format!("{}f 1 \\\n  2 3 # a comment\n", TRIANGLE));
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(error(&format!("{}f 1 \\\n 2 \\\n 9\n", TRIANGLE)).line, 4);
+    }
+
+    #[test]
+    fn reads_library_names_with_spaces() {
+        assert_eq!(library_names(" my materials.mtl "), ["my materials.mtl"]);
+        assert_eq!(library_names(" a.mtl  b.MTL"), ["a.mtl", "b.MTL"]);
+        assert!(library_names("").is_empty());
+
+        let directory = std::env::temp_dir().join(format!("obj_mtllib_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("my materials.mtl"), "newmtl brick\nKd 0.8 0.3 0.2\n").unwrap();
+        let file = directory.join("test.obj").display().to_string();
+        let (mesh, warnings) = parse_obj(&format!("mtllib my materials.mtl\n{}usemtl brick\nf 1 2 3\n", TRIANGLE), &file).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(mesh.materials.len(), 1);
+        assert_eq!(mesh.materials[0].name, "brick");
+        assert_eq!(mesh.materials[0].diffuse, Vec3::new(0.8, 0.3, 0.2));
+    }
+
+    #[test]
+    fn warns_about_missing_material_libraries() {
+        let (mesh, warnings) = parse_obj(&format!("mtllib missing.mtl\n{}f 1 2 3\n", TRIANGLE), "no/such/dir/test.obj").unwrap();
+        assert_eq!(mesh.indices.len(), 3);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 1);
+        assert!(matches!(warnings[0].kind, ObjErrorKind::MaterialLibrary(_)));
+    }
+}
@@ -0,0 +1,466 @@
+// formats/ply.rs
+// Stanford PLY meshes, ASCII and binary (both byte orders). Vertices may carry
+// normals, texture coordinates and colors; faces of any size are triangulated.
+// Elements other than vertices and faces are skipped.
+use super::obj::triangulate;
+use crate::mesh::Mesh;
+use crate::vertex::Vertex;
+use nalgebra_glm::{Vec2, Vec3};
+use raylib::prelude::Color;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug)]
+pub enum PlyErrorKind {
+    Io(io::Error),
+    Header { line: usize, message: String },
+    // Problem in the body: element name, element number and description
+    Data { element: String, index: usize, message: String },
+}
+
+#[derive(Debug)]
+pub struct PlyError {
+    pub file: String,
+    pub kind: PlyErrorKind,
+}
+
+impl fmt::Display for PlyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlyErrorKind::Io(err) => write!(f, "could not read file: {}", err),
+            PlyErrorKind::Header { line, message } => write!(f, "header line {}: {}", line, message),
+            PlyErrorKind::Data { element, index, message } => write!(f, "{} {}: {}", element, index, message),
+        }
+    }
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.kind)
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            PlyErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, ScalarType::F32 | ScalarType::F64)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Loads a PLY file as an indexed mesh
+pub fn load_ply(path: impl AsRef<Path>) -> Result<Mesh, PlyError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let bytes = std::fs::read(path).map_err(|err| PlyError { file: file.clone(), kind: PlyErrorKind::Io(err) })?;
+    parse_ply(&bytes, &file)
+}
+
+// Parses the contents of a PLY file. `file` is only used in error messages
+pub fn parse_ply(bytes: &[u8], file: &str) -> Result<Mesh, PlyError> {
+    let error = |kind| PlyError { file: file.to_string(), kind };
+    let (format, elements, body) = parse_header(bytes).map_err(error)?;
+    read_body(format, &elements, body).map_err(error)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<(PlyFormat, Vec<Element>, &[u8]), PlyErrorKind> {
+    let header_error = |line: usize, message: &str| PlyErrorKind::Header { line, message: message.to_string() };
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+        let end = bytes[position..].iter().position(|&b| b == b'\n').ok_or_else(|| header_error(line_number, "missing end_header"))?;
+        let line = std::str::from_utf8(&bytes[position..position + end]).map_err(|_| header_error(line_number, "header is not text"))?;
+        position += end + 1;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        match parts.as_slice() {
+            ["ply"] if line_number == 1 => {}
+            _ if line_number == 1 => return Err(header_error(1, "not a PLY file")),
+            ["format", name, "1.0"] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(header_error(line_number, "unknown format")),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| header_error(line_number, "invalid element count"))?;
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            }
+            ["property", "list", count, item, name] => {
+                let (Some(count), Some(item)) = (ScalarType::from_name(count), ScalarType::from_name(item)) else {
+                    return Err(header_error(line_number, "unknown property type"));
+                };
+                let element = elements.last_mut().ok_or_else(|| header_error(line_number, "property before any element"))?;
+                element.properties.push(Property { name: name.to_string(), kind: PropertyType::List { count, item } });
+            }
+            ["property", kind, name] => {
+                let kind = ScalarType::from_name(kind).ok_or_else(|| header_error(line_number, "unknown property type"))?;
+                let element = elements.last_mut().ok_or_else(|| header_error(line_number, "property before any element"))?;
+                element.properties.push(Property { name: name.to_string(), kind: PropertyType::Scalar(kind) });
+            }
+            ["end_header"] => break,
+            _ => return Err(header_error(line_number, &format!("unexpected '{}'", line.trim()))),
+        }
+    }
+
+    let format = format.ok_or_else(|| header_error(line_number, "missing format"))?;
+    Ok((format, elements, &bytes[position..]))
+}
+
+// Source of property values: whitespace separated text or packed binary
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], position: usize, big_endian: bool },
+}
+
+impl Values<'_> {
+    fn read(&mut self, kind: ScalarType) -> Result<f64, String> {
+        match self {
+            Values::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of the file")?;
+                token.parse().map_err(|_| format!("invalid number '{}'", token))
+            }
+            Values::Binary { bytes, position, big_endian } => {
+                let data = bytes.get(*position..*position + kind.size()).ok_or("unexpected end of the file")?;
+                *position += kind.size();
+                let mut raw = [0u8; 8];
+                raw[..data.len()].copy_from_slice(data);
+                if *big_endian {
+                    raw[..data.len()].reverse();
+                }
+                Ok(match kind {
+                    ScalarType::I8 => raw[0] as i8 as f64,
+                    ScalarType::U8 => raw[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(raw),
+                })
+            }
+        }
+    }
+}
+
+fn read_body(format: PlyFormat, elements: &[Element], body: &[u8]) -> Result<Mesh, PlyErrorKind> {
+    let mut values = match format {
+        PlyFormat::Ascii => {
+            let text = std::str::from_utf8(body).map_err(|_| PlyErrorKind::Data {
+                element: "body".to_string(),
+                index: 0,
+                message: "ASCII body is not text".to_string(),
+            })?;
+            Values::Ascii(text.split_ascii_whitespace())
+        }
+        PlyFormat::BinaryLittleEndian => Values::Binary { bytes: body, position: 0, big_endian: false },
+        PlyFormat::BinaryBigEndian => Values::Binary { bytes: body, position: 0, big_endian: true },
+    };
+
+    let mut vertices = Vec::new();
+    let mut faces: Vec<Vec<f64>> = Vec::new();
+    for element in elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()));
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let tex_coords = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+        let color = [
+            find(&["red", "diffuse_red"]),
+            find(&["green", "diffuse_green"]),
+            find(&["blue", "diffuse_blue"]),
+            find(&["alpha", "diffuse_alpha"]),
+        ];
+        let face_indices = find(&["vertex_indices", "vertex_index"]);
+
+        for index in 0..element.count {
+            let data_error = |message: String| PlyErrorKind::Data { element: element.name.clone(), index, message };
+            // Scalars keep their value, lists keep their items
+            let mut record: Vec<Vec<f64>> = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                let value = match property.kind {
+                    PropertyType::Scalar(kind) => vec![values.read(kind).map_err(data_error)?],
+                    PropertyType::List { count, item } => {
+                        let length = values.read(count).map_err(data_error)? as usize;
+                        (0..length).map(|_| values.read(item)).collect::<Result<_, _>>().map_err(data_error)?
+                    }
+                };
+                record.push(value);
+            }
+            let scalar = |property: Option<usize>| property.and_then(|p| record[p].first().copied());
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z] = position.map(|p| scalar(p).unwrap_or(0.0) as f32);
+                    let normal = match normal.map(scalar) {
+                        [Some(x), Some(y), Some(z)] => Vec3::new(x as f32, y as f32, z as f32),
+                        _ => Vec3::zeros(), // Can be generated afterwards
+                    };
+                    let channel = |property: Option<usize>, default: u8| match (property, scalar(property)) {
+                        (Some(p), Some(value)) => match &element.properties[p].kind {
+                            PropertyType::Scalar(kind) if kind.is_float() => (value * 255.0).round().clamp(0.0, 255.0) as u8,
+                            _ => value.clamp(0.0, 255.0) as u8,
+                        },
+                        _ => default,
+                    };
+                    let mut vertex = Vertex::new(
+                        Vec3::new(x, y, z),
+                        normal,
+                        Color::new(channel(color[0], 255), channel(color[1], 255), channel(color[2], 255), channel(color[3], 255)),
+                    );
+                    if let [Some(u), Some(v)] = tex_coords.map(scalar) {
+                        vertex.tex_coords = Vec2::new(u as f32, v as f32);
+                    }
+                    vertices.push(vertex);
+                }
+                "face" => {
+                    let indices = face_indices.map(|p| record[p].clone()).ok_or_else(|| data_error("face without vertex_indices".to_string()))?;
+                    faces.push(indices);
+                }
+                _ => {} // Edges, materials and custom elements are not used
+            }
+        }
+    }
+
+    let mut indices = Vec::new();
+    for (face, corners) in faces.iter().enumerate() {
+        let data_error = |message: String| PlyErrorKind::Data { element: "face".to_string(), index: face, message };
+        if corners.len() < 3 {
+            return Err(data_error(format!("face with {} vertices, at least 3 are needed", corners.len())));
+        }
+        // NaN would otherwise turn into index 0
+        if let Some(&bad) = corners.iter().find(|&&i| !i.is_finite() || i < 0.0 || i.fract() != 0.0) {
+            return Err(data_error(format!("invalid vertex index {}", bad)));
+        }
+        if let Some(&bad) = corners.iter().find(|&&i| i as usize >= vertices.len()) {
+            return Err(data_error(format!("vertex index {} out of range ({} vertices)", bad, vertices.len())));
+        }
+
+        let polygon: Vec<Vec3> = corners.iter().map(|&i| vertices[i as usize].position).collect();
+        for triangle in triangulate(&polygon) {
+            indices.extend(triangle.iter().map(|&corner| corners[corner] as u32));
+        }
+    }
+
+    Ok(Mesh::new(vertices, indices))
+}
+
+// Writes a mesh with positions, normals, texture coordinates and colors
+pub fn write_ply(mesh: &Mesh, writer: &mut impl Write, format: PlyFormat) -> io::Result<()> {
+    let format_name = match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        PlyFormat::BinaryBigEndian => "binary_big_endian",
+    };
+    writeln!(writer, "ply\nformat {} 1.0", format_name)?;
+    writeln!(writer, "element vertex {}", mesh.vertices.len())?;
+    for name in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(writer, "property float {}", name)?;
+    }
+    for name in ["red", "green", "blue", "alpha"] {
+        writeln!(writer, "property uchar {}", name)?;
+    }
+    writeln!(writer, "element face {}", mesh.triangle_count())?;
+    writeln!(writer, "property list uchar int vertex_indices\nend_header")?;
+
+    let float = |value: f32| if format == PlyFormat::BinaryBigEndian { value.to_be_bytes() } else { value.to_le_bytes() };
+    let int = |value: u32| if format == PlyFormat::BinaryBigEndian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+    for v in &mesh.vertices {
+        let floats = [v.position.x, v.position.y, v.position.z, v.normal.x, v.normal.y, v.normal.z, v.tex_coords.x, v.tex_coords.y];
+        let color = [v.color.r, v.color.g, v.color.b, v.color.a];
+        if format == PlyFormat::Ascii {
+            let floats: Vec<String> = floats.iter().map(f32::to_string).collect();
+            writeln!(writer, "{} {} {} {} {}", floats.join(" "), color[0], color[1], color[2], color[3])?;
+        } else {
+            for value in floats {
+                writer.write_all(&float(value))?;
+            }
+            writer.write_all(&color)?;
+        }
+    }
+
+    for [a, b, c] in mesh.triangles() {
+        if format == PlyFormat::Ascii {
+            writeln!(writer, "3 {} {} {}", a, b, c)?;
+        } else {
+            writer.write_all(&[3])?;
+            for index in [a, b, c] {
+                writer.write_all(&int(index))?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+pub fn save_ply(mesh: &Mesh, path: impl AsRef<Path>, format: PlyFormat) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_ply(mesh, &mut writer, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    const FORMATS: [PlyFormat; 3] = [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian];
+
+    fn write(mesh: &Mesh, format: PlyFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_ply(mesh, &mut bytes, format).unwrap();
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> PlyErrorKind {
+        parse_ply(bytes, "test.ply").map(|_| ()).expect_err(&String::from_utf8_lossy(bytes)).kind
+    }
+
+    // A header for triangles over `vertices` lines of "x y z", then the body
+    fn ascii(vertices: &str, faces: &str) -> Vec<u8> {
+        let count = |text: &str| text.lines().count();
+        format!(
+            "ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\nelement face {}\nproperty list uchar int vertex_indices\nend_header\n{}{}",
+            count(vertices), count(faces), vertices, faces
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn round_trips_through_every_format() {
+        let mut mesh = primitives::uv_sphere(1.0, 8, 4);
+        for (index, vertex) in mesh.vertices.iter_mut().enumerate() {
+            vertex.color = Color::new(index as u8, 255 - index as u8, 7, 200);
+        }
+
+        for format in FORMATS {
+            let loaded = parse_ply(&write(&mesh, format), "test.ply").unwrap();
+            assert_eq!(loaded.indices, mesh.indices, "{:?}", format);
+            assert_eq!(loaded.vertices.len(), mesh.vertices.len());
+            for (a, b) in loaded.vertices.iter().zip(&mesh.vertices) {
+                assert_eq!((a.position, a.normal, a.tex_coords, a.color), (b.position, b.normal, b.tex_coords, b.color), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_vertex_colors() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n";
+        let faces = "element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        let bytes = format!("{}property uchar red\nproperty uchar green\nproperty uchar blue\n{}0 0 0 255 0 0\n1 0 0 0 128 0\n0 1 0 10 20 30\n3 0 1 2\n", header, faces);
+        let mesh = parse_ply(bytes.as_bytes(), "test.ply").unwrap();
+        let colors: Vec<Color> = mesh.vertices.iter().map(|vertex| vertex.color).collect();
+        assert_eq!(colors, [Color::new(255, 0, 0, 255), Color::new(0, 128, 0, 255), Color::new(10, 20, 30, 255)]);
+
+        // Float channels go from [0, 1] to [0, 255]
+        let bytes = format!("{}property float diffuse_red\nproperty float diffuse_green\nproperty float diffuse_blue\nproperty float alpha\n{}0 0 0 1 0.5 0 0.25\n1 0 0 0 0 0 1\n0 1 0 2 -1 0 1\n3 0 1 2\n", header, faces);
+        let mesh = parse_ply(bytes.as_bytes(), "test.ply").unwrap();
+        assert_eq!(mesh.vertices[0].color, Color::new(255, 128, 0, 64));
+        assert_eq!(mesh.vertices[2].color, Color::new(255, 0, 0, 255));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mesh = primitives::cube(1.0);
+        for format in FORMATS {
+            let bytes = write(&mesh, format);
+            let body = bytes.windows(11).position(|window| window == b"end_header\n").unwrap() + 11;
+            for length in 0..bytes.len() {
+                if length < body {
+                    assert!(matches!(error(&bytes[..length]), PlyErrorKind::Header { .. }), "{:?} cut at {}", format, length);
+                } else if format != PlyFormat::Ascii {
+                    // Text cut inside its last number can still be a valid file
+                    assert!(matches!(error(&bytes[..length]), PlyErrorKind::Data { .. }), "{:?} cut at {}", format, length);
+                }
+            }
+        }
+
+        // Text cut between two numbers leaves values missing
+        let bytes = ascii("0 0 0\n1 0 0\n0 1 0\n", "3 0 1 2\n");
+        assert!(matches!(error(&bytes[..bytes.len() - 3]), PlyErrorKind::Data { element, index: 0, .. } if element == "face"));
+    }
+
+    #[test]
+    fn rejects_invalid_vertex_indices() {
+        let vertices = "0 0 0\n1 0 0\n0 1 0\n";
+        assert!(parse_ply(&ascii(vertices, "3 0 1 2\n"), "test.ply").is_ok());
+        for face in ["3 0 1 3\n", "3 0 1 -1\n", "3 0 1 nan\n", "3 0 1 NaN\n", "3 0 1 1.5\n", "3 0 1 inf\n", "2 0 1\n"] {
+            assert!(matches!(error(&ascii(vertices, face)), PlyErrorKind::Data { element, index: 0, .. } if element == "face"), "{}", face);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// formats/stl.rs
+// STL meshes, ASCII and binary. STL stores independent triangles with one
+// normal each; shared corners are merged when loading, so the mesh is indexed
+// but faces keep their flat normals. Colors and attribute bytes are ignored.
+use crate::mesh::Mesh;
+use crate::vertex::Vertex;
+use nalgebra_glm::Vec3;
+use raylib::prelude::Color;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StlFormat {
+    Ascii,
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum StlErrorKind {
+    Io(io::Error),
+    Truncated,
+    Syntax { line: usize, message: String },
+}
+
+#[derive(Debug)]
+pub struct StlError {
+    pub file: String,
+    pub kind: StlErrorKind,
+}
+
+impl fmt::Display for StlErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StlErrorKind::Io(err) => write!(f, "could not read file: {}", err),
+            StlErrorKind::Truncated => write!(f, "file is truncated"),
+            StlErrorKind::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.kind)
+    }
+}
+
+impl std::error::Error for StlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            StlErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Loads an STL file as an indexed mesh
+pub fn load_stl(path: impl AsRef<Path>) -> Result<Mesh, StlError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let bytes = std::fs::read(path).map_err(|err| StlError { file: file.clone(), kind: StlErrorKind::Io(err) })?;
+    parse_stl(&bytes, &file)
+}
+
+// Parses the contents of an STL file. `file` is only used in error messages
+pub fn parse_stl(bytes: &[u8], file: &str) -> Result<Mesh, StlError> {
+    // Binary files may also start with "solid", so the size decides first
+    let binary_count = bytes.get(80..84).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let format = match binary_count {
+        Some(count) if bytes.len() == 84 + count * 50 => StlFormat::Binary,
+        _ if bytes.trim_ascii_start().starts_with(b"solid") => StlFormat::Ascii,
+        _ => StlFormat::Binary,
+    };
+
+    let triangles = match format {
+        StlFormat::Ascii => parse_ascii(bytes),
+        StlFormat::Binary => parse_binary(bytes),
+    };
+    let triangles = triangles.map_err(|kind| StlError { file: file.to_string(), kind })?;
+    Ok(Mesh::from_triangle_list(&triangles))
+}
+
+// Corner vertices of a triangle. A missing (zero) facet normal is computed
+// from the counter-clockwise winding
+fn facet(normal: Vec3, corners: [Vec3; 3]) -> [Vertex; 3] {
+    let normal = if normal.norm() > 0.0 { normal.normalize() } else { face_normal(&corners) };
+    corners.map(|position| Vertex::new(position, normal, Color::WHITE))
+}
+
+fn face_normal([a, b, c]: &[Vec3; 3]) -> Vec3 {
+    let normal = (b - a).cross(&(c - a));
+    if normal.norm() > 0.0 { normal.normalize() } else { normal }
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Vertex>, StlErrorKind> {
+    let count = bytes.get(80..84).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or(StlErrorKind::Truncated)?;
+    let records = bytes.get(84..84 + count * 50).ok_or(StlErrorKind::Truncated)?;
+
+    let mut vertices = Vec::with_capacity(count * 3);
+    for record in records.chunks_exact(50) {
+        // Normal and 3 corners, 3 floats each, then 2 attribute bytes
+        let float = |offset: usize| f32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]]);
+        let vector = |i: usize| Vec3::new(float(i * 12), float(i * 12 + 4), float(i * 12 + 8));
+        vertices.extend(facet(vector(0), [vector(1), vector(2), vector(3)]));
+    }
+    Ok(vertices)
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<Vertex>, StlErrorKind> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut vertices = Vec::new();
+    let mut normal = Vec3::zeros();
+    let mut corners: Vec<Vec3> = Vec::with_capacity(3);
+    let mut in_facet = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let syntax = |message: &str| StlErrorKind::Syntax { line: number + 1, message: message.to_string() };
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let vector = |values: &[&str]| -> Result<Vec3, StlErrorKind> {
+            let parsed: Vec<f32> = values.iter().map(|v| v.parse()).collect::<Result<_, _>>().map_err(|_| syntax("invalid number"))?;
+            match parsed.as_slice() {
+                &[x, y, z] => Ok(Vec3::new(x, y, z)),
+                _ => Err(syntax("expected three numbers")),
+            }
+        };
+
+        match parts.first().copied() {
+            None | Some("solid" | "outer" | "endloop" | "endsolid") => {}
+            Some("facet") => {
+                if parts.get(1) != Some(&"normal") {
+                    return Err(syntax("expected 'facet normal'"));
+                }
+                normal = vector(&parts[2..])?;
+                corners.clear();
+                in_facet = true;
+            }
+            Some("vertex") => {
+                if corners.len() == 3 {
+                    return Err(syntax("facet with more than 3 vertices"));
+                }
+                corners.push(vector(&parts[1..])?);
+            }
+            Some("endfacet") => match corners.as_slice() {
+                &[a, b, c] => {
+                    vertices.extend(facet(normal, [a, b, c]));
+                    in_facet = false;
+                }
+                _ => return Err(syntax("facet without 3 vertices")),
+            },
+            Some(other) => return Err(syntax(&format!("unexpected '{}'", other))),
+        }
+    }
+
+    // A file cut in the middle of a facet would otherwise lose it silently
+    if in_facet {
+        return Err(StlErrorKind::Truncated);
+    }
+    Ok(vertices)
+}
+
+// Writes every triangle with its facet normal, computed from the positions
+pub fn write_stl(mesh: &Mesh, writer: &mut impl Write, format: StlFormat) -> io::Result<()> {
+    let triangles = mesh.triangles().map(|triangle| triangle.map(|index| mesh.vertices[index as usize].position));
+
+    match format {
+        StlFormat::Ascii => {
+            writeln!(writer, "solid mesh")?;
+            for corners in triangles {
+                let n = face_normal(&corners);
+                writeln!(writer, "  facet normal {} {} {}\n    outer loop", n.x, n.y, n.z)?;
+                for p in corners {
+                    writeln!(writer, "      vertex {} {} {}", p.x, p.y, p.z)?;
+                }
+                writeln!(writer, "    endloop\n  endfacet")?;
+            }
+            writeln!(writer, "endsolid mesh")?;
+        }
+        StlFormat::Binary => {
+            // The header must not start with "solid", readers would take it for ASCII
+            let mut header = [b' '; 80];
+            header[..10].copy_from_slice(b"binary STL");
+            writer.write_all(&header)?;
+            writer.write_all(&(mesh.triangle_count() as u32).to_le_bytes())?;
+            for corners in triangles {
+                for vector in std::iter::once(face_normal(&corners)).chain(corners) {
+                    for value in [vector.x, vector.y, vector.z] {
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                writer.write_all(&[0, 0])?; // Attribute byte count
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+pub fn save_stl(mesh: &Mesh, path: impl AsRef<Path>, format: StlFormat) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_stl(mesh, &mut writer, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    fn write(mesh: &Mesh, format: StlFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_stl(mesh, &mut bytes, format).unwrap();
+        bytes
+    }
+
+    // Position and normal of every triangle corner
+    fn corners(mesh: &Mesh) -> Vec<(Vec3, Vec3)> {
+        mesh.indices.iter().map(|&index| (mesh.vertices[index as usize].position, mesh.vertices[index as usize].normal)).collect()
+    }
+
+    #[test]
+    fn round_trips_through_both_formats() {
+        let cube = primitives::cube(2.0);
+        for format in [StlFormat::Ascii, StlFormat::Binary] {
+            let bytes = write(&cube, format);
+            assert_eq!(bytes.starts_with(b"solid"), format == StlFormat::Ascii);
+            let loaded = parse_stl(&bytes, "test.stl").unwrap();
+            assert_eq!(corners(&loaded), corners(&cube), "{:?}", format);
+            // Corners shared inside a face are merged again, faces stay apart
+            assert_eq!(loaded.vertices.len(), 24, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn computes_missing_facet_normals() {
+        let text = "solid t\nfacet normal 0 0 0\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid t\n";
+        let mesh = parse_stl(text.as_bytes(), "test.stl").unwrap();
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == Vec3::z()));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let cube = primitives::cube(1.0);
+        let binary = write(&cube, StlFormat::Binary);
+        for length in 0..binary.len() {
+            let result = parse_stl(&binary[..length], "test.stl");
+            assert!(matches!(result, Err(StlError { kind: StlErrorKind::Truncated, .. })), "binary cut at {}", length);
+        }
+
+        let ascii = write(&cube, StlFormat::Ascii);
+        let text = String::from_utf8(ascii).unwrap();
+        let inside_facet = text.find("endloop").unwrap();
+        assert!(matches!(parse_stl(&text.as_bytes()[..inside_facet], "test.stl"), Err(StlError { kind: StlErrorKind::Truncated, .. })));
+        let after_first = text.find("endfacet").unwrap() + "endfacet\n".len();
+        assert_eq!(parse_stl(&text.as_bytes()[..after_first], "test.stl").unwrap().triangle_count(), 1);
+
+        let bad = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n";
+        assert!(matches!(parse_stl(bad.as_bytes(), "test.stl"), Err(StlError { kind: StlErrorKind::Syntax { line: 4, .. }, .. })));
+    }
+}
@@ -1,23 +1,24 @@
 // fragment.rs
-use raylib::prelude::*;
+use raylib::prelude::Color;
+use nalgebra_glm::{Vec2, Vec3, Vec4};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Fragment {
-    pub position: (usize, usize),
-    pub depth: f32,
     pub color: Color,
-    pub normal: Vector3,
-    pub intensity: f32,
+    pub normal: Vec3,
+    pub world_position: Vec3,
+    pub tex_coords: Vec2,
+    pub tangent: Vec4, // Zero when the mesh has no tangents
 }
 
 impl Fragment {
-    pub fn new(x: usize, y: usize, depth: f32) -> Self {
+    pub fn new() -> Self {
         Fragment {
-            position: (x, y),
-            depth,
             color: Color::BLACK,
-            normal: Vector3::new(0.0, 0.0, 0.0),
-            intensity: 1.0,
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            world_position: Vec3::new(0.0, 0.0, 0.0),
+            tex_coords: Vec2::new(0.0, 0.0),
+            tangent: Vec4::new(0.0, 0.0, 0.0, 0.0),
         }
     }
-}
\ No newline at end of file
+}
@@ -1,18 +1,63 @@
 // framebuffer.rs
-// use raylib::prelude::*; // Remove raylib import for Color
-
-// Define a simple color type or use u32 directly
+// Colors are stored as 0xRRGGBB
 type Color = u32;
 
-// Helper function to convert RGB (u8) to u32 (0xRRGGBB)
 fn color_to_u32(r: u8, g: u8, b: u8) -> Color {
     ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
 }
 
-// Helper function to convert raylib Color to u32 if needed elsewhere
-// fn rl_color_to_u32(rl_color: raylib::prelude::Color) -> Color {
-//     color_to_u32(rl_color.r, rl_color.g, rl_color.b)
-// }
+// Samples per pixel of the densest anti-aliasing mode
+pub const MAX_SAMPLES: usize = 16;
+
+// Anti-aliasing modes. Each one is a set of sample positions inside the pixel;
+// the samples are averaged when the framebuffer is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    Ssaa2x2, // Supersampling, 2x2 ordered grid, every sample shaded
+    Ssaa4x4, // Supersampling, 4x4 ordered grid, every sample shaded
+    Msaa4x, // Multisampling, 4 rotated-grid samples, shaded once per pixel
+}
+
+impl AntiAliasing {
+    // Sample positions relative to the pixel center, in pixels
+    pub fn sample_positions(self) -> &'static [(f32, f32)] {
+        match self {
+            AntiAliasing::None => &[(0.0, 0.0)],
+            AntiAliasing::Ssaa2x2 => &[(-0.25, -0.25), (0.25, -0.25), (-0.25, 0.25), (0.25, 0.25)],
+            AntiAliasing::Ssaa4x4 => &[
+                (-0.375, -0.375), (-0.125, -0.375), (0.125, -0.375), (0.375, -0.375),
+                (-0.375, -0.125), (-0.125, -0.125), (0.125, -0.125), (0.375, -0.125),
+                (-0.375, 0.125), (-0.125, 0.125), (0.125, 0.125), (0.375, 0.125),
+                (-0.375, 0.375), (-0.125, 0.375), (0.125, 0.375), (0.375, 0.375),
+            ],
+            AntiAliasing::Msaa4x => &[(-0.125, -0.375), (0.375, -0.125), (-0.375, 0.125), (0.125, 0.375)],
+        }
+    }
+
+    pub fn samples(self) -> usize {
+        self.sample_positions().len()
+    }
+
+    // Supersampling runs the fragment shader for every sample, multisampling
+    // only once per pixel
+    pub fn shades_per_sample(self) -> bool {
+        self != AntiAliasing::Msaa4x
+    }
+}
+
+// Rectangle of pixels a rendering pass writes to, with the color and depth
+// storage backing it (`width * height` pixels row by row, each pixel holding
+// one value per sample). It is either the whole framebuffer or a tile of it
+pub struct RenderTarget<'a> {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub antialiasing: AntiAliasing,
+    pub color: &'a mut [u32],
+    pub depth: &'a mut [f32],
+}
 
 pub struct Framebuffer {
     pub width: usize,
@@ -20,6 +65,11 @@ pub struct Framebuffer {
     pub buffer: Vec<u32>, // Keep buffer as u32
     pub zbuffer: Vec<f32>,
     background_color: Color,
+    antialiasing: AntiAliasing,
+    // Per-sample storage, only allocated when there is more than one sample.
+    // `resolve` averages it into `buffer` and `zbuffer`
+    sample_buffer: Vec<u32>,
+    sample_zbuffer: Vec<f32>,
 }
 
 impl Framebuffer {
@@ -30,37 +80,180 @@ impl Framebuffer {
             buffer: vec![0; width * height], // Initialize with black (0x000000)
             zbuffer: vec![f32::INFINITY; width * height],
             background_color: color_to_u32(0, 0, 0), // Black background
+            antialiasing: AntiAliasing::None,
+            sample_buffer: Vec::new(),
+            sample_zbuffer: Vec::new(),
+        }
+    }
+
+    // Reallocates the buffers for a new size, e.g. after the window is resized
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.buffer = vec![self.background_color; width * height];
+        self.zbuffer = vec![f32::INFINITY; width * height];
+        self.allocate_samples();
+    }
+
+    pub fn antialiasing(&self) -> AntiAliasing {
+        self.antialiasing
+    }
+
+    pub fn set_antialiasing(&mut self, antialiasing: AntiAliasing) {
+        self.antialiasing = antialiasing;
+        self.allocate_samples();
+    }
+
+    fn allocate_samples(&mut self) {
+        let samples = self.antialiasing.samples();
+        if samples > 1 {
+            self.sample_buffer = vec![self.background_color; self.width * self.height * samples];
+            self.sample_zbuffer = vec![f32::INFINITY; self.width * self.height * samples];
+        } else {
+            self.sample_buffer = Vec::new();
+            self.sample_zbuffer = Vec::new();
         }
     }
 
     pub fn clear(&mut self) {
         // Use the stored background color value directly
-        for pixel in self.buffer.iter_mut() {
+        for pixel in self.buffer.iter_mut().chain(self.sample_buffer.iter_mut()) {
             *pixel = self.background_color;
         }
-        for depth in self.zbuffer.iter_mut() {
+        for depth in self.zbuffer.iter_mut().chain(self.sample_zbuffer.iter_mut()) {
             *depth = f32::INFINITY;
         }
     }
 
-    pub fn point(&mut self, x: usize, y: usize, color: Color) { // Accept u32 color
-        if x < self.width && y < self.height {
-            let index = y * self.width + x;
-            self.buffer[index] = color;
+    // Color and depth storage the rasterizer writes to: the per-sample buffers
+    // when anti-aliasing is on, the regular ones otherwise
+    pub fn storage_mut(&mut self) -> (&mut [u32], &mut [f32]) {
+        if self.antialiasing.samples() > 1 {
+            (&mut self.sample_buffer, &mut self.sample_zbuffer)
+        } else {
+            (&mut self.buffer, &mut self.zbuffer)
         }
     }
 
-    pub fn point_with_depth(&mut self, x: usize, y: usize, depth: f32, color: Color) { // Accept u32 color
-        if x < self.width && y < self.height {
-            let index = y * self.width + x;
-            if depth < self.zbuffer[index] {
-                self.zbuffer[index] = depth;
-                self.buffer[index] = color; // Store u32 color
+    // The whole framebuffer as a render target
+    pub fn target(&mut self) -> RenderTarget<'_> {
+        let (width, height, antialiasing) = (self.width, self.height, self.antialiasing);
+        let (color, depth) = self.storage_mut();
+        RenderTarget {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            antialiasing,
+            color,
+            depth,
+        }
+    }
+
+    // Averages the samples of every pixel into `buffer` (box filter) and keeps
+    // the nearest sample depth in `zbuffer`. Nothing to do without anti-aliasing
+    pub fn resolve(&mut self) {
+        let samples = self.antialiasing.samples();
+        if samples == 1 {
+            return;
+        }
+
+        let pixels = self.sample_buffer.chunks(samples).zip(self.sample_zbuffer.chunks(samples));
+        for ((pixel, depth), (colors, depths)) in self.buffer.iter_mut().zip(self.zbuffer.iter_mut()).zip(pixels) {
+            let (mut r, mut g, mut b) = (0, 0, 0);
+            for color in colors {
+                r += (color >> 16) & 0xFF;
+                g += (color >> 8) & 0xFF;
+                b += color & 0xFF;
             }
+            let n = samples as u32;
+            *pixel = color_to_u32(((r + n / 2) / n) as u8, ((g + n / 2) / n) as u8, ((b + n / 2) / n) as u8);
+            *depth = depths.iter().copied().fold(f32::INFINITY, f32::min);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::Fragment;
+    use crate::shaders::Shader;
+    use crate::triangle::{setup_triangle, shade_triangle, Uniforms};
+    use crate::vertex::Vertex;
+    use nalgebra_glm::{Vec3, Vec4};
+    use std::cell::Cell;
+
+    // One color everywhere, counting the fragments it shades
+    struct Flat {
+        fragments: Cell<usize>,
+    }
+
+    impl Shader for Flat {
+        fn fragment_shader(&self, _fragment: &Fragment, _uniforms: &Uniforms) -> raylib::prelude::Color {
+            self.fragments.set(self.fragments.get() + 1);
+            raylib::prelude::Color::new(200, 101, 7, 255)
+        }
+    }
+
+    // Draws a triangle covering the left half of a single pixel, at depth
+    // 0.3: no sample sits on its center line, so half of them are covered.
+    // Returns the resolved framebuffer and the fragments shaded
+    fn draw_left_half(antialiasing: AntiAliasing) -> (Framebuffer, usize) {
+        let mut framebuffer = Framebuffer::new(1, 1);
+        framebuffer.set_antialiasing(antialiasing);
+        let [v1, v2, v3] = [(-10.0, -10.0), (0.5, -10.0), (0.5, 20.0)].map(|(x, y)| {
+            let mut vertex = Vertex::new(Vec3::new(x, y, 0.3), Vec3::z(), raylib::prelude::Color::WHITE);
+            vertex.transformed_position = Vec4::new(x, y, 0.3, 1.0);
+            vertex
+        });
+        let (uniforms, shader) = (Uniforms::new(), Flat { fragments: Cell::new(0) });
+        let screen_triangle = setup_triangle(&v1, &v2, &v3, &uniforms).unwrap();
+        shade_triangle(&screen_triangle, &mut framebuffer.target(), &uniforms, &shader);
+        framebuffer.resolve();
+        (framebuffer, shader.fragments.get())
+    }
+
+    #[test]
+    fn resolves_to_the_average_color_and_nearest_depth() {
+        for antialiasing in [AntiAliasing::Ssaa2x2, AntiAliasing::Ssaa4x4, AntiAliasing::Msaa4x] {
+            let (framebuffer, _) = draw_left_half(antialiasing);
+            let covered = framebuffer.sample_zbuffer.iter().filter(|depth| depth.is_finite()).count();
+            assert_eq!(covered * 2, antialiasing.samples(), "{:?}", antialiasing);
+            // Half of (200, 101, 7) over black, rounded to nearest
+            assert_eq!(framebuffer.buffer[0], color_to_u32(100, 51, 4), "{:?}", antialiasing);
+            assert!((framebuffer.zbuffer[0] - 0.3).abs() < 1e-6, "{:?}", antialiasing);
         }
+
+        // Without anti-aliasing the pixel center decides alone
+        let (framebuffer, _) = draw_left_half(AntiAliasing::None);
+        assert_eq!(framebuffer.buffer[0], color_to_u32(0, 0, 0));
+    }
+
+    #[test]
+    fn shades_per_pixel_only_when_multisampling() {
+        assert_eq!(draw_left_half(AntiAliasing::Msaa4x).1, 1);
+        assert_eq!(draw_left_half(AntiAliasing::Ssaa2x2).1, 2);
+        assert_eq!(draw_left_half(AntiAliasing::Ssaa4x4).1, 8);
+        assert!(!AntiAliasing::Msaa4x.shades_per_sample());
+        assert!(AntiAliasing::Ssaa2x2.shades_per_sample() && AntiAliasing::Ssaa4x4.shades_per_sample());
     }
 
-    pub fn set_background_color(&mut self, color: Color) { // Accept u32 color
-        self.background_color = color;
+    #[test]
+    fn reallocates_samples_with_the_size_and_mode() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        assert!(framebuffer.sample_buffer.is_empty());
+
+        framebuffer.set_antialiasing(AntiAliasing::Msaa4x);
+        assert_eq!((framebuffer.sample_buffer.len(), framebuffer.sample_zbuffer.len()), (3 * 2 * 4, 3 * 2 * 4));
+        framebuffer.resize(5, 4);
+        assert_eq!((framebuffer.buffer.len(), framebuffer.zbuffer.len()), (20, 20));
+        assert_eq!((framebuffer.sample_buffer.len(), framebuffer.sample_zbuffer.len()), (5 * 4 * 4, 5 * 4 * 4));
+        framebuffer.set_antialiasing(AntiAliasing::Ssaa4x4);
+        assert_eq!((framebuffer.sample_buffer.len(), framebuffer.sample_zbuffer.len()), (5 * 4 * 16, 5 * 4 * 16));
+        assert_eq!(framebuffer.target().color.len(), 5 * 4 * 16);
+
+        framebuffer.set_antialiasing(AntiAliasing::None);
+        assert!(framebuffer.sample_buffer.is_empty() && framebuffer.sample_zbuffer.is_empty());
+        assert_eq!(framebuffer.target().color.len(), 20);
     }
-}
\ No newline at end of file
+}
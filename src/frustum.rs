@@ -0,0 +1,110 @@
+// frustum.rs
+// View frustum as six planes, to reject whole objects before any of their
+// vertices is transformed. Triangles that cross the frustum are still clipped
+// one by one in clipping.rs.
+use crate::mesh::bounds::{Aabb, BoundingSphere};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    // (normal, distance) with unit normals pointing inside: a point p is in
+    // front of a plane when normal . p + distance >= 0
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Planes of the clip volume -w <= x, y, z <= w brought back through a
+    // projection * view matrix (Gribb and Hartmann), in world space. With a
+    // full model-view-projection matrix they are in model space instead
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let row = |i: usize| matrix.row(i).transpose();
+        let planes = [row(3) + row(0), row(3) - row(0), row(3) + row(1), row(3) - row(1), row(3) + row(2), row(3) - row(2)]
+            .map(|plane| {
+                let length = plane.xyz().norm();
+                if length > 0.0 { plane / length } else { plane }
+            });
+        Frustum { planes }
+    }
+
+    fn distance(plane: &Vec4, point: &Vec3) -> f32 {
+        plane.xyz().dot(point) + plane.w
+    }
+
+    // False only when the sphere is entirely outside one of the planes.
+    // Spheres near a corner of the frustum may pass while being outside
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| Frustum::distance(plane, &sphere.center) >= -sphere.radius)
+    }
+
+    // False only when the box is entirely outside one of the planes: its
+    // corner farthest along the plane normal is behind it
+    pub fn intersects_box(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = Vec3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            Frustum::distance(plane, &corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::create_projection_matrix;
+    use std::f32::consts::FRAC_PI_2;
+
+    // Camera at z = 5 looking at the origin with a 90 degree field of view:
+    // a point at depth d = 5 - z (from 1 to 10) is visible while |x|, |y| <= d
+    fn frustum() -> Frustum {
+        let view = nalgebra_glm::look_at(&Vec3::new(0.0, 0.0, 5.0), &Vec3::zeros(), &Vec3::y());
+        Frustum::from_matrix(&(create_projection_matrix(FRAC_PI_2, 1.0, 1.0, 10.0) * view))
+    }
+
+    fn sphere(center: Vec3) -> BoundingSphere {
+        BoundingSphere { center, radius: 0.5 }
+    }
+
+    fn cube(center: Vec3) -> Aabb {
+        Aabb { min: center - Vec3::repeat(0.5), max: center + Vec3::repeat(0.5) }
+    }
+
+    // A point on each plane (left, right, bottom, top, near, far) and the way out of it
+    fn planes() -> [(Vec3, Vec3); 6] {
+        [
+            (Vec3::new(-5.0, 0.0, 0.0), -Vec3::x()),
+            (Vec3::new(5.0, 0.0, 0.0), Vec3::x()),
+            (Vec3::new(0.0, -5.0, 0.0), -Vec3::y()),
+            (Vec3::new(0.0, 5.0, 0.0), Vec3::y()),
+            (Vec3::new(0.0, 0.0, 4.0), Vec3::z()),
+            (Vec3::new(0.0, 0.0, -5.0), -Vec3::z()),
+        ]
+    }
+
+    #[test]
+    fn keeps_volumes_inside_or_across_the_planes() {
+        let frustum = frustum();
+        for center in [Vec3::zeros(), Vec3::new(2.0, -2.0, 2.0), Vec3::new(-6.0, 6.0, -4.0)] {
+            assert!(frustum.intersects_sphere(&sphere(center)), "{:?}", center);
+            assert!(frustum.intersects_box(&cube(center)), "{:?}", center);
+        }
+        for (on_plane, _) in planes() {
+            assert!(frustum.intersects_sphere(&sphere(on_plane)), "{:?}", on_plane);
+            assert!(frustum.intersects_box(&cube(on_plane)), "{:?}", on_plane);
+        }
+    }
+
+    #[test]
+    fn rejects_volumes_outside_each_plane() {
+        let frustum = frustum();
+        for (on_plane, outwards) in planes() {
+            let center = on_plane + outwards * 2.0;
+            assert!(!frustum.intersects_sphere(&sphere(center)), "{:?}", center);
+            assert!(!frustum.intersects_box(&cube(center)), "{:?}", center);
+            // Until they are large enough to reach back in
+            assert!(frustum.intersects_sphere(&BoundingSphere { center, radius: 2.5 }), "{:?}", center);
+        }
+    }
+}
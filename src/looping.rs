@@ -0,0 +1,96 @@
+// looping.rs
+// Loop period for recordings. With one set, every time-dependent input (the
+// noise drift, the pulsation, the star rotation, glTF animations) repeats
+// exactly after that many seconds, so a clip of one period can be played in
+// a loop without a jump where it restarts. Speeds are rounded to a whole
+// number of cycles per period, which changes them a little.
+use std::f32::consts::TAU;
+
+// Angle of something turning at `speed` radians per second, with the speed
+// rounded to a whole number of turns per period (and at least `min_turns`)
+pub fn looped_angle(time: f32, speed: f32, period: f32, min_turns: u32) -> f32 {
+    let turns = (speed * period / TAU).round().max(min_turns as f32);
+    TAU * turns * phase(time, period)
+}
+
+// Time into an animation lasting `duration` seconds, played a whole number of
+// times per period (at least once) at about its own speed
+pub fn looped_animation_time(time: f32, duration: f32, period: f32) -> f32 {
+    let plays = (period / duration).round().max(1.0);
+    (phase(time, period) * plays).fract() * duration
+}
+
+// Animation time of frame `frame` of a recording at `fps` frames per second.
+// With a loop period the period is split into a whole number of frames (the
+// nearest to `period * fps`) and the time comes from the frame index modulo
+// that number, so the frame one period in is exactly the first one again
+// instead of depending on how `frame * dt / period` rounds
+pub fn frame_time(frame: usize, fps: u32, loop_period: Option<f32>) -> f32 {
+    match loop_period {
+        Some(period) => {
+            let frames_per_period = ((period * fps as f32).round() as usize).max(1);
+            (frame % frames_per_period) as f32 / frames_per_period as f32 * period
+        }
+        None => frame as f32 / fps as f32,
+    }
+}
+
+// Position within the period, from 0 to 1
+pub fn phase(time: f32, period: f32) -> f32 {
+    (time / period).rem_euclid(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::matrix::{create_model_matrix, create_projection_matrix, create_viewport_matrix};
+    use crate::mesh::primitives;
+    use crate::pipeline;
+    use crate::shaders::star::Star;
+    use crate::stats::RenderStats;
+    use crate::triangle::Uniforms;
+    use nalgebra_glm::Vec3;
+
+    const FPS: u32 = 60;
+
+    // The star on a sphere, rendered as the headless mode renders `frame`
+    fn render(frame: usize, loop_period: f32) -> Vec<u32> {
+        let (width, height) = (64, 48);
+        let star = Star::new(1.0, Vec3::zeros());
+        let mut uniforms = Uniforms::new();
+        uniforms.time = frame_time(frame, FPS, Some(loop_period));
+        uniforms.loop_period = Some(loop_period);
+        let rotation = star.rotation_at(uniforms.time, uniforms.loop_period);
+        uniforms.model_matrix = create_model_matrix(star.position, 0.5, Vec3::new(rotation, rotation * 0.5, 0.0));
+        uniforms.view_matrix = nalgebra_glm::look_at(&Vec3::new(0.0, 0.0, -3.0), &Vec3::zeros(), &Vec3::y());
+        uniforms.projection_matrix = create_projection_matrix(1.0, width as f32 / height as f32, 0.1, 100.0);
+        uniforms.viewport_matrix = create_viewport_matrix(width as f32, height as f32);
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        pipeline::draw(&primitives::uv_sphere(1.0, 24, 12), &uniforms, &mut framebuffer, &star, &mut RenderStats::default());
+        framebuffer.buffer
+    }
+
+    #[test]
+    fn splits_the_period_into_whole_frames() {
+        assert_eq!(frame_time(90, FPS, None), 1.5);
+        // 1.3 s at 60 fps is 78 frames, 0.01 s rounds up to one frame
+        assert_eq!(frame_time(78, FPS, Some(1.3)), 0.0);
+        assert_eq!(frame_time(78 * 7 + 39, FPS, Some(1.3)), frame_time(39, FPS, Some(1.3)));
+        assert!((frame_time(39, FPS, Some(1.3)) - 0.65).abs() < 1e-6);
+        assert_eq!(frame_time(5, FPS, Some(0.01)), 0.0);
+    }
+
+    #[test]
+    fn frames_one_period_apart_are_identical() {
+        // Periods whose frame count times the timestep is not exactly the period in f32
+        for (period, frames) in [(1.3, 78), (2.7, 162), (0.35, 21)] {
+            let first = render(0, period);
+            assert!(first.iter().any(|&pixel| pixel != 0));
+            assert!(render(1, period) != first, "the animation does not move with a period of {}", period);
+            assert!(render(frames, period) == first, "frame {} differs from frame 0 with a period of {}", frames, period);
+            assert!(render(3 * frames + 5, period) == render(5, period));
+        }
+    }
+}
@@ -1,3 +1,7 @@
+// Without a window the interactive controls (camera moves, anti-aliasing and
+// culling modes, mesh edits) have nothing calling them
+#![cfg_attr(not(feature = "window"), allow(dead_code))]
+
 mod framebuffer;
 mod vertex;
 mod fragment;
@@ -5,88 +9,535 @@ mod camera;
 mod shaders;
 mod matrix; // Import the new matrix module
 mod triangle; // Import the new triangle module
+mod clipping;
+mod stats;
+mod tiled;
+mod pipeline;
+mod frustum;
+mod looping;
+mod mesh;
+mod formats;
+mod export;
 
 use crate::shaders::star::Star; // Import the Star struct
-use crate::vertex::Vertex; // Import Vertex
-use crate::triangle::{triangle_3d_with_star_shader, Uniforms}; // Import the rendering function and Uniforms
+use crate::formats::cache::{CacheErrorKind, CacheKey};
+use crate::formats::obj::{ObjError, ObjErrorKind};
+use crate::formats::gltf::Scene;
+#[cfg(feature = "window")]
+use crate::formats::ply::PlyFormat;
+#[cfg(feature = "window")]
+use crate::formats::stl::StlFormat;
+use crate::export::sink::{DepthSequence, FrameSink, ImageSequence};
+use crate::export::y4m::{Chroma, Y4mWriter};
+use crate::export::ImageFormat;
+use crate::frustum::Frustum;
+use crate::mesh::lod::LodMesh;
+use crate::mesh::{primitives, Mesh};
+use crate::triangle::{CullMode, Uniforms}; // Import the rendering function and Uniforms
+#[cfg(feature = "window")]
+use crate::triangle::{FrontFace, Interpolation};
 use crate::matrix::{create_projection_matrix, create_viewport_matrix, create_model_matrix}; // Import matrix functions
 
-use minifb::{Key, Window, WindowOptions};
+#[cfg(feature = "window")]
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use nalgebra_glm::Vec3;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use std::f32::consts::PI;
 
 use framebuffer::Framebuffer;
+#[cfg(feature = "window")]
+use framebuffer::AntiAliasing;
 use camera::Camera;
-use raylib::prelude::Color; // We still need this for the Star's color types
+use stats::RenderStats;
+use tiled::{TiledRasterizer, DEFAULT_TILE_SIZE};
 
 
 const WIDTH: usize = 800;
 const HEIGHT: usize = 600;
+#[cfg(feature = "window")]
+const WINDOW_TITLE: &str = "Star Dynamic Shaders - Iris Ayala";
+const DEFAULT_MODEL: &str = "models/sphere.obj";
+#[cfg(feature = "window")]
+const EXPORT_PLY: &str = "export.ply";
+#[cfg(feature = "window")]
+const EXPORT_STL: &str = "export.stl";
+const HEADLESS_OUTPUT: &str = "frames";
+const HEADLESS_FPS: u32 = 60;
+const HEADLESS_TIMESTEP: f32 = 1.0 / HEADLESS_FPS as f32; // Seconds between headless frames
+#[cfg(feature = "window")]
+const SCREENSHOT_PREFIX: &str = "screenshot";
+const PRIMITIVE_PREFIX: &str = "gen:"; // Models starting with it are generated, e.g. "gen:torus:48:24"
+#[cfg(feature = "window")]
+const MAX_SUBDIVIDED_TRIANGLES: usize = 2_000_000; // ] stops subdividing past this
+const CREASE_ANGLE: f32 = 60.0 * PI / 180.0; // Sharper edges keep flat normals
 
-// Simple struct to hold OBJ vertex data before processing
-#[derive(Debug, Clone)]
-struct ObjVertex {
-    pos: Vec3,
-    norm: Vec3,
-}
-
-// Function to load the sphere.obj file
-fn load_obj(filename: &str) -> Result<Vec<Vertex>, Box<dyn std::error::Error>> {
-    let contents = std::fs::read_to_string(filename)?;
-    let mut vertices = Vec::new();
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-
-    for line in contents.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() { continue; }
-
-        match parts[0] {
-            "v" => {
-                let x: f32 = parts[1].parse()?;
-                let y: f32 = parts[2].parse()?;
-                let z: f32 = parts[3].parse()?;
-                positions.push(Vec3::new(x, y, z));
+// Loads the model to render: a glTF scene (.gltf/.glb), a generated primitive
+// such as "gen:icosphere:5", or an OBJ file. Without a model the sphere model
+// is used, falling back to a generated sphere when the file is missing
+fn load_scene(model: Option<&str>) -> Result<Scene, Box<dyn std::error::Error>> {
+    let Some(model) = model else {
+        return match load_obj(DEFAULT_MODEL) {
+            Ok(mesh) => Ok(Scene::from_mesh(mesh)),
+            Err(err) if matches!(&err.kind, ObjErrorKind::Io(io) if io.kind() == std::io::ErrorKind::NotFound) => {
+                eprintln!("Warning: {}, using a generated icosphere", err);
+                Ok(Scene::from_mesh(primitives::icosphere(0.5, 4)))
             }
-            "vn" => {
-                let x: f32 = parts[1].parse()?;
-                let y: f32 = parts[2].parse()?;
-                let z: f32 = parts[3].parse()?;
-                normals.push(Vec3::new(x, y, z));
+            Err(err) => Err(err.into()),
+        };
+    };
+
+    // The prefix keeps a model file named e.g. "cube" from being replaced by a primitive
+    if let Some(spec) = model.strip_prefix(PRIMITIVE_PREFIX) {
+        let mesh = primitives::from_spec(spec).ok_or_else(|| format!("unknown primitive '{}'", spec))?;
+        return Ok(Scene::from_mesh(mesh));
+    }
+
+    let extension = Path::new(model).extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("gltf" | "glb") => Ok(formats::gltf::load_gltf(model)?),
+        Some("ply") => Ok(Scene::from_mesh(formats::ply::load_ply(model)?)),
+        Some("stl") => Ok(Scene::from_mesh(formats::stl::load_stl(model)?)),
+        _ => Ok(Scene::from_mesh(load_obj(model)?)),
+    }
+}
+
+// Loads an OBJ file, printing what could not be loaded along with it
+fn load_obj(path: &str) -> Result<Mesh, ObjError> {
+    let (mesh, warnings) = formats::obj::load_obj(path)?;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(mesh)
+}
+
+// Fills in what the model files left out: smooth normals where they are
+// missing, and tangents for textured meshes
+fn prepare_meshes(scene: &mut Scene) {
+    for mesh in scene.meshes.iter_mut() {
+        if mesh.has_missing_normals() {
+            mesh.fill_missing_normals(CREASE_ANGLE);
+        }
+        if mesh.has_tex_coords() && mesh.has_missing_tangents() {
+            mesh.compute_tangents();
+        }
+    }
+}
+
+// Replaces the normals of every mesh with generated flat or smooth ones
+#[cfg(feature = "window")]
+fn regenerate_normals(scene: &mut Scene, flat: bool) {
+    for mesh in scene.meshes.iter_mut() {
+        if flat {
+            mesh.compute_flat_normals();
+        } else {
+            mesh.compute_smooth_normals(CREASE_ANGLE);
+        }
+        if mesh.has_tex_coords() {
+            mesh.compute_tangents();
+        }
+    }
+}
+
+// One level of Loop subdivision for every mesh. Spheres are projected back
+// onto their sphere, so they get rounder instead of shrinking
+#[cfg(feature = "window")]
+fn subdivide_meshes(scene: &mut Scene) {
+    for mesh in scene.meshes.iter_mut() {
+        let sphere = mesh.fitted_sphere();
+        *mesh = mesh.subdivide_loop();
+        if let Some((center, radius)) = sphere {
+            mesh.project_onto_sphere(center, radius);
+        }
+    }
+}
+
+// Levels of detail of every mesh, rebuilt whenever the meshes change
+fn build_lods(scene: &Scene) -> Vec<LodMesh> {
+    scene.meshes.iter().cloned().map(LodMesh::new).collect()
+}
+
+// Loads the model and prepares it for drawing: missing normals and tangents,
+// levels of detail. Single-mesh model files keep the prepared mesh in a cache
+// next to them, reused until the file or the processing settings change
+fn load_model(model: Option<&str>) -> Result<(Scene, Vec<LodMesh>), Box<dyn std::error::Error>> {
+    let source = Path::new(model.unwrap_or(DEFAULT_MODEL));
+    let extension = source.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+    let cacheable = matches!(extension.as_deref(), Some("obj" | "ply" | "stl")) && source.is_file();
+    let settings = formats::cache::settings_hash(CREASE_ANGLE);
+    let key = if cacheable { formats::cache::source_hash(source).ok() } else { None }.map(|source| CacheKey { source, settings });
+    let cache_path = formats::cache::cache_path(source);
+
+    if let Some(key) = key {
+        match formats::cache::load_cache(&cache_path, key) {
+            Ok(lod) => {
+                eprintln!("Using the mesh cache {}", cache_path.display());
+                return Ok((Scene::from_mesh(lod.levels[0].clone()), vec![lod]));
             }
-            "f" => {
-                // Assume faces are triangles and have vertex/normal indices like f v//vn v//vn v//vn
-                for i in 1..=3 {
-                    let face_part = parts[i];
-                    let sub_parts: Vec<&str> = face_part.split('/').collect();
-                    let pos_idx: usize = sub_parts[0].parse()?;
-                    let norm_idx: usize = sub_parts[2].parse()?;
-
-                    // OBJ format uses 1-based indexing
-                    let pos = positions[pos_idx - 1];
-                    let norm = normals[norm_idx - 1];
-
-                    // Create a vertex with a default color, will be shaded later
-                    vertices.push(Vertex::new(pos, norm, Color::WHITE));
+            Err(err) if matches!(&err.kind, CacheErrorKind::Io(io) if io.kind() == std::io::ErrorKind::NotFound) => {}
+            Err(err) => eprintln!("Warning: {}, rebuilding it", err),
+        }
+    }
+
+    let mut scene = load_scene(model)?;
+    prepare_meshes(&mut scene);
+    let lods = build_lods(&scene);
+    if let (Some(key), [lod]) = (key, lods.as_slice())
+        && let Err(err) = formats::cache::save_cache(lod, key, &cache_path)
+    {
+        eprintln!("Warning: could not write the mesh cache {}: {}", cache_path.display(), err);
+    }
+    Ok((scene, lods))
+}
+
+// Writes the scene as one mesh, with the node transforms and the shader's
+// displacement applied, to PLY and STL files for inspection in other tools
+#[cfg(feature = "window")]
+fn export_scene(scene: &Scene, uniforms: &Uniforms, star: &Star) -> std::io::Result<()> {
+    let mut combined = Mesh::new(Vec::new(), Vec::new());
+    for instance in scene.instances() {
+        let displaced = pipeline::bake_displacement(&scene.meshes[instance.mesh], uniforms, star);
+        combined.append(&displaced.transformed(&instance.model_matrix));
+    }
+
+    formats::ply::save_ply(&combined, EXPORT_PLY, PlyFormat::BinaryLittleEndian)?;
+    formats::stl::save_stl(&combined, EXPORT_STL, StlFormat::Ascii)
+}
+
+// Updates the projection aspect ratio and the viewport for a new render size
+fn update_viewport(uniforms: &mut Uniforms, width: usize, height: usize) {
+    uniforms.projection_matrix = create_projection_matrix(
+        45.0 * PI / 180.0,
+        width as f32 / height as f32,
+        0.1,
+        100.0,
+    );
+    uniforms.viewport_matrix = create_viewport_matrix(width as f32, height as f32);
+}
+
+// Command line: [model] [--headless FRAMES] [--output DIR] [--size WIDTHxHEIGHT]
+// [--format ppm|bmp|png] [--depth] [--gif FILE] [--dither] [--video FILE|-]
+// [--chroma 420|444] [--loop SECONDS]
+struct Options {
+    model: Option<String>,
+    headless_frames: Option<usize>, // Render this many frames to files instead of opening a window
+    output: PathBuf,
+    format: ImageFormat, // Of the headless frames
+    depth: bool, // Also dump the depth buffer of every headless frame
+    gif: Option<PathBuf>, // Encode the headless frames as one animated GIF instead of images
+    dither: bool, // Dither the GIF frames
+    video: Option<PathBuf>, // Write the headless frames as a Y4M video instead of images, "-" for stdout
+    chroma: Chroma, // Of the video
+    loop_period: Option<f32>, // Make the animation repeat exactly after this many seconds
+    width: usize,
+    height: usize,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        model: None,
+        headless_frames: None,
+        output: PathBuf::from(HEADLESS_OUTPUT),
+        format: ImageFormat::Ppm,
+        depth: false,
+        gif: None,
+        dither: false,
+        video: None,
+        chroma: Chroma::Subsampled420,
+        loop_period: None,
+        width: WIDTH,
+        height: HEIGHT,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--headless" => {
+                let frames = value("--headless")?;
+                options.headless_frames = Some(frames.parse().map_err(|_| format!("invalid frame count '{}'", frames))?);
+            }
+            "--output" => options.output = PathBuf::from(value("--output")?),
+            "--format" => {
+                let format = value("--format")?;
+                options.format = ImageFormat::from_extension(&format)
+                    .ok_or_else(|| format!("unknown image format '{}', expected ppm, bmp or png", format))?;
+            }
+            "--depth" => options.depth = true,
+            "--gif" => options.gif = Some(PathBuf::from(value("--gif")?)),
+            "--dither" => options.dither = true,
+            "--video" => options.video = Some(PathBuf::from(value("--video")?)),
+            "--chroma" => {
+                let chroma = value("--chroma")?;
+                options.chroma = Chroma::from_name(&chroma).ok_or_else(|| format!("unknown chroma '{}', expected 420 or 444", chroma))?;
+            }
+            "--loop" => {
+                let period = value("--loop")?;
+                match period.parse::<f32>() {
+                    Ok(period) if period > 0.0 && period.is_finite() => options.loop_period = Some(period),
+                    _ => return Err(format!("invalid loop period '{}', expected seconds", period)),
+                }
+            }
+            "--size" => {
+                let size = value("--size")?;
+                let parsed = size.split_once('x').and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+                match parsed {
+                    Some((width, height)) if width > 0 && height > 0 => (options.width, options.height) = (width, height),
+                    _ => return Err(format!("invalid size '{}', expected WIDTHxHEIGHT", size)),
                 }
             }
-            _ => {} // Ignore other lines (vt, etc.)
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            _ if options.model.is_none() => options.model = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    Ok(options)
+}
+
+// What is drawn every frame: the scene, its levels of detail (with the level
+// each instance drew last) and the star that moves and shades it
+struct World {
+    scene: Scene,
+    lods: Vec<LodMesh>,
+    lod_levels: Vec<usize>,
+    star: Star,
+}
+
+impl World {
+    fn new(scene: Scene, lods: Vec<LodMesh>) -> Self {
+        let lod_levels = vec![0; scene.instances().len()];
+        World { scene, lods, lod_levels, star: Star::new(1.5, Vec3::new(0.0, 0.0, 0.0)) }
+    }
+
+    // On stderr, like every message a headless run prints: stdout may carry video
+    fn print_summary(&self) {
+        let scene = &self.scene;
+        let mesh_count = |f: fn(&Mesh) -> usize| scene.meshes.iter().map(f).sum::<usize>();
+        eprintln!(
+            "Loaded {} meshes ({} vertices, {} triangles, {} materials), {} animations",
+            scene.meshes.len(),
+            mesh_count(|mesh| mesh.vertices.len()),
+            mesh_count(Mesh::triangle_count),
+            mesh_count(|mesh| mesh.materials.len()),
+            scene.animations.len()
+        );
+        for instance in scene.instances() {
+            let placement = instance.placement();
+            eprintln!(
+                "  mesh {} at node {} '{}': position {:?}, scale {}, rotation {:?}",
+                instance.mesh,
+                instance.node,
+                scene.nodes[instance.node].name,
+                placement.position.as_slice(),
+                placement.scale,
+                placement.rotation.as_slice()
+            );
+        }
+        for animation in &scene.animations {
+            eprintln!("  animation '{}': {:.2}s, {} channels", animation.name, animation.duration, animation.channels.len());
+        }
+        for (index, lod) in self.lods.iter().enumerate() {
+            let triangles: Vec<usize> = lod.levels.iter().map(Mesh::triangle_count).collect();
+            eprintln!("  mesh {} levels of detail: {:?} triangles", index, triangles);
+        }
+    }
+}
+
+fn default_camera() -> Camera {
+    Camera::new(
+        // Frames the star as the camera at z = -15 did before the projection
+        // matrix was transposed to be correct: the old matrix gave w = 0.2 * depth,
+        // which magnified everything five times
+        Vec3::new(0.0, 0.0, -3.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    )
+}
+
+fn initial_uniforms(camera: &Camera, width: usize, height: usize) -> Uniforms {
+    let mut uniforms = Uniforms::new(); // Use Uniforms from triangle.rs
+    update_viewport(&mut uniforms, width, height);
+    uniforms.view_matrix = camera.get_view_matrix();
+    uniforms.cull_mode = CullMode::Back; // The sphere is closed, its back faces are never visible
+    uniforms
+}
+
+// Draws the world at `uniforms.time` into the framebuffer and resolves it,
+// on the tile rasterizer when one is given
+fn render_frame(world: &mut World, uniforms: &mut Uniforms, framebuffer: &mut Framebuffer, mut rasterizer: Option<&mut TiledRasterizer>, stats: &mut RenderStats) {
+    framebuffer.clear();
+    stats.reset();
+
+    // --- Render Star ---
+    let star = &world.star;
+    let rotation = star.rotation_at(uniforms.time, uniforms.loop_period);
+    let star_matrix = create_model_matrix(
+        star.position,
+        0.5, // Scale
+        Vec3::new(rotation, rotation * 0.5, 0.0), // Rotation
+    );
+
+    // glTF models play their first animation, a whole number of times per
+    // loop period when there is one
+    if let Some(animation) = world.scene.animations.first() {
+        let time = match uniforms.loop_period {
+            Some(period) if animation.duration > 0.0 => looping::looped_animation_time(uniforms.time, animation.duration, period),
+            _ => uniforms.time,
+        };
+        world.scene.animate(0, time);
+    }
+
+    // Objects outside the view are skipped whole; the others draw the level
+    // of detail that suits their size on screen
+    let frustum = Frustum::from_matrix(&(uniforms.projection_matrix * uniforms.view_matrix));
+    for (instance, level) in world.scene.instances().iter().zip(world.lod_levels.iter_mut()) {
+        uniforms.model_matrix = star_matrix * instance.model_matrix;
+        let lod = &world.lods[instance.mesh];
+        if !frustum.intersects_sphere(&lod.bounds.transformed(&uniforms.model_matrix))
+            || !frustum.intersects_box(&lod.bounding_box.transformed(&uniforms.model_matrix))
+        {
+            stats.objects_culled += 1;
+            continue;
+        }
+
+        let screen_radius = lod.bounds.screen_radius(
+            &(uniforms.view_matrix * uniforms.model_matrix),
+            &uniforms.projection_matrix,
+            framebuffer.height as f32,
+        );
+        *level = lod.select(*level, screen_radius);
+        stats.objects_per_lod[*level] += 1;
+
+        let mesh = &lod.levels[*level];
+        match rasterizer.as_deref_mut() {
+            Some(rasterizer) => rasterizer.draw(mesh, uniforms, star, stats),
+            None => pipeline::draw(mesh, uniforms, framebuffer, star, stats),
         }
     }
 
-    Ok(vertices)
+    // The tile rasterizer shades the whole frame at once
+    if let Some(rasterizer) = rasterizer {
+        rasterizer.flush(framebuffer, star);
+    }
+    framebuffer.resolve();
+}
+
+// Writes the framebuffer as the first free screenshot_N.png in the working
+// directory, with the depth buffer in screenshot_N_depth.png
+#[cfg(feature = "window")]
+fn save_screenshot(framebuffer: &Framebuffer) -> std::io::Result<PathBuf> {
+    let number = (1..).find(|n| !Path::new(&format!("{}_{}.png", SCREENSHOT_PREFIX, n)).exists()).unwrap();
+    let path = PathBuf::from(format!("{}_{}.png", SCREENSHOT_PREFIX, number));
+    export::png::save_png(framebuffer, &path)?;
+    export::depth::save_depth_png(framebuffer, format!("{}_{}_depth.png", SCREENSHOT_PREFIX, number))?;
+    Ok(path)
 }
 
+// A frame sink and the name its errors are reported with
+type NamedSink = (String, Box<dyn FrameSink>);
+
+// Where the headless frames go: the animated GIF and the video asked for,
+// numbered images when neither is, and depth dumps
+fn open_sinks(options: &Options) -> Result<Vec<NamedSink>, Box<dyn std::error::Error>> {
+    let mut sinks: Vec<NamedSink> = Vec::new();
+    let create = |path: &Path| {
+        std::fs::File::create(path).map(std::io::BufWriter::new).map_err(|err| format!("{}: {}", path.display(), err))
+    };
+    if let Some(path) = &options.gif {
+        let gif = export::gif::GifEncoder::new(create(path)?, options.width, options.height, options.dither)?;
+        sinks.push((path.display().to_string(), Box::new(gif)));
+    }
+    if let Some(path) = &options.video {
+        let frame_rate = (HEADLESS_FPS, 1);
+        if path == Path::new("-") {
+            let video = Y4mWriter::new(std::io::BufWriter::new(std::io::stdout().lock()), options.width, options.height, frame_rate, options.chroma)?;
+            sinks.push(("stdout".to_string(), Box::new(video)));
+        } else {
+            let video = Y4mWriter::new(create(path)?, options.width, options.height, frame_rate, options.chroma)?;
+            sinks.push((path.display().to_string(), Box::new(video)));
+        }
+    }
+    let output = options.output.display().to_string();
+    if sinks.is_empty() {
+        let images = ImageSequence::new(&options.output, options.format).map_err(|err| format!("{}: {}", output, err))?;
+        sinks.push((output.clone(), Box::new(images)));
+    }
+    if options.depth {
+        let depth = DepthSequence::new(&options.output).map_err(|err| format!("{}: {}", output, err))?;
+        sinks.push((output, Box::new(depth)));
+    }
+    Ok(sinks)
+}
+
+// Renders `frames` frames at a fixed timestep without opening a window and
+// hands each to the frame sinks. Messages go to stderr, stdout may be the video
+fn run_headless(mut world: World, options: &Options, frames: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sinks = open_sinks(options)?;
+    let mut framebuffer = Framebuffer::new(options.width, options.height);
+    let mut uniforms = initial_uniforms(&default_camera(), options.width, options.height);
+    uniforms.loop_period = options.loop_period;
+    let mut rasterizer = TiledRasterizer::with_available_threads(DEFAULT_TILE_SIZE);
+    let mut stats = RenderStats::default();
+    let start_time = Instant::now();
+
+    for frame in 0..frames {
+        // With a loop period the shader time wraps around, the sinks still
+        // get steadily increasing timestamps
+        uniforms.time = looping::frame_time(frame, HEADLESS_FPS, options.loop_period);
+        world.star.update(HEADLESS_TIMESTEP);
+        render_frame(&mut world, &mut uniforms, &mut framebuffer, Some(&mut rasterizer), &mut stats);
+
+        for (name, sink) in sinks.iter_mut() {
+            sink.write_frame(&framebuffer, frame as f32 * HEADLESS_TIMESTEP).map_err(|err| format!("{}: {}", name, err))?;
+        }
+    }
+    for (name, sink) in sinks.iter_mut() {
+        sink.finish(frames as f32 * HEADLESS_TIMESTEP).map_err(|err| format!("{}: {}", name, err))?;
+    }
+
+    let mut names: Vec<&str> = sinks.iter().map(|(name, _)| name.as_str()).collect();
+    names.dedup();
+    eprintln!(
+        "Rendered {} frames to {} in {:.2}s - last frame: {}",
+        frames,
+        names.join(" and "),
+        start_time.elapsed().as_secs_f32(),
+        stats
+    );
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+    // The model can be given as the first argument, without one the sphere
+    // model is loaded. --headless renders to files instead of a window
+    let options = parse_options(std::env::args().skip(1))?;
+    let (scene, lods) = load_model(options.model.as_deref())?;
+    let world = World::new(scene, lods);
+    world.print_summary();
+
+    match options.headless_frames {
+        Some(frames) => run_headless(world, &options, frames),
+        None => run_window(world, &options),
+    }
+}
+
+// Built without the window feature there is only the headless mode
+#[cfg(not(feature = "window"))]
+fn run_window(_world: World, _options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    Err("built without the window feature, pass --headless FRAMES".into())
+}
+
+// Interactive mode: renders into a window until it is closed or Escape is pressed
+#[cfg(feature = "window")]
+fn run_window(mut world: World, options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut framebuffer = Framebuffer::new(options.width, options.height);
     
     let mut window = Window::new(
-        "Star Dynamic Shaders - Iris Ayala",
-        WIDTH,
-        HEIGHT,
-        WindowOptions::default(),
+        WINDOW_TITLE,
+        options.width,
+        options.height,
+        WindowOptions {
+            resize: true,
+            ..WindowOptions::default()
+        },
     )
     .unwrap_or_else(|e| {
         panic!("Unable to create window: {}", e);
@@ -94,27 +545,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     window.set_target_fps(60);
 
-    let mut camera = Camera::new(
-        Vec3::new(0.0, 0.0, -15.0), // Move camera closer to see the sphere
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 1.0, 0.0),
-    );
-
-    // Load the sphere model
-    let sphere_mesh = load_obj("models/sphere.obj")?;
-    println!("Loaded {} vertices from sphere.obj", sphere_mesh.len());
+    let mut camera = default_camera();
+    let mut flat_normals = false;
+    let mut uniforms = initial_uniforms(&camera, options.width, options.height);
+    uniforms.loop_period = options.loop_period;
 
-    let mut star = Star::new(1.5, Vec3::new(0.0, 0.0, 0.0));
+    // Multithreaded tile rasterizer, T switches to the single-threaded path
+    let mut rasterizer = TiledRasterizer::with_available_threads(DEFAULT_TILE_SIZE);
+    let mut use_tiles = true;
 
-    let mut uniforms = Uniforms::new(); // Use Uniforms from triangle.rs
-    uniforms.projection_matrix = create_projection_matrix(
-        45.0 * PI / 180.0,
-        WIDTH as f32 / HEIGHT as f32,
-        0.1,
-        100.0,
-    );
-    uniforms.view_matrix = camera.get_view_matrix();
-    uniforms.viewport_matrix = create_viewport_matrix(WIDTH as f32, HEIGHT as f32);
+    let mut stats = RenderStats::default();
+    let mut frames_since_title = 0;
+    let mut last_title_update = Instant::now();
 
     let start_time = Instant::now();
 
@@ -122,6 +564,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let elapsed = start_time.elapsed().as_secs_f32();
         uniforms.time = elapsed;
 
+        // Follow the window size (a minimized window reports 0x0, keep the old buffer then)
+        let (window_width, window_height) = window.get_size();
+        if window_width > 0 && window_height > 0
+            && (window_width != framebuffer.width || window_height != framebuffer.height)
+        {
+            framebuffer.resize(window_width, window_height);
+            update_viewport(&mut uniforms, window_width, window_height);
+        }
+
         // Camera Controls (WASD to move, QE to move up/down)
         let camera_speed = 0.1;
         if window.is_key_down(Key::W) {
@@ -143,34 +594,167 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             camera.move_up(-camera_speed);
         }
 
-        uniforms.view_matrix = camera.get_view_matrix();
+        // P toggles perspective-correct / affine interpolation for comparison
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            uniforms.interpolation = match uniforms.interpolation {
+                Interpolation::PerspectiveCorrect => Interpolation::Affine,
+                Interpolation::Affine => Interpolation::PerspectiveCorrect,
+            };
+        }
+
+        // C cycles the face culling mode
+        if window.is_key_pressed(Key::C, KeyRepeat::No) {
+            uniforms.cull_mode = match uniforms.cull_mode {
+                CullMode::Back => CullMode::Front,
+                CullMode::Front => CullMode::None,
+                CullMode::None => CullMode::Back,
+            };
+        }
 
-        star.update(0.016); // Update star rotation and animation state
+        // F flips which winding order counts as front-facing
+        if window.is_key_pressed(Key::F, KeyRepeat::No) {
+            uniforms.front_face = match uniforms.front_face {
+                FrontFace::CounterClockwise => FrontFace::Clockwise,
+                FrontFace::Clockwise => FrontFace::CounterClockwise,
+            };
+        }
 
-        framebuffer.clear();
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            use_tiles = !use_tiles;
+        }
 
-        // --- Render Star ---
-        uniforms.model_matrix = create_model_matrix(
-            star.position,
-            0.5, // Scale
-            Vec3::new(star.rotation, star.rotation * 0.5, 0.0), // Rotation
-        );
+        // M cycles the anti-aliasing mode
+        if window.is_key_pressed(Key::M, KeyRepeat::No) {
+            framebuffer.set_antialiasing(match framebuffer.antialiasing() {
+                AntiAliasing::None => AntiAliasing::Ssaa2x2,
+                AntiAliasing::Ssaa2x2 => AntiAliasing::Ssaa4x4,
+                AntiAliasing::Ssaa4x4 => AntiAliasing::Msaa4x,
+                AntiAliasing::Msaa4x => AntiAliasing::None,
+            });
+        }
 
-        // Iterate through the loaded mesh triangles (every 3 vertices)
-        for i in (0..sphere_mesh.len()).step_by(3) {
-            if i + 2 < sphere_mesh.len() {
-                let v1 = &sphere_mesh[i];
-                let v2 = &sphere_mesh[i + 1];
-                let v3 = &sphere_mesh[i + 2];
+        let mut meshes_changed = false;
 
-                triangle_3d_with_star_shader(v1, v2, v3, &uniforms, &mut framebuffer, &star);
+        // N regenerates the normals, switching between flat and smooth shading
+        if window.is_key_pressed(Key::N, KeyRepeat::No) {
+            flat_normals = !flat_normals;
+            regenerate_normals(&mut world.scene, flat_normals);
+            meshes_changed = true;
+            println!("Generated {} normals", if flat_normals { "flat" } else { "smooth" });
+        }
+
+        // ] subdivides the meshes and [ simplifies them to half their triangles
+        if window.is_key_pressed(Key::RightBracket, KeyRepeat::No) {
+            let triangles = world.scene.meshes.iter().map(Mesh::triangle_count).sum::<usize>();
+            if triangles * 4 <= MAX_SUBDIVIDED_TRIANGLES {
+                subdivide_meshes(&mut world.scene);
+                meshes_changed = true;
+                println!("Meshes have {} triangles", triangles * 4);
+            } else {
+                println!("Not subdividing, the meshes already have {} triangles", triangles);
+            }
+        }
+        if window.is_key_pressed(Key::LeftBracket, KeyRepeat::No) {
+            for mesh in world.scene.meshes.iter_mut() {
+                *mesh = mesh.simplify(mesh.triangle_count() / 2);
+            }
+            meshes_changed = true;
+            println!("Meshes have {} triangles", world.scene.meshes.iter().map(Mesh::triangle_count).sum::<usize>());
+        }
+
+        if meshes_changed {
+            world.lods = build_lods(&world.scene);
+        }
+
+        // X exports the model as it is displaced right now
+        if window.is_key_pressed(Key::X, KeyRepeat::No) {
+            match export_scene(&world.scene, &uniforms, &world.star) {
+                Ok(()) => println!("Exported the model to {} and {}", EXPORT_PLY, EXPORT_STL),
+                Err(err) => eprintln!("Could not export the model: {}", err),
             }
         }
 
+        uniforms.view_matrix = camera.get_view_matrix();
+
+        world.star.update(0.016); // Update star rotation and animation state
+
+        render_frame(&mut world, &mut uniforms, &mut framebuffer, use_tiles.then_some(&mut rasterizer), &mut stats);
+
         window
-            .update_with_buffer(&framebuffer.buffer, WIDTH, HEIGHT)
+            .update_with_buffer(&framebuffer.buffer, framebuffer.width, framebuffer.height)
             .unwrap();
+
+        // Show FPS and the pipeline counters in the title, once per second
+        frames_since_title += 1;
+        if last_title_update.elapsed().as_secs_f32() >= 1.0 {
+            let fps = frames_since_title as f32 / last_title_update.elapsed().as_secs_f32();
+            let mode = if use_tiles { format!("{} threads", rasterizer.threads) } else { "1 thread".to_string() };
+            window.set_title(&format!(
+                "{} - {:.0} FPS ({}) - {:?} culling, {:?} front - {:?} AA - {}",
+                WINDOW_TITLE, fps, mode, uniforms.cull_mode, uniforms.front_face, framebuffer.antialiasing(), stats
+            ));
+            frames_since_title = 0;
+            last_title_update = Instant::now();
+        }
+
+        // F12 saves the frame just shown and its depth buffer
+        if window.is_key_pressed(Key::F12, KeyRepeat::No) {
+            match save_screenshot(&framebuffer) {
+                Ok(path) => println!("Saved a screenshot to {}", path.display()),
+                Err(err) => eprintln!("Could not save a screenshot: {}", err),
+            }
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fresh directory under the system temporary directory
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("headless_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    // Runs the program as `main` would with the given arguments
+    fn run(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        let options = parse_options(args.iter().map(|arg| arg.to_string()))?;
+        let (scene, lods) = load_model(options.model.as_deref())?;
+        run_headless(World::new(scene, lods), &options, options.headless_frames.unwrap())
+    }
+
+    #[test]
+    fn headless_run_writes_every_frame() {
+        let directory = temporary_directory("frames");
+        let output = directory.to_str().unwrap();
+        run(&["gen:icosphere:1", "--headless", "3", "--output", output, "--depth", "--size", "32x24"]).unwrap();
+
+        let mut names: Vec<String> = std::fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        let expected: Vec<String> = (0..3).flat_map(|frame| [format!("frame_{:05}.ppm", frame), format!("frame_{:05}_depth.pfm", frame)]).collect();
+        assert_eq!(names, expected);
+        assert!(std::fs::read(directory.join("frame_00002.ppm")).unwrap().starts_with(b"P6\n32 24\n255\n"));
+
+        // The video replaces the images and holds every frame once it is finished
+        let video = directory.join("sun.y4m");
+        run(&["gen:icosphere:1", "--headless", "3", "--video", video.to_str().unwrap(), "--size", "32x24"]).unwrap();
+        let bytes = std::fs::read(&video).unwrap();
+        let header = bytes.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        assert_eq!(bytes.len(), header + 3 * (b"FRAME\n".len() + 32 * 24 * 3 / 2));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn headless_errors_name_the_sink_and_file() {
+        let directory = temporary_directory("errors");
+        std::fs::create_dir_all(directory.join("frame_00001.ppm")).unwrap();
+        let output = directory.to_str().unwrap();
+        let err = run(&["gen:cube", "--headless", "3", "--output", output, "--size", "8x8"]).unwrap_err();
+        assert_eq!(err.to_string().split(": ").take(2).collect::<Vec<_>>(), [output, "frame_00001.ppm"]);
+        assert!(directory.join("frame_00000.ppm").is_file());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
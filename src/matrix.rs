@@ -1,6 +1,8 @@
 use nalgebra_glm::{Mat4, Vec3};
 
 // Function to create the projection matrix
+// Note: Mat4::new takes its arguments row by row, so this is the standard
+// OpenGL perspective matrix (clip.w = -view.z, visible volume -w <= x,y,z <= w)
 pub fn create_projection_matrix(fov: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
     let f = 1.0 / (fov / 2.0).tan();
     let nf = 1.0 / (near - far);
@@ -8,18 +10,19 @@ pub fn create_projection_matrix(fov: f32, aspect: f32, near: f32, far: f32) -> M
     Mat4::new(
         f / aspect, 0.0, 0.0, 0.0,
         0.0, f, 0.0, 0.0,
-        0.0, 0.0, (far + near) * nf, -1.0,
-        0.0, 0.0, (2.0 * far * near) * nf, 0.0,
+        0.0, 0.0, (far + near) * nf, (2.0 * far * near) * nf,
+        0.0, 0.0, -1.0, 0.0,
     )
 }
 
 // Function to create the viewport matrix
+// Maps NDC [-1, 1] to pixel coordinates, with y pointing down the screen
 pub fn create_viewport_matrix(width: f32, height: f32) -> Mat4 {
     Mat4::new(
-        width / 2.0, 0.0, 0.0, 0.0,
-        0.0, height / 2.0, 0.0, 0.0,
+        width / 2.0, 0.0, 0.0, width / 2.0,
+        0.0, -height / 2.0, 0.0, height / 2.0,
         0.0, 0.0, 1.0, 0.0,
-        width / 2.0, height / 2.0, 0.0, 1.0,
+        0.0, 0.0, 0.0, 1.0,
     )
 }
 
@@ -34,4 +37,4 @@ pub fn create_model_matrix(position: Vec3, scale: f32, rotation: Vec3) -> Mat4 {
     let rot_z = nalgebra_glm::rotate_z(&rot_y, rotation.z);
 
     trans * rot_z * scale_mat // Order: Scale -> Rotate -> Translate
-}
\ No newline at end of file
+}
@@ -0,0 +1,129 @@
+// mesh/bounds.rs
+// Bounding volumes, to reason about a whole mesh (how large it looks on
+// screen, whether it is in view) without touching its vertices every frame.
+use super::Mesh;
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Sphere around a transformed copy of the contents. Non-uniform scaling
+    // grows the radius by the largest axis scale, so it stays conservative
+    pub fn transformed(&self, matrix: &Mat4) -> BoundingSphere {
+        let center = matrix * Vec4::new(self.center.x, self.center.y, self.center.z, 1.0);
+        let scale = (0..3).map(|axis| matrix.fixed_view::<3, 1>(0, axis).norm()).fold(0.0, f32::max);
+        BoundingSphere { center: center.xyz() / center.w, radius: self.radius * scale }
+    }
+
+    // Radius in pixels of the sphere on screen, for a model-view matrix, a
+    // perspective projection and the viewport height. Infinite when the
+    // camera is inside the sphere or the center is behind it but the sphere
+    // still reaches the near side
+    pub fn screen_radius(&self, model_view: &Mat4, projection: &Mat4, viewport_height: f32) -> f32 {
+        let view = self.transformed(model_view);
+        let depth = -view.center.z; // The camera looks down -z
+        if depth <= view.radius {
+            return f32::INFINITY;
+        }
+        view.radius * projection[(1, 1)] * viewport_height / 2.0 / depth
+    }
+}
+
+// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+
+    // Box around the transformed box (affine matrices). Each axis of the
+    // result adds up the extents of the source axes rotated onto it
+    pub fn transformed(&self, matrix: &Mat4) -> Aabb {
+        let center = matrix * Vec4::new(self.center().x, self.center().y, self.center().z, 1.0);
+        let half_extents = (self.max - self.min) / 2.0;
+        let linear = nalgebra_glm::mat4_to_mat3(matrix);
+        let extents = linear.abs() * half_extents;
+        Aabb { min: center.xyz() - extents, max: center.xyz() + extents }
+    }
+}
+
+impl Mesh {
+    pub fn bounding_box(&self) -> Aabb {
+        let (min, max) = self.vertices.iter().fold((Vec3::repeat(f32::MAX), Vec3::repeat(f32::MIN)), |(min, max), vertex| {
+            (min.inf(&vertex.position), max.sup(&vertex.position))
+        });
+        if self.vertices.is_empty() { Aabb { min: Vec3::zeros(), max: Vec3::zeros() } } else { Aabb { min, max } }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn transform(matrix: &Mat4, point: Vec3) -> Vec3 {
+        (matrix * Vec4::new(point.x, point.y, point.z, 1.0)).xyz()
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    // Rotated a quarter turn (or an eighth) about z and scaled unevenly
+    fn matrices() -> [Mat4; 2] {
+        let scale = Mat4::new_nonuniform_scaling(&Vec3::new(2.0, 1.0, 3.0));
+        let translation = Mat4::new_translation(&Vec3::new(10.0, 0.0, -1.0));
+        [
+            translation * nalgebra_glm::rotate_z(&Mat4::identity(), FRAC_PI_2) * scale,
+            translation * nalgebra_glm::rotate_z(&Mat4::identity(), FRAC_PI_2 / 2.0) * scale,
+        ]
+    }
+
+    #[test]
+    fn transforms_boxes_to_the_box_of_their_corners() {
+        let aabb = Aabb { min: Vec3::new(0.0, 0.0, 0.0), max: Vec3::new(1.0, 2.0, 3.0) };
+        let quarter = aabb.transformed(&matrices()[0]);
+        assert!(close(quarter.min, Vec3::new(8.0, 0.0, -1.0)) && close(quarter.max, Vec3::new(10.0, 2.0, 8.0)), "{:?}", quarter);
+
+        for matrix in matrices() {
+            let corners = (0..8).map(|i| {
+                let pick = |bit: usize, min: f32, max: f32| if i >> bit & 1 == 1 { max } else { min };
+                transform(&matrix, Vec3::new(pick(0, aabb.min.x, aabb.max.x), pick(1, aabb.min.y, aabb.max.y), pick(2, aabb.min.z, aabb.max.z)))
+            });
+            let (min, max) = corners.fold((Vec3::repeat(f32::MAX), Vec3::repeat(f32::MIN)), |(min, max), p| (min.inf(&p), max.sup(&p)));
+            let transformed = aabb.transformed(&matrix);
+            assert!(close(transformed.min, min) && close(transformed.max, max), "{:?}", transformed);
+        }
+    }
+
+    #[test]
+    fn transforms_spheres_with_the_largest_scale() {
+        let sphere = BoundingSphere { center: Vec3::new(1.0, 0.0, 0.0), radius: 0.5 };
+        let [quarter, eighth] = matrices();
+        let transformed = sphere.transformed(&quarter);
+        assert!(close(transformed.center, Vec3::new(10.0, 2.0, -1.0)), "{:?}", transformed.center);
+        assert!((transformed.radius - 1.5).abs() < 1e-5);
+
+        // Points on the sphere stay inside whatever the rotation
+        let transformed = sphere.transformed(&eighth);
+        for direction in [Vec3::x(), Vec3::y(), Vec3::z(), Vec3::new(1.0, -1.0, 1.0).normalize()] {
+            for sign in [1.0, -1.0] {
+                let point = transform(&eighth, sphere.center + direction * sign * sphere.radius);
+                assert!((point - transformed.center).norm() <= transformed.radius + 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// mesh/lod.rs
+// Levels of detail: a mesh together with simplified copies of it, each with
+// about a quarter of the triangles of the previous one. The level drawn is the
+// coarsest whose triangle edges still look short on screen, judged from the
+// projected size of the bounding sphere.
+use super::bounds::{Aabb, BoundingSphere};
+use super::Mesh;
+
+pub const MAX_LOD_LEVELS: usize = 5;
+pub const MIN_LOD_TRIANGLES: usize = 32; // Levels stop before getting this coarse
+const TARGET_EDGE_PIXELS: f32 = 8.0; // Longest edge on screen a level may show
+const HYSTERESIS: f32 = 0.25; // Margin around the switch points, so levels do not flicker
+
+#[derive(Debug, Clone)]
+pub struct LodMesh {
+    pub levels: Vec<Mesh>, // Finest first
+    pub bounds: BoundingSphere,
+    pub bounding_box: Aabb,
+    edge_lengths: Vec<f32>, // Mean edge length of every level
+}
+
+impl LodMesh {
+    // Builds the chain by repeatedly simplifying the mesh, stopping at
+    // MAX_LOD_LEVELS or when the mesh would get too coarse to be useful
+    pub fn new(mesh: Mesh) -> Self {
+        let mut levels = vec![mesh];
+        while levels.len() < MAX_LOD_LEVELS {
+            let previous = &levels[levels.len() - 1];
+            let target = previous.triangle_count() / 4;
+            if target < MIN_LOD_TRIANGLES {
+                break;
+            }
+            let simplified = previous.simplify(target);
+            if simplified.triangle_count() * 2 > previous.triangle_count() {
+                break; // The simplifier got stuck, this level would not be much cheaper
+            }
+            levels.push(simplified);
+        }
+
+        // Simplified levels may bulge a little out of the original, the bounds
+        // cover all of them
+        let bounding_box = levels.iter().map(Mesh::bounding_box).reduce(|a, b| a.union(&b)).unwrap();
+        let center = bounding_box.center();
+        let radius = levels.iter().flat_map(|level| &level.vertices).map(|vertex| (vertex.position - center).norm()).fold(0.0, f32::max);
+        LodMesh::from_parts(levels, BoundingSphere { center, radius }, bounding_box)
+    }
+
+    // Chain from levels built before (e.g. read from a cache) and their bounds
+    pub fn from_parts(levels: Vec<Mesh>, bounds: BoundingSphere, bounding_box: Aabb) -> Self {
+        let edge_lengths = levels.iter().map(mean_edge_length).collect();
+        LodMesh { levels, bounds, bounding_box, edge_lengths }
+    }
+
+    // Level to draw for a bounding sphere `screen_radius` pixels large, when
+    // `current` was drawn in the previous frame. A level is left only once
+    // the size is past its switch point by the hysteresis margin
+    pub fn select(&self, current: usize, screen_radius: f32) -> usize {
+        if self.bounds.radius <= 0.0 {
+            return 0;
+        }
+        let edge_pixels = |level: usize| self.edge_lengths[level] / self.bounds.radius * screen_radius;
+        let mut level = current.min(self.levels.len() - 1);
+        while level > 0 && edge_pixels(level) > TARGET_EDGE_PIXELS * (1.0 + HYSTERESIS) {
+            level -= 1;
+        }
+        while level + 1 < self.levels.len() && edge_pixels(level + 1) < TARGET_EDGE_PIXELS * (1.0 - HYSTERESIS) {
+            level += 1;
+        }
+        level
+    }
+}
+
+fn mean_edge_length(mesh: &Mesh) -> f32 {
+    let mut total = 0.0;
+    for triangle in mesh.triangles() {
+        let [a, b, c] = triangle.map(|index| mesh.vertices[index as usize].position);
+        total += (b - a).norm() + (c - b).norm() + (a - c).norm();
+    }
+    if mesh.indices.is_empty() { 0.0 } else { total / mesh.indices.len() as f32 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    // Screen radius at which `level` shows edges of `edge_pixels` pixels
+    fn radius_for(lod: &LodMesh, level: usize, edge_pixels: f32) -> f32 {
+        edge_pixels * lod.bounds.radius / lod.edge_lengths[level]
+    }
+
+    #[test]
+    fn builds_coarser_levels() {
+        let lod = LodMesh::new(primitives::icosphere(1.0, 4));
+        assert!(lod.levels.len() >= 3 && lod.levels.len() <= MAX_LOD_LEVELS, "{} levels", lod.levels.len());
+        assert_eq!(lod.levels[0].triangle_count(), 5120);
+        for pair in lod.levels.windows(2) {
+            assert!(pair[1].triangle_count() * 2 <= pair[0].triangle_count(), "{} after {}", pair[1].triangle_count(), pair[0].triangle_count());
+        }
+        assert!(lod.edge_lengths.windows(2).all(|pair| pair[1] > pair[0]));
+
+        // The bounds hold every level
+        for vertex in lod.levels.iter().flat_map(|level| &level.vertices) {
+            assert!((vertex.position - lod.bounds.center).norm() <= lod.bounds.radius + 1e-5);
+            assert!(vertex.position >= lod.bounding_box.min && vertex.position <= lod.bounding_box.max);
+        }
+    }
+
+    #[test]
+    fn changes_level_monotonically_with_size() {
+        let lod = LodMesh::new(primitives::icosphere(1.0, 4));
+        let last = lod.levels.len() - 1;
+        let radii: Vec<f32> = (0..60).map(|step| 4000.0 * 0.85f32.powi(step)).collect();
+
+        let mut level = 0;
+        let mut shrinking = Vec::new();
+        for &radius in &radii {
+            level = lod.select(level, radius);
+            shrinking.push(level);
+        }
+        assert_eq!((shrinking[0], shrinking[radii.len() - 1]), (0, last));
+        assert!(shrinking.windows(2).all(|pair| pair[1] >= pair[0]), "{:?}", shrinking);
+
+        let mut growing = Vec::new();
+        for &radius in radii.iter().rev() {
+            level = lod.select(level, radius);
+            growing.push(level);
+        }
+        assert_eq!((growing[0], growing[radii.len() - 1]), (last, 0));
+        assert!(growing.windows(2).all(|pair| pair[1] <= pair[0]), "{:?}", growing);
+    }
+
+    #[test]
+    fn keeps_the_current_level_inside_the_hysteresis_band() {
+        let lod = LodMesh::new(primitives::icosphere(1.0, 4));
+        // Level 1 at exactly the target edge size is between both switch points
+        let radius = radius_for(&lod, 1, TARGET_EDGE_PIXELS);
+        assert_eq!(lod.select(0, radius), 0);
+        assert_eq!(lod.select(1, radius), 1);
+
+        // Past the margin the level changes whichever was drawn before
+        let coarser = radius_for(&lod, 1, TARGET_EDGE_PIXELS * (1.0 - HYSTERESIS) * 0.99);
+        assert!(lod.select(0, coarser) >= 1);
+        let finer = radius_for(&lod, 1, TARGET_EDGE_PIXELS * (1.0 + HYSTERESIS) * 1.01);
+        assert_eq!(lod.select(1, finer), 0);
+    }
+
+    #[test]
+    fn handles_empty_bounds_and_unknown_levels() {
+        let lod = LodMesh::new(primitives::icosphere(1.0, 4));
+        let last = lod.levels.len() - 1;
+        // A level from some other chain is clamped to this one
+        assert_eq!(lod.select(99, 0.01), last);
+        assert_eq!(lod.select(99, 1e6), 0);
+
+        let point = LodMesh::from_parts(lod.levels.clone(), BoundingSphere { center: lod.bounds.center, radius: 0.0 }, lod.bounding_box);
+        assert_eq!(point.select(last, 100.0), 0);
+        assert_eq!(point.select(last, 0.0), 0);
+    }
+}
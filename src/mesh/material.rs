@@ -0,0 +1,47 @@
+// mesh/material.rs
+use nalgebra_glm::Vec3;
+use raylib::prelude::Color;
+use std::path::PathBuf;
+
+// Surface description shared by the triangles of a sub-mesh, as read from an
+// MTL library or a glTF file. Colors are linear RGB in [0, 1]. Shaders use the
+// colors; the texture paths are only kept (and cached) for now, as there is
+// no image decoder to sample them with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub diffuse: Vec3,  // Kd
+    pub specular: Vec3, // Ks
+    pub emissive: Vec3, // Ke
+    pub shininess: f32, // Ns, specular exponent
+    pub opacity: f32,   // d (or 1 - Tr)
+    pub metallic: f32,  // Pm, PBR metallic-roughness model
+    pub roughness: f32, // Pr
+    pub diffuse_map: Option<PathBuf>, // map_Kd
+    pub bump_map: Option<PathBuf>,    // map_Bump / bump
+}
+
+impl Material {
+    // White, opaque and without specular highlights, so a mesh without a
+    // material library keeps rendering as before
+    pub fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            diffuse: Vec3::new(1.0, 1.0, 1.0),
+            specular: Vec3::zeros(),
+            emissive: Vec3::zeros(),
+            shininess: 0.0,
+            opacity: 1.0,
+            metallic: 0.0,
+            roughness: 1.0,
+            diffuse_map: None,
+            bump_map: None,
+        }
+    }
+
+    // Diffuse color with the opacity as alpha, used as the vertex color
+    pub fn diffuse_color(&self) -> Color {
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color::new(channel(self.diffuse.x), channel(self.diffuse.y), channel(self.diffuse.z), channel(self.opacity))
+    }
+}
@@ -0,0 +1,140 @@
+// mesh/mod.rs
+pub mod bounds;
+pub mod lod;
+pub mod material;
+pub mod normals;
+pub mod primitives;
+pub mod simplify;
+pub mod subdivision;
+
+use crate::vertex::Vertex;
+use nalgebra_glm::{Mat3, Mat4, Vec4};
+use std::collections::HashMap;
+pub use material::Material;
+
+// Named range of the index buffer, e.g. an OBJ object or group, drawn with
+// one material
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubMesh {
+    pub name: String,
+    pub first_index: usize,
+    pub index_count: usize,
+    pub material: Option<usize>, // Index into `Mesh::materials`
+}
+
+// Indexed triangle mesh: every vertex is stored once and triangles refer to
+// it through the index buffer (3 indices per triangle)
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub submeshes: Vec<SubMesh>, // Empty when the source has no groups
+    pub materials: Vec<Material>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Mesh { vertices, indices, submeshes: Vec::new(), materials: Vec::new() }
+    }
+
+    // Builds an indexed mesh from unindexed triangles (3 vertices each), as
+    // formats like STL store them. Vertices with exactly the same attributes
+    // are merged; a different normal keeps them apart, so hard edges stay hard
+    pub fn from_triangle_list(triangle_vertices: &[Vertex]) -> Self {
+        let mut vertices = Vec::new();
+        let mut lookup: HashMap<([u32; 8], [u8; 4]), u32> = HashMap::new();
+        let indices = triangle_vertices[..triangle_vertices.len() / 3 * 3]
+            .iter()
+            .map(|vertex| {
+                let (p, n, t, c) = (vertex.position, vertex.normal, vertex.tex_coords, vertex.color);
+                // Adding zero turns -0 into 0, so the two compare equal as bits too
+                let key = ([p.x, p.y, p.z, n.x, n.y, n.z, t.x, t.y].map(|value| (value + 0.0).to_bits()), [c.r, c.g, c.b, c.a]);
+                *lookup.entry(key).or_insert_with(|| {
+                    vertices.push(*vertex);
+                    (vertices.len() - 1) as u32
+                })
+            })
+            .collect();
+
+        Mesh::new(vertices, indices)
+    }
+
+    // Copy of the mesh with positions, normals and tangents transformed by a model matrix
+    pub fn transformed(&self, matrix: &Mat4) -> Mesh {
+        let linear = nalgebra_glm::mat4_to_mat3(matrix);
+        let normal_matrix: Mat3 = linear.try_inverse().map(|inverse| inverse.transpose()).unwrap_or(linear);
+        let mirror = if linear.determinant() < 0.0 { -1.0 } else { 1.0 }; // Mirroring flips the bitangent
+        let mut mesh = self.clone();
+        for vertex in mesh.vertices.iter_mut() {
+            let position = matrix * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+            vertex.position = position.xyz() / position.w;
+            let normal = normal_matrix * vertex.normal;
+            vertex.normal = if normal.norm() > 0.0 { normal.normalize() } else { normal };
+            let tangent = linear * vertex.tangent.xyz();
+            if tangent.norm() > 0.0 {
+                let tangent = tangent.normalize();
+                vertex.tangent = Vec4::new(tangent.x, tangent.y, tangent.z, vertex.tangent.w * mirror);
+            }
+        }
+        mesh
+    }
+
+    // Adds the triangles of another mesh, with its sub-meshes and materials
+    pub fn append(&mut self, other: &Mesh) {
+        let (first_vertex, first_index, first_material) = (self.vertices.len() as u32, self.indices.len(), self.materials.len());
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|index| index + first_vertex));
+        self.materials.extend_from_slice(&other.materials);
+        self.submeshes.extend(other.submeshes.iter().map(|submesh| SubMesh {
+            first_index: submesh.first_index + first_index,
+            material: submesh.material.map(|material| material + first_material),
+            ..submesh.clone()
+        }));
+    }
+
+    // Groups the vertices by position, as texture or normal seams split one
+    // point of the surface into several vertices. Positions closer than a small
+    // tolerance (relative to the mesh size) are the same point, since wrapped
+    // surfaces rarely close exactly. Returns the point of every vertex and the
+    // number of points
+    pub(super) fn weld_points(&self) -> (Vec<usize>, usize) {
+        let extent = self.vertices.iter().map(|vertex| vertex.position.abs().max()).fold(0.0, f32::max);
+        let cell = if extent > 0.0 { extent * 1e-5 } else { 1.0 };
+        let mut points: HashMap<[i64; 3], usize> = HashMap::new();
+        let point_of_vertex = self
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let key = [vertex.position.x, vertex.position.y, vertex.position.z].map(|value| (value / cell).round() as i64);
+                let count = points.len();
+                *points.entry(key).or_insert(count)
+            })
+            .collect();
+        (point_of_vertex, points.len())
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // Index triples of every triangle
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|tri| [tri[0], tri[1], tri[2]])
+    }
+}
+
+#[cfg(test)]
+impl Mesh {
+    // True when every edge of the surface, welded by position, is shared by
+    // exactly two triangles
+    pub(crate) fn is_closed(&self) -> bool {
+        let (point_of_vertex, _) = self.weld_points();
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for [a, b, c] in self.triangles().map(|triangle| triangle.map(|index| point_of_vertex[index as usize])) {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((from.min(to), from.max(to))).or_default() += 1;
+            }
+        }
+        edges.values().all(|&count| count == 2)
+    }
+}
@@ -0,0 +1,345 @@
+// mesh/normals.rs
+// Normal and tangent generation for meshes whose files leave them out (the
+// loaders store missing normals as zero). Generated normals replace the
+// vertex buffer, so tangents have to be computed after them.
+use super::Mesh;
+use crate::vertex::Vertex;
+use nalgebra_glm::{Vec3, Vec4};
+use std::collections::HashMap;
+
+impl Mesh {
+    // True when some vertex has no usable normal
+    pub fn has_missing_normals(&self) -> bool {
+        self.vertices.iter().any(|vertex| vertex.normal.norm() == 0.0)
+    }
+
+    // True when some vertex has no tangent
+    pub fn has_missing_tangents(&self) -> bool {
+        self.vertices.iter().any(|vertex| vertex.tangent.xyz().norm() == 0.0)
+    }
+
+    // True when the mesh has texture coordinates (not all of them zero)
+    pub fn has_tex_coords(&self) -> bool {
+        self.vertices.iter().any(|vertex| vertex.tex_coords.x != 0.0 || vertex.tex_coords.y != 0.0)
+    }
+
+    // Gives every triangle its own face normal. Vertices shared by triangles
+    // facing different ways are split
+    pub fn compute_flat_normals(&mut self) {
+        let face_normals = self.face_normals();
+        let normals = (0..self.indices.len()).map(|corner| face_normals[corner / 3]).collect();
+        self.rebuild_vertices(normals);
+    }
+
+    // Smooth normals: each corner averages the normals of the triangles around
+    // its position, weighted by their angle at that corner, so the result does
+    // not depend on how the faces were triangulated. Triangles meeting at more
+    // than `crease_angle` (radians) are not averaged, keeping that edge hard.
+    // Corners are grouped by position, so seams where only the texture
+    // coordinates or material change are smoothed across
+    pub fn compute_smooth_normals(&mut self, crease_angle: f32) {
+        let normals = self.smooth_normals(crease_angle);
+        self.rebuild_vertices(normals);
+    }
+
+    // Gives smooth normals (as compute_smooth_normals) only to the vertices
+    // that have none, keeping the normals the file provides
+    pub fn fill_missing_normals(&mut self, crease_angle: f32) {
+        let normals = self
+            .smooth_normals(crease_angle)
+            .into_iter()
+            .zip(&self.indices)
+            .map(|(smooth, &index)| match self.vertices[index as usize].normal {
+                normal if normal.norm() == 0.0 => smooth,
+                normal => normal,
+            })
+            .collect();
+        self.rebuild_vertices(normals);
+    }
+
+    // Smooth normal of every triangle corner (one per index)
+    fn smooth_normals(&self, crease_angle: f32) -> Vec<Vec3> {
+        let face_normals = self.face_normals();
+        let min_cosine = crease_angle.cos();
+        let (point_of_vertex, point_count) = self.weld_points();
+
+        // Triangle corners around every point
+        let mut corners_at: Vec<Vec<(usize, usize)>> = vec![Vec::new(); point_count];
+        for (triangle, indices) in self.triangles().enumerate() {
+            for (corner, &index) in indices.iter().enumerate() {
+                corners_at[point_of_vertex[index as usize]].push((triangle, corner));
+            }
+        }
+        let angles: Vec<[f32; 3]> = self.triangles().map(|indices| corner_angles(&self.positions(indices))).collect();
+
+        (0..self.indices.len())
+            .map(|corner| {
+                let face_normal = face_normals[corner / 3];
+                corners_at[point_of_vertex[self.indices[corner] as usize]]
+                    .iter()
+                    // A degenerate triangle has no normal of its own and takes all its neighbors
+                    .filter(|&&(other, _)| face_normal == Vec3::zeros() || face_normal.dot(&face_normals[other]) >= min_cosine)
+                    .map(|&(other, other_corner)| face_normals[other] * angles[other][other_corner])
+                    .sum::<Vec3>()
+                    .try_normalize(0.0)
+                    .unwrap_or(face_normal)
+            })
+            .collect()
+    }
+
+    // Tangents along the direction of increasing u, in the spirit of
+    // MikkTSpace: per-triangle tangents are projected onto the plane of each
+    // corner's normal and averaged with angle weights, then orthonormalized.
+    // The w component stores the handedness, bitangent = w * normal x tangent.
+    // Where mirrored texture coordinates meet, corners of different
+    // handedness get separate vertices. Needs normals and texture coordinates;
+    // vertices without a usable frame get any tangent perpendicular to the normal
+    pub fn compute_tangents(&mut self) {
+        let triangles: Vec<[u32; 3]> = self.triangles().collect();
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(self.vertices.len());
+        let mut sums: Vec<(Vec3, f32)> = Vec::with_capacity(self.vertices.len()); // Tangent sum and handedness
+        let mut lookup: HashMap<(u32, bool), u32> = HashMap::new();
+
+        for (triangle, indices) in triangles.iter().enumerate() {
+            let corners = indices.map(|index| self.vertices[index as usize]);
+            let (tangent, flipped) = triangle_tangent(&corners);
+            let angles = corner_angles(&corners.map(|vertex| vertex.position));
+
+            for corner in 0..3 {
+                let index = *lookup.entry((indices[corner], flipped)).or_insert_with(|| {
+                    vertices.push(corners[corner]);
+                    sums.push((Vec3::zeros(), if flipped { -1.0 } else { 1.0 }));
+                    (vertices.len() - 1) as u32
+                });
+                let normal = corners[corner].normal;
+                sums[index as usize].0 += (tangent - normal * normal.dot(&tangent)) * angles[corner];
+                self.indices[triangle * 3 + corner] = index;
+            }
+        }
+
+        for (vertex, (sum, handedness)) in vertices.iter_mut().zip(sums) {
+            let normal = vertex.normal;
+            let tangent = sum - normal * normal.dot(&sum);
+            let tangent = if tangent.norm() > 1e-12 { tangent.normalize() } else { any_perpendicular(normal) };
+            vertex.tangent = Vec4::new(tangent.x, tangent.y, tangent.z, handedness);
+        }
+        self.vertices = vertices;
+    }
+
+    fn positions(&self, indices: [u32; 3]) -> [Vec3; 3] {
+        indices.map(|index| self.vertices[index as usize].position)
+    }
+
+    // Unit normal of every triangle from its counter-clockwise winding, zero
+    // for degenerate triangles
+    fn face_normals(&self) -> Vec<Vec3> {
+        self.triangles()
+            .map(|indices| {
+                let [a, b, c] = self.positions(indices);
+                let normal = (b - a).cross(&(c - a));
+                if normal.norm() > 0.0 { normal.normalize() } else { normal }
+            })
+            .collect()
+    }
+
+    // Replaces the normal of every triangle corner (one per index) and merges
+    // the corners that end up identical again. Triangle order, and so the
+    // sub-meshes, is kept
+    fn rebuild_vertices(&mut self, normals: Vec<Vec3>) {
+        let corners: Vec<Vertex> = self
+            .indices
+            .iter()
+            .zip(normals)
+            .map(|(&index, normal)| Vertex { normal, ..self.vertices[index as usize] })
+            .collect();
+        let rebuilt = Mesh::from_triangle_list(&corners);
+        self.vertices = rebuilt.vertices;
+        self.indices = rebuilt.indices;
+    }
+}
+
+// Interior angle of a triangle at each corner
+fn corner_angles([a, b, c]: &[Vec3; 3]) -> [f32; 3] {
+    let angle = |corner: &Vec3, next: &Vec3, previous: &Vec3| {
+        let (u, v) = (next - corner, previous - corner);
+        if u.norm() == 0.0 || v.norm() == 0.0 { 0.0 } else { u.angle(&v) }
+    };
+    [angle(a, b, c), angle(b, c, a), angle(c, a, b)]
+}
+
+// Unit tangent of a triangle from its texture coordinates, and whether its
+// texture space is mirrored (bitangent pointing against normal x tangent).
+// Zero when the texture coordinates are degenerate
+fn triangle_tangent([a, b, c]: &[Vertex; 3]) -> (Vec3, bool) {
+    let (edge1, edge2) = (b.position - a.position, c.position - a.position);
+    let (duv1, duv2) = (b.tex_coords - a.tex_coords, c.tex_coords - a.tex_coords);
+    let area = duv1.x * duv2.y - duv2.x * duv1.y;
+    if area.abs() < 1e-12 {
+        return (Vec3::zeros(), false);
+    }
+
+    let tangent = (edge1 * duv2.y - edge2 * duv1.y) / area;
+    let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / area;
+    let normal = edge1.cross(&edge2);
+    let flipped = normal.cross(&tangent).dot(&bitangent) < 0.0;
+    let tangent = if tangent.norm() > 0.0 { tangent.normalize() } else { tangent };
+    (tangent, flipped)
+}
+
+fn any_perpendicular(normal: Vec3) -> Vec3 {
+    let axis = if normal.x.abs() < 0.9 { Vec3::x() } else { Vec3::y() };
+    let tangent = axis - normal * normal.dot(&axis);
+    if tangent.norm() > 0.0 { tangent.normalize() } else { axis }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+    use nalgebra_glm::Vec2;
+    use raylib::prelude::Color;
+    use std::f32::consts::PI;
+
+    fn vertex(position: (f32, f32, f32), normal: Vec3, tex_coords: (f32, f32)) -> Vertex {
+        let mut vertex = Vertex::new(Vec3::new(position.0, position.1, position.2), normal, Color::WHITE);
+        vertex.tex_coords = Vec2::new(tex_coords.0, tex_coords.1);
+        vertex
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    // Normal of every triangle corner, in index order
+    fn corner_normals(mesh: &Mesh) -> Vec<Vec3> {
+        mesh.indices.iter().map(|&index| mesh.vertices[index as usize].normal).collect()
+    }
+
+    #[test]
+    fn keeps_cube_edges_below_the_crease_angle() {
+        let cube = primitives::cube(2.0);
+        let mut mesh = cube.clone();
+        mesh.compute_smooth_normals(60f32.to_radians());
+        assert_eq!(corner_normals(&mesh), corner_normals(&cube));
+        assert_eq!(mesh.vertices.len(), 24);
+
+        // Flat normals are the face normals the cube already has
+        let mut mesh = cube.clone();
+        mesh.compute_flat_normals();
+        assert_eq!(corner_normals(&mesh), corner_normals(&cube));
+    }
+
+    #[test]
+    fn averages_cube_corners_above_the_crease_angle() {
+        let mut mesh = primitives::cube(2.0);
+        mesh.compute_smooth_normals(100f32.to_radians());
+        // Every face meets its corner with 90 degrees, so the three count the same
+        for vertex in &mesh.vertices {
+            assert!(close(vertex.normal, vertex.position.normalize()), "{:?} at {:?}", vertex.normal, vertex.position);
+        }
+    }
+
+    #[test]
+    fn weights_by_corner_angle_not_area() {
+        // A tiny and a huge triangle fan out of the origin, both with a right angle there
+        let vertices = vec![
+            vertex((0.0, 0.0, 0.0), Vec3::zeros(), (0.0, 0.0)),
+            vertex((0.1, 0.0, 0.0), Vec3::zeros(), (0.0, 0.0)),
+            vertex((0.0, 0.1, 0.0), Vec3::zeros(), (0.0, 0.0)),
+            vertex((0.0, 10.0, 0.0), Vec3::zeros(), (0.0, 0.0)),
+            vertex((-10.0, 0.0, 10.0), Vec3::zeros(), (0.0, 0.0)),
+        ];
+        let mut mesh = Mesh::new(vertices, vec![0, 1, 2, 0, 3, 4]);
+        mesh.compute_smooth_normals(PI);
+
+        let small = Vec3::z();
+        let large = Vec3::new(1.0, 0.0, 1.0).normalize();
+        let by_angle = (small + large).normalize();
+        let by_area = (small * 0.005 + large * 100.0 / 2f32.sqrt()).normalize();
+        let origin = mesh.vertices.iter().find(|vertex| vertex.position == Vec3::zeros()).unwrap();
+        assert!(close(origin.normal, by_angle), "{:?}", origin.normal);
+        assert!((origin.normal - by_area).norm() > 0.1);
+    }
+
+    #[test]
+    fn fills_only_missing_normals() {
+        let cube = primitives::cube(2.0);
+        let mut mesh = cube.clone();
+        for vertex in mesh.vertices.iter_mut().filter(|vertex| vertex.normal == Vec3::y()) {
+            vertex.normal = Vec3::zeros();
+        }
+        assert!(mesh.has_missing_normals());
+        mesh.fill_missing_normals(100f32.to_radians());
+        assert!(!mesh.has_missing_normals());
+
+        // Triangle order is kept, so corners can be compared one by one
+        for (&index, original) in mesh.indices.iter().zip(corner_normals(&cube)) {
+            let vertex = &mesh.vertices[index as usize];
+            let expected = if original == Vec3::y() { vertex.position.normalize() } else { original };
+            assert!(close(vertex.normal, expected), "{:?} at {:?}", vertex.normal, vertex.position);
+        }
+    }
+
+    // Two quads on z = 0 side by side, left one from x = -1 and right one to
+    // x = 1, sharing the vertices on x = 0. `u` gives the texture u of each x
+    fn quads(u: fn(f32) -> f32) -> Mesh {
+        let vertices = [-1.0, 0.0, 1.0]
+            .iter()
+            .flat_map(|&x| [0.0, 1.0].map(|y| vertex((x, y, 0.0), Vec3::z(), (u(x), y))))
+            .collect();
+        Mesh::new(vertices, vec![0, 2, 3, 0, 3, 1, 2, 4, 5, 2, 5, 3])
+    }
+
+    fn check_frame(vertex: &Vertex) {
+        let tangent = vertex.tangent.xyz();
+        assert!((tangent.norm() - 1.0).abs() < 1e-5);
+        assert!(tangent.dot(&vertex.normal).abs() < 1e-5);
+        assert!(vertex.tangent.w == 1.0 || vertex.tangent.w == -1.0);
+    }
+
+    #[test]
+    fn computes_unit_tangents_with_handedness() {
+        let mut mesh = quads(|x| x);
+        mesh.compute_tangents();
+        assert!(!mesh.has_missing_tangents());
+        for vertex in &mesh.vertices {
+            check_frame(vertex);
+            assert!(close(vertex.tangent.xyz(), Vec3::x()));
+            assert_eq!(vertex.tangent.w, 1.0);
+        }
+
+        // u grows to the left: the tangent turns around and, with v still up, the frame is mirrored
+        let mut mesh = quads(|x| -x);
+        mesh.compute_tangents();
+        for vertex in &mesh.vertices {
+            check_frame(vertex);
+            assert!(close(vertex.tangent.xyz(), -Vec3::x()));
+            assert_eq!(vertex.tangent.w, -1.0);
+        }
+    }
+
+    #[test]
+    fn splits_tangents_at_a_mirrored_uv_seam() {
+        // The texture is mirrored at x = 0, as on the two halves of a symmetric model
+        let mut mesh = quads(f32::abs);
+        mesh.compute_tangents();
+        assert_eq!(mesh.vertices.len(), 8);
+
+        for vertex in &mesh.vertices {
+            check_frame(vertex);
+        }
+        let on_seam: Vec<&Vertex> = mesh.vertices.iter().filter(|vertex| vertex.position.x == 0.0).collect();
+        assert_eq!(on_seam.len(), 4);
+        assert_eq!(on_seam.iter().filter(|vertex| vertex.tangent.w == -1.0).count(), 2);
+
+        // Each side keeps the frame of its own half
+        for triangle in mesh.triangles() {
+            let corners = triangle.map(|index| mesh.vertices[index as usize]);
+            let left = corners.iter().any(|vertex| vertex.position.x < 0.0);
+            for vertex in corners {
+                assert!(close(vertex.tangent.xyz(), if left { -Vec3::x() } else { Vec3::x() }));
+                assert_eq!(vertex.tangent.w, if left { -1.0 } else { 1.0 });
+            }
+        }
+    }
+}
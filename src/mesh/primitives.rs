@@ -0,0 +1,304 @@
+// mesh/primitives.rs
+// Procedural meshes, so scenes do not depend on model files. Every generator
+// returns unit normals and texture coordinates, with counter-clockwise
+// triangles when seen from the outside (the front face for culling).
+use super::Mesh;
+use crate::vertex::Vertex;
+use nalgebra_glm::{Vec2, Vec3};
+use raylib::prelude::Color;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// Latitude/longitude sphere. `segments` divide the equator and `rings` go from
+// pole to pole; u follows the longitude and v goes from the south pole (0) to
+// the north pole (1)
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Mesh {
+    parametric(segments.max(3), rings.max(2), |u, v| {
+        let direction = sphere_direction(2.0 * PI * u, PI * (v - 0.5), v == 0.0 || v == 1.0);
+        (direction * radius, direction)
+    })
+}
+
+// Geodesic sphere: an icosahedron whose triangles are split in four
+// `subdivisions` times (20 * 4^n triangles), so they all have similar sizes.
+// Texture coordinates use the same mapping as `uv_sphere`
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalize())
+    .collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Each edge is split once, shared by the two triangles next to it
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                (positions.len() - 1) as u32
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // The longitude wraps around, so triangles crossing the seam get copies of
+    // their vertices with u shifted by one, and vertices on a pole (where the
+    // longitude is undefined) get one copy per triangle using its mean u
+    let mut vertices = Vec::new();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    let mut lookup: HashMap<(u32, u32), u32> = HashMap::new();
+
+    for triangle in triangles {
+        let directions = triangle.map(|index| positions[index as usize]);
+        let on_pole = directions.map(|d| d.x * d.x + d.z * d.z < 1e-10);
+        let mut uvs = directions.map(|d| Vec2::new((-d.z).atan2(d.x) / (2.0 * PI), d.y.asin() / PI + 0.5));
+        for uv in uvs.iter_mut() {
+            uv.x = uv.x.rem_euclid(1.0);
+        }
+
+        let longitudes: Vec<f32> = (0..3).filter(|&i| !on_pole[i]).map(|i| uvs[i].x).collect();
+        let crosses_seam = longitudes.iter().any(|&u| u > 0.75) && longitudes.iter().any(|&u| u < 0.25);
+        for uv in uvs.iter_mut() {
+            if crosses_seam && uv.x < 0.5 {
+                uv.x += 1.0;
+            }
+        }
+        let longitudes: Vec<f32> = (0..3).filter(|&i| !on_pole[i]).map(|i| uvs[i].x).collect();
+        let mean_longitude = longitudes.iter().sum::<f32>() / longitudes.len() as f32;
+
+        for i in 0..3 {
+            if on_pole[i] {
+                uvs[i].x = mean_longitude;
+            }
+            let index = *lookup.entry((triangle[i], uvs[i].x.to_bits())).or_insert_with(|| {
+                let mut vertex = Vertex::new(directions[i] * radius, directions[i], Color::WHITE);
+                vertex.tex_coords = uvs[i];
+                vertices.push(vertex);
+                (vertices.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+    }
+
+    Mesh::new(vertices, indices)
+}
+
+// Axis-aligned cube centered at the origin. Faces do not share vertices so
+// each keeps its own normal, and each face maps the whole texture
+pub fn cube(size: f32) -> Mesh {
+    let half = size / 2.0;
+    // Normal and the two in-face axes of each face, with u x v = normal
+    let faces = [
+        (Vec3::x(), -Vec3::z(), Vec3::y()),
+        (-Vec3::x(), Vec3::z(), Vec3::y()),
+        (Vec3::y(), Vec3::x(), -Vec3::z()),
+        (-Vec3::y(), Vec3::x(), Vec3::z()),
+        (Vec3::z(), Vec3::x(), Vec3::y()),
+        (-Vec3::z(), -Vec3::x(), Vec3::y()),
+    ];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (normal, u, v) in faces {
+        let first = vertices.len() as u32;
+        for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            let position = (normal + u * (2.0 * s - 1.0) + v * (2.0 * t - 1.0)) * half;
+            let mut vertex = Vertex::new(position, normal, Color::WHITE);
+            vertex.tex_coords = Vec2::new(s, t);
+            vertices.push(vertex);
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    Mesh::new(vertices, indices)
+}
+
+// Plane on XZ facing +Y, split in `subdivisions` x `subdivisions` quads
+pub fn plane(width: f32, depth: f32, subdivisions: usize) -> Mesh {
+    parametric(subdivisions.max(1), subdivisions.max(1), |u, v| {
+        (Vec3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth), Vec3::y())
+    })
+}
+
+// Torus around the Y axis. `major_radius` goes from the center to the middle
+// of the tube, `segments` split the ring and `sides` the tube
+pub fn torus(major_radius: f32, minor_radius: f32, segments: usize, sides: usize) -> Mesh {
+    parametric(segments.max(3), sides.max(3), |u, v| {
+        let (sin_phi, cos_phi) = (2.0 * PI * u).sin_cos();
+        let (sin_psi, cos_psi) = (2.0 * PI * v).sin_cos();
+        let normal = Vec3::new(cos_psi * cos_phi, sin_psi, -cos_psi * sin_phi);
+        let center = Vec3::new(cos_phi, 0.0, -sin_phi) * major_radius;
+        (center + normal * minor_radius, normal)
+    })
+}
+
+// Flat ring (annulus) on XZ facing +Y, e.g. for planetary rings. u goes from
+// the inner (0) to the outer (1) edge and v around the ring. Seen from below
+// it is back-facing, so draw it without culling to see both sides
+pub fn ring(inner_radius: f32, outer_radius: f32, segments: usize) -> Mesh {
+    parametric(1, segments.max(3), |u, v| {
+        let (sin_phi, cos_phi) = (2.0 * PI * v).sin_cos();
+        let radius = inner_radius + (outer_radius - inner_radius) * u;
+        (Vec3::new(cos_phi, 0.0, -sin_phi) * radius, Vec3::y())
+    })
+}
+
+// Direction on the unit sphere for a longitude and latitude. At the poles the
+// longitude is ignored so all the pole vertices coincide exactly
+fn sphere_direction(longitude: f32, latitude: f32, pole: bool) -> Vec3 {
+    let (sin_lat, cos_lat) = latitude.sin_cos();
+    if pole {
+        return Vec3::new(0.0, sin_lat.signum(), 0.0);
+    }
+    let (sin_lon, cos_lon) = longitude.sin_cos();
+    Vec3::new(cos_lat * cos_lon, sin_lat, -cos_lat * sin_lon)
+}
+
+// Samples a surface (u, v) -> (position, normal) on a (columns + 1) x (rows + 1)
+// grid with (u, v) as texture coordinates, and joins the grid in quads. The
+// surface must be oriented so that dp/du x dp/dv points outside. Triangles
+// collapsed to a line (at the poles of a sphere) are left out
+fn parametric(columns: usize, rows: usize, surface: impl Fn(f32, f32) -> (Vec3, Vec3)) -> Mesh {
+    let mut vertices = Vec::with_capacity((columns + 1) * (rows + 1));
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let (u, v) = (column as f32 / columns as f32, row as f32 / rows as f32);
+            let (position, normal) = surface(u, v);
+            let mut vertex = Vertex::new(position, normal, Color::WHITE);
+            vertex.tex_coords = Vec2::new(u, v);
+            vertices.push(vertex);
+        }
+    }
+
+    let index = |row: usize, column: usize| (row * (columns + 1) + column) as u32;
+    let mut indices = Vec::with_capacity(columns * rows * 6);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (a, b) = (index(row, column), index(row, column + 1));
+            let (c, d) = (index(row + 1, column + 1), index(row + 1, column));
+            for triangle in [[a, b, c], [a, c, d]] {
+                let [p0, p1, p2] = triangle.map(|i| vertices[i as usize].position);
+                if p0 != p1 && p1 != p2 && p2 != p0 {
+                    indices.extend_from_slice(&triangle);
+                }
+            }
+        }
+    }
+
+    Mesh::new(vertices, indices)
+}
+
+// Builds a primitive from a description such as "icosphere:5" or "torus:48:24":
+// the generator name followed by its optional resolution parameters, sized to
+// fit the demo scene. None when the name or a parameter is not recognized
+pub fn from_spec(spec: &str) -> Option<Mesh> {
+    let mut parts = spec.split(':');
+    let name = parts.next()?;
+    let params: Vec<usize> = parts.map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let param = |index: usize, default: usize| params.get(index).copied().unwrap_or(default);
+
+    match name {
+        "uvsphere" => Some(uv_sphere(0.5, param(0, 32), param(1, 16))),
+        "icosphere" => Some(icosphere(0.5, param(0, 4).min(8) as u32)),
+        "cube" => Some(cube(0.8)),
+        "plane" => Some(plane(1.0, 1.0, param(0, 1))),
+        "torus" => Some(torus(0.35, 0.15, param(0, 32), param(1, 16))),
+        "ring" => Some(ring(0.3, 0.5, param(0, 64))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Point the normal of a vertex at a given position points away from
+    type Inside = fn(Vec3) -> Vec3;
+
+    // Every generator with its inside (the tube center for the torus)
+    fn generated() -> Vec<(&'static str, Mesh, Inside)> {
+        fn origin(_: Vec3) -> Vec3 {
+            Vec3::zeros()
+        }
+        fn below(position: Vec3) -> Vec3 {
+            Vec3::new(position.x, position.y - 1.0, position.z)
+        }
+        fn tube_center(position: Vec3) -> Vec3 {
+            Vec3::new(position.x, 0.0, position.z).normalize() * 2.0
+        }
+        vec![
+            ("uv_sphere", uv_sphere(1.5, 16, 8), origin),
+            ("icosphere", icosphere(1.5, 2), origin),
+            ("cube", cube(2.0), origin),
+            ("plane", plane(2.0, 3.0, 4), below),
+            ("torus", torus(2.0, 0.5, 24, 12), tube_center),
+            ("ring", ring(1.0, 2.0, 32), below),
+        ]
+    }
+
+    #[test]
+    fn normals_are_unit_length_and_point_outwards() {
+        for (name, mesh, inside) in generated() {
+            assert!(mesh.triangle_count() > 0, "{}", name);
+            for vertex in &mesh.vertices {
+                assert!((vertex.normal.norm() - 1.0).abs() < 1e-5, "{}: normal {:?}", name, vertex.normal);
+                let outwards = vertex.position - inside(vertex.position);
+                assert!(vertex.normal.dot(&outwards.normalize()) > 0.5, "{}: normal {:?} at {:?}", name, vertex.normal, vertex.position);
+            }
+
+            // Triangles are counter-clockwise seen from the side the normals point to
+            for [a, b, c] in mesh.triangles().map(|triangle| triangle.map(|index| mesh.vertices[index as usize])) {
+                let face = (b.position - a.position).cross(&(c.position - a.position));
+                assert!(face.norm() > 0.0, "{}: degenerate triangle", name);
+                assert!(face.dot(&(a.normal + b.normal + c.normal)) > 0.0, "{}: triangle facing inwards", name);
+            }
+        }
+    }
+
+    #[test]
+    fn texture_coordinates_stay_in_range() {
+        for (name, mesh, _) in generated() {
+            for vertex in &mesh.vertices {
+                let (u, v) = (vertex.tex_coords.x, vertex.tex_coords.y);
+                assert!((0.0..=1.0).contains(&v), "{}: v = {}", name, v);
+                // Icosphere triangles across the seam get copies of their
+                // vertices with u shifted past 1, so they do not wrap backwards
+                let max_u = if name == "icosphere" { 1.5 } else { 1.0 };
+                assert!((0.0..=max_u).contains(&u), "{}: u = {}", name, u);
+            }
+        }
+
+        // The seam copies only span the seam: no triangle stretches over half the texture
+        let sphere = icosphere(1.0, 3);
+        for triangle in sphere.triangles() {
+            let us = triangle.map(|index| sphere.vertices[index as usize].tex_coords.x);
+            assert!(us.iter().fold(0.0f32, |max, &u| max.max(u)) - us.iter().fold(2.0f32, |min, &u| min.min(u)) < 0.5);
+        }
+    }
+
+    #[test]
+    fn builds_primitives_from_specs() {
+        assert_eq!(from_spec("icosphere:2").unwrap().triangle_count(), 20 * 16);
+        assert_eq!(from_spec("cube").unwrap().triangle_count(), 12);
+        assert_eq!(from_spec("torus:8:4").unwrap().triangle_count(), 8 * 4 * 2);
+        assert!(from_spec("sphere").is_none());
+        assert!(from_spec("torus:many").is_none());
+    }
+}
@@ -0,0 +1,361 @@
+// mesh/simplify.rs
+// Edge-collapse simplification with quadric error metrics (Garland and
+// Heckbert): every point accumulates the planes of its triangles, and the edge
+// whose collapse moves the surface the least is collapsed first. Texture and
+// normal seams are kept: a point split in several vertices moves all of them,
+// and the attributes of the surviving vertices are left as they were.
+use super::{Mesh, SubMesh};
+use nalgebra_glm::{DMat3, DVec3, Vec3};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// Boundary edges get planes perpendicular to their triangle with this weight,
+// so open borders do not shrink
+const BOUNDARY_WEIGHT: f64 = 100.0;
+
+// Symmetric 4x4 matrix of the squared distance to a set of planes, upper
+// triangle only
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // Plane through `point` with unit `normal`, scaled by `weight`
+    fn plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let [a, b, c] = [normal.x, normal.y, normal.z];
+        let d = -normal.dot(&point);
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|value| value * weight))
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = self.0;
+        for (value, other) in sum.iter_mut().zip(other.0) {
+            *value += other;
+        }
+        Quadric(sum)
+    }
+
+    fn error(&self, p: DVec3) -> f64 {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        a2 * x * x + 2.0 * ab * x * y + 2.0 * ac * x * z + 2.0 * ad * x
+            + b2 * y * y + 2.0 * bc * y * z + 2.0 * bd * y
+            + c2 * z * z + 2.0 * cd * z
+            + d2
+    }
+
+    // Position with the least error, None when the planes do not fix a single
+    // point (flat or cylindrical neighborhoods)
+    fn minimum(&self) -> Option<DVec3> {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, _] = self.0;
+        let matrix = DMat3::new(a2, ab, ac, ab, b2, bc, ac, bc, c2);
+        matrix.try_inverse().map(|inverse| -(inverse * DVec3::new(ad, bd, cd)))
+    }
+}
+
+// Edge waiting in the queue. The versions of its points tell whether it is
+// outdated because one of them changed since
+struct Candidate {
+    error: f64,
+    points: (usize, usize),
+    versions: (u32, u32),
+    position: DVec3,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // Reversed, so the max-heap pops the smallest error first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.total_cmp(&self.error)
+    }
+}
+
+struct Simplifier {
+    positions: Vec<DVec3>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    point_of_vertex: Vec<usize>,
+    vertices_of: Vec<Vec<u32>>, // Vertices of every point
+    triangles: Vec<[u32; 3]>, // Vertex indices
+    removed: Vec<bool>,
+    triangles_at: Vec<Vec<usize>>, // Triangles around every point, removed ones included
+}
+
+impl Simplifier {
+    fn new(mesh: &Mesh) -> Self {
+        let (point_of_vertex, point_count) = mesh.weld_points();
+        let mut positions = vec![DVec3::zeros(); point_count];
+        let mut vertices_of = vec![Vec::new(); point_count];
+        for (index, vertex) in mesh.vertices.iter().enumerate() {
+            positions[point_of_vertex[index]] = vertex.position.cast();
+            vertices_of[point_of_vertex[index]].push(index as u32);
+        }
+
+        let triangles: Vec<[u32; 3]> = mesh.triangles().collect();
+        let mut simplifier = Simplifier {
+            positions,
+            quadrics: vec![Quadric::default(); point_count],
+            versions: vec![0; point_count],
+            point_of_vertex,
+            vertices_of,
+            removed: vec![false; triangles.len()],
+            triangles_at: vec![Vec::new(); point_count],
+            triangles,
+        };
+
+        let mut edge_uses: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for triangle in 0..simplifier.triangles.len() {
+            let points = simplifier.points(triangle);
+            let [a, b, c] = points.map(|p| simplifier.positions[p]);
+            let cross = (b - a).cross(&(c - a));
+            let area = cross.norm() / 2.0;
+            for (corner, &p) in points.iter().enumerate() {
+                if area > 0.0 {
+                    simplifier.quadrics[p] = simplifier.quadrics[p].add(&Quadric::plane(cross.normalize(), a, area));
+                }
+                simplifier.triangles_at[p].push(triangle);
+                let next = points[(corner + 1) % 3];
+                edge_uses.entry((p.min(next), p.max(next))).or_default().push(triangle);
+            }
+        }
+
+        // Planes through the boundary edges, perpendicular to their triangle
+        for (&(a, b), triangles) in &edge_uses {
+            if let &[triangle] = triangles.as_slice() {
+                let [p0, p1, p2] = simplifier.points(triangle).map(|p| simplifier.positions[p]);
+                let face_normal = (p1 - p0).cross(&(p2 - p0));
+                let edge = simplifier.positions[b] - simplifier.positions[a];
+                let normal = edge.cross(&face_normal);
+                if normal.norm() > 0.0 {
+                    let plane = Quadric::plane(normal.normalize(), simplifier.positions[a], BOUNDARY_WEIGHT * edge.norm_squared());
+                    simplifier.quadrics[a] = simplifier.quadrics[a].add(&plane);
+                    simplifier.quadrics[b] = simplifier.quadrics[b].add(&plane);
+                }
+            }
+        }
+        simplifier
+    }
+
+    fn points(&self, triangle: usize) -> [usize; 3] {
+        self.triangles[triangle].map(|index| self.point_of_vertex[index as usize])
+    }
+
+    fn live_triangles(&self, point: usize) -> impl Iterator<Item = usize> + '_ {
+        self.triangles_at[point].iter().copied().filter(move |&triangle| !self.removed[triangle])
+    }
+
+    fn neighbors(&self, point: usize) -> HashSet<usize> {
+        self.live_triangles(point).flat_map(|triangle| self.points(triangle)).filter(|&p| p != point).collect()
+    }
+
+    // Error and position of collapsing an edge: the quadric minimum when it
+    // exists near the edge (a nearly singular quadric can put it anywhere),
+    // else the best of the end points and the midpoint
+    fn candidate(&self, a: usize, b: usize) -> Candidate {
+        let quadric = self.quadrics[a].add(&self.quadrics[b]);
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        let midpoint = (pa + pb) / 2.0;
+        let position = match quadric.minimum() {
+            Some(minimum) if (minimum - midpoint).norm() <= (pb - pa).norm() => minimum,
+            _ => [pa, pb, midpoint].into_iter().min_by(|x, y| quadric.error(*x).total_cmp(&quadric.error(*y))).unwrap(),
+        };
+        Candidate { error: quadric.error(position), points: (a, b), versions: (self.versions[a], self.versions[b]), position }
+    }
+
+    // Merges point `b` into `a` at `position`. Refused when it would fold a
+    // triangle over or pinch the surface into a non-manifold shape
+    fn collapse(&mut self, a: usize, b: usize, position: DVec3) -> Option<usize> {
+        let shared: Vec<usize> = self.live_triangles(b).filter(|&triangle| self.points(triangle).contains(&a)).collect();
+        // Link condition: the only points next to both are the ones across the shared triangles
+        if self.neighbors(a).intersection(&self.neighbors(b)).count() != shared.len() {
+            return None;
+        }
+
+        let moved: Vec<usize> = self.live_triangles(a).chain(self.live_triangles(b)).filter(|t| !shared.contains(t)).collect();
+        for &triangle in &moved {
+            let points = self.points(triangle);
+            let before = points.map(|p| self.positions[p]);
+            let after = points.map(|p| if p == a || p == b { position } else { self.positions[p] });
+            let normal = |[p0, p1, p2]: [DVec3; 3]| (p1 - p0).cross(&(p2 - p0));
+            if normal(before).dot(&normal(after)) <= 0.0 {
+                return None;
+            }
+        }
+
+        // Vertices of `b` take the vertex of `a` they share a triangle with, so
+        // both sides of an attribute seam stay apart
+        let mut replacement: Vec<(u32, u32)> = Vec::new();
+        for &triangle in &shared {
+            let corners = self.triangles[triangle];
+            let find = |point: usize| corners.iter().copied().find(|&index| self.point_of_vertex[index as usize] == point);
+            if let (Some(from), Some(to)) = (find(b), find(a)) {
+                replacement.push((from, to));
+            }
+            self.removed[triangle] = true;
+        }
+        for &triangle in &moved {
+            for index in self.triangles[triangle].iter_mut() {
+                if let Some(&(_, to)) = replacement.iter().find(|(from, _)| from == index) {
+                    *index = to;
+                }
+            }
+        }
+        let vertices_of_b = std::mem::take(&mut self.vertices_of[b]);
+        for &index in &vertices_of_b {
+            self.point_of_vertex[index as usize] = a;
+        }
+        self.vertices_of[a].extend(vertices_of_b);
+
+        let triangles_of_b = std::mem::take(&mut self.triangles_at[b]);
+        self.triangles_at[a].extend(triangles_of_b);
+        self.positions[a] = position;
+        self.quadrics[a] = self.quadrics[a].add(&self.quadrics[b]);
+        self.versions[a] += 1;
+        self.versions[b] += 1;
+        Some(shared.len())
+    }
+}
+
+impl Mesh {
+    // Copy of the mesh reduced to about `target_triangles` triangles by
+    // collapsing edges. Stops earlier when no collapse keeps the surface
+    // valid. Sub-meshes keep their triangles in order
+    pub fn simplify(&self, target_triangles: usize) -> Mesh {
+        let mut simplifier = Simplifier::new(self);
+        let mut remaining = self.triangle_count();
+
+        let mut queue = BinaryHeap::new();
+        let mut edges = HashSet::new();
+        for triangle in 0..simplifier.triangles.len() {
+            let [a, b, c] = simplifier.points(triangle);
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                if from != to && edges.insert((from.min(to), from.max(to))) {
+                    queue.push(simplifier.candidate(from.min(to), from.max(to)));
+                }
+            }
+        }
+
+        while remaining > target_triangles {
+            let Some(candidate) = queue.pop() else { break };
+            let (a, b) = candidate.points;
+            if candidate.versions != (simplifier.versions[a], simplifier.versions[b]) {
+                continue; // Outdated, the edge was queued again when it changed
+            }
+            let Some(removed) = simplifier.collapse(a, b, candidate.position) else { continue };
+            remaining -= removed;
+            for neighbor in simplifier.neighbors(a) {
+                queue.push(simplifier.candidate(a, neighbor));
+            }
+        }
+
+        self.rebuilt_from(&simplifier)
+    }
+
+    fn rebuilt_from(&self, simplifier: &Simplifier) -> Mesh {
+        let mut vertices = Vec::new();
+        let mut new_index = vec![u32::MAX; self.vertices.len()];
+        let mut indices = Vec::new();
+        let mut kept_before = Vec::with_capacity(simplifier.triangles.len() + 1); // Triangles kept before each one
+        for (triangle, corners) in simplifier.triangles.iter().enumerate() {
+            kept_before.push(indices.len() / 3);
+            if simplifier.removed[triangle] {
+                continue;
+            }
+            for &index in corners {
+                if new_index[index as usize] == u32::MAX {
+                    let position: Vec3 = simplifier.positions[simplifier.point_of_vertex[index as usize]].cast();
+                    let mut vertex = self.vertices[index as usize];
+                    vertex.position = position;
+                    vertices.push(vertex);
+                    new_index[index as usize] = (vertices.len() - 1) as u32;
+                }
+                indices.push(new_index[index as usize]);
+            }
+        }
+        kept_before.push(indices.len() / 3);
+
+        let mut mesh = Mesh::new(vertices, indices);
+        mesh.materials = self.materials.clone();
+        mesh.submeshes = self
+            .submeshes
+            .iter()
+            .map(|submesh| {
+                let first = kept_before[submesh.first_index / 3];
+                let end = kept_before[(submesh.first_index + submesh.index_count) / 3];
+                SubMesh { first_index: first * 3, index_count: (end - first) * 3, ..submesh.clone() }
+            })
+            .collect();
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    // Every edge collapse removes the two triangles next to the edge
+    fn assert_reaches(mesh: &Mesh, target: usize) -> Mesh {
+        let simplified = mesh.simplify(target);
+        let count = simplified.triangle_count();
+        assert!(count <= target && count + 2 > target, "{} triangles for a target of {}", count, target);
+        assert!(simplified.indices.iter().all(|&index| (index as usize) < simplified.vertices.len()));
+        simplified
+    }
+
+    #[test]
+    fn reaches_the_target_and_keeps_spheres_closed() {
+        let sphere = primitives::icosphere(1.0, 3);
+        for target in [640, 321, 80] {
+            let simplified = assert_reaches(&sphere, target);
+            assert!(simplified.is_closed(), "open surface at {} triangles", target);
+            // The collapsed points stay close to the surface
+            for vertex in &simplified.vertices {
+                assert!((vertex.position.norm() - 1.0).abs() < 0.1);
+            }
+        }
+
+        // Nothing to do when the mesh is already small enough
+        assert_eq!(sphere.simplify(sphere.triangle_count()).triangle_count(), sphere.triangle_count());
+    }
+
+    #[test]
+    fn keeps_sub_mesh_ranges_valid() {
+        let mut mesh = primitives::uv_sphere(1.0, 24, 12);
+        let third = mesh.indices.len() / 3 / 3 * 3;
+        mesh.submeshes = vec![
+            SubMesh { name: "top".to_string(), first_index: 0, index_count: third, material: Some(0) },
+            SubMesh { name: "rest".to_string(), first_index: third, index_count: mesh.indices.len() - third, material: None },
+        ];
+        let simplified = assert_reaches(&mesh, mesh.triangle_count() / 4);
+
+        let [top, rest] = [&simplified.submeshes[0], &simplified.submeshes[1]];
+        assert_eq!((top.first_index, top.material), (0, Some(0)));
+        assert_eq!(rest.first_index, top.index_count);
+        assert_eq!(rest.first_index + rest.index_count, simplified.indices.len());
+        assert!(top.index_count > 0 && top.index_count % 3 == 0 && rest.index_count > 0);
+    }
+
+    #[test]
+    fn keeps_open_borders_in_place() {
+        let plane = primitives::plane(2.0, 2.0, 8);
+        let simplified = assert_reaches(&plane, 32);
+        for vertex in &simplified.vertices {
+            assert!(vertex.position.y.abs() < 1e-5);
+        }
+        let extent = simplified.vertices.iter().fold(0.0f32, |extent, vertex| extent.max(vertex.position.x.abs()).max(vertex.position.z.abs()));
+        assert!((extent - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,195 @@
+// mesh/subdivision.rs
+// Loop subdivision: every triangle is split in four and the points are moved
+// towards the smooth limit surface. Positions follow the Loop masks over the
+// surface welded by position, while texture coordinates, normals and colors
+// are interpolated linearly, so texture seams and hard edges stay where they
+// were.
+use super::{Mesh, SubMesh};
+use crate::vertex::Vertex;
+use nalgebra_glm::Vec3;
+use std::collections::HashMap;
+
+impl Mesh {
+    // One level of Loop subdivision (4 times the triangles). Boundary edges,
+    // and edges shared by more than two triangles, are kept as creases.
+    // New vertices get the normalized mean of their edge's normals, and
+    // tangents are recomputed when the mesh had them
+    pub fn subdivide_loop(&self) -> Mesh {
+        let (point_of_vertex, point_count) = self.weld_points();
+        let point = |index: u32| point_of_vertex[index as usize];
+
+        // Point of every edge's triangles opposite to it
+        let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for [a, b, c] in self.triangles().map(|triangle| triangle.map(point)) {
+            for (from, to, opposite) in [(a, b, c), (b, c, a), (c, a, b)] {
+                edges.entry((from.min(to), from.max(to))).or_default().push(opposite);
+            }
+        }
+
+        let mut positions = vec![Vec3::zeros(); point_count];
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            positions[point_of_vertex[index]] = vertex.position;
+        }
+
+        // Old points: the weighted mean of their neighbors, or only of their
+        // crease neighbors when they lie on a crease
+        let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); point_count];
+        let mut crease_neighbors: Vec<Vec<usize>> = vec![Vec::new(); point_count];
+        for (&(a, b), opposites) in &edges {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+            if opposites.len() != 2 {
+                crease_neighbors[a].push(b);
+                crease_neighbors[b].push(a);
+            }
+        }
+        let smoothed: Vec<Vec3> = (0..point_count)
+            .map(|p| match (crease_neighbors[p].as_slice(), neighbors[p].len()) {
+                ([], 0) => positions[p],
+                ([], valence) => {
+                    let beta = if valence == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * valence as f32) };
+                    let sum: Vec3 = neighbors[p].iter().map(|&n| positions[n]).sum();
+                    positions[p] * (1.0 - valence as f32 * beta) + sum * beta
+                }
+                (&[a, b], _) => positions[p] * 0.75 + (positions[a] + positions[b]) * 0.125,
+                _ => positions[p], // Corner of several creases
+            })
+            .collect();
+
+        let edge_point = |a: usize, b: usize| match edges[&(a.min(b), a.max(b))].as_slice() {
+            &[c, d] => (positions[a] + positions[b]) * 0.375 + (positions[c] + positions[d]) * 0.125,
+            _ => (positions[a] + positions[b]) * 0.5,
+        };
+
+        // Old vertices keep their attributes; vertices on an edge are shared by
+        // the two triangles only when both use the same pair of vertices
+        let mut vertices: Vec<Vertex> = self
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(index, vertex)| Vertex { position: smoothed[point_of_vertex[index]], ..*vertex })
+            .collect();
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let vertex = self.vertices[a as usize].lerp(&self.vertices[b as usize], 0.5);
+                let normal = if vertex.normal.norm() > 0.0 { vertex.normal.normalize() } else { vertex.normal };
+                vertices.push(Vertex { position: edge_point(point(a), point(b)), normal, ..vertex });
+                (vertices.len() - 1) as u32
+            })
+        };
+
+        let mut indices = Vec::with_capacity(self.indices.len() * 4);
+        for [a, b, c] in self.triangles() {
+            let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+            indices.extend_from_slice(&[a, ab, ca, b, bc, ab, c, ca, bc, ab, bc, ca]);
+        }
+
+        let mut mesh = Mesh::new(vertices, indices);
+        mesh.materials = self.materials.clone();
+        mesh.submeshes = self
+            .submeshes
+            .iter()
+            .map(|submesh| SubMesh { first_index: submesh.first_index * 4, index_count: submesh.index_count * 4, ..submesh.clone() })
+            .collect();
+        if !self.has_missing_tangents() {
+            mesh.compute_tangents();
+        }
+        mesh
+    }
+
+    // Moves every vertex onto a sphere along its direction from the center,
+    // with the radial direction as normal. Used after subdividing a sphere,
+    // since Loop subdivision shrinks it a little at every level
+    pub fn project_onto_sphere(&mut self, center: Vec3, radius: f32) {
+        for vertex in self.vertices.iter_mut() {
+            let direction = vertex.position - center;
+            if direction.norm() > 0.0 {
+                vertex.normal = direction.normalize();
+                vertex.position = center + vertex.normal * radius;
+            }
+        }
+    }
+
+    // Center and radius of the sphere the mesh approximates, when every
+    // vertex lies within 1% of the radius from it. None for other shapes
+    pub fn fitted_sphere(&self) -> Option<(Vec3, f32)> {
+        if self.vertices.is_empty() {
+            return None;
+        }
+        let (min, max) = self.vertices.iter().fold((Vec3::repeat(f32::MAX), Vec3::repeat(f32::MIN)), |(min, max), vertex| {
+            (min.inf(&vertex.position), max.sup(&vertex.position))
+        });
+        let center = (min + max) / 2.0;
+        let radius = self.vertices.iter().map(|vertex| (vertex.position - center).norm()).sum::<f32>() / self.vertices.len() as f32;
+        let spherical = radius > 0.0
+            && self.vertices.iter().all(|vertex| ((vertex.position - center).norm() - radius).abs() <= radius * 0.01);
+        spherical.then_some((center, radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    #[test]
+    fn splits_every_triangle_in_four() {
+        let mut mesh = primitives::icosphere(1.0, 1);
+        let half = mesh.indices.len() / 2 / 3 * 3;
+        mesh.submeshes = vec![
+            SubMesh { name: "north".to_string(), first_index: 0, index_count: half, material: None },
+            SubMesh { name: "south".to_string(), first_index: half, index_count: mesh.indices.len() - half, material: None },
+        ];
+        let subdivided = mesh.subdivide_loop();
+
+        assert_eq!(subdivided.triangle_count(), mesh.triangle_count() * 4);
+        assert!(subdivided.indices.iter().all(|&index| (index as usize) < subdivided.vertices.len()));
+        assert_eq!(subdivided.submeshes[0].index_count, half * 4);
+        assert_eq!(subdivided.submeshes[1].first_index, half * 4);
+        assert_eq!(subdivided.submeshes[1].first_index + subdivided.submeshes[1].index_count, subdivided.indices.len());
+    }
+
+    #[test]
+    fn keeps_closed_surfaces_closed() {
+        // The icosphere has texture seams, so this also covers points split
+        // in several vertices
+        let mesh = primitives::icosphere(1.0, 2);
+        assert!(mesh.is_closed());
+        let subdivided = mesh.subdivide_loop();
+        assert!(subdivided.is_closed());
+        assert!(subdivided.subdivide_loop().is_closed());
+    }
+
+    #[test]
+    fn interpolates_normals_across_hard_edges() {
+        // Cube faces share no vertices, so every new vertex keeps its face normal
+        let cube = primitives::cube(2.0);
+        let subdivided = cube.subdivide_loop();
+        for vertex in &subdivided.vertices {
+            assert!((vertex.normal.norm() - 1.0).abs() < 1e-6);
+            assert_eq!(vertex.normal.abs().max(), 1.0, "normal {:?} is not a face normal", vertex.normal);
+        }
+
+        // On a smooth sphere the normals stay close to the radial direction
+        let sphere = primitives::uv_sphere(1.0, 16, 8).subdivide_loop();
+        for vertex in &sphere.vertices {
+            assert!((vertex.normal.norm() - 1.0).abs() < 1e-5);
+            assert!(vertex.normal.dot(&vertex.position.normalize()) > 0.95);
+        }
+    }
+
+    #[test]
+    fn projects_vertices_onto_the_sphere() {
+        let center = Vec3::new(1.0, -2.0, 0.5);
+        let mut mesh = primitives::icosphere(1.5, 1).transformed(&nalgebra_glm::translation(&center)).subdivide_loop();
+        let (fitted_center, radius) = mesh.fitted_sphere().unwrap();
+        assert!((fitted_center - center).norm() < 0.05 && radius < 1.5);
+
+        mesh.project_onto_sphere(center, 1.5);
+        for vertex in &mesh.vertices {
+            assert!(((vertex.position - center).norm() - 1.5).abs() < 1e-5);
+            assert!((vertex.normal - (vertex.position - center) / 1.5).norm() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// pipeline.rs
+// Geometry stages shared by every rasterizer: primitive assembly walks the
+// index buffer and fetches each vertex through a post-transform cache, so the
+// vertex stage runs once per referenced mesh vertex and per mesh drawn. The
+// assembled triangles then go on to clipping and rasterization.
+use crate::framebuffer::Framebuffer;
+use crate::mesh::Mesh;
+use crate::shaders::Shader;
+use crate::stats::RenderStats;
+use crate::triangle::{triangle, Uniforms};
+use crate::vertex::Vertex;
+use nalgebra_glm::{Mat3, Mat4};
+use std::ops::Range;
+
+// Matrices the vertex stage needs, computed once per draw call instead of
+// once per vertex
+pub struct VertexTransform {
+    pub mvp_matrix: Mat4,
+    pub normal_matrix: Mat3, // Inverse transpose of the model matrix, keeps normals perpendicular under non-uniform scaling
+}
+
+impl VertexTransform {
+    pub fn new(uniforms: &Uniforms) -> Self {
+        let model = nalgebra_glm::mat4_to_mat3(&uniforms.model_matrix);
+        VertexTransform {
+            mvp_matrix: uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix,
+            normal_matrix: model.try_inverse().map(|inverse| inverse.transpose()).unwrap_or(model),
+        }
+    }
+}
+
+// Post-transform vertex cache. A vertex goes through the vertex shader the
+// first time an index refers to it; later references reuse the result
+pub struct VertexCache<'a, S: Shader> {
+    vertices: &'a [Vertex],
+    uniforms: &'a Uniforms,
+    shader: &'a S,
+    transform: VertexTransform,
+    transformed: Vec<Option<Vertex>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<'a, S: Shader> VertexCache<'a, S> {
+    pub fn new(vertices: &'a [Vertex], uniforms: &'a Uniforms, shader: &'a S) -> Self {
+        VertexCache {
+            vertices,
+            uniforms,
+            shader,
+            transform: VertexTransform::new(uniforms),
+            transformed: vec![None; vertices.len()],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    // Vertex stage: runs the shader's vertex program on a cache miss, filling
+    // in `transformed_position` (clip space) and `transformed_normal`. None
+    // when the index is past the end of the vertex buffer
+    pub fn fetch(&mut self, index: u32) -> Option<Vertex> {
+        let index = index as usize;
+        match *self.transformed.get(index)? {
+            Some(vertex) => {
+                self.hits += 1;
+                Some(vertex)
+            }
+            None => {
+                self.misses += 1;
+                let vertex = self.shader.vertex_shader(&self.vertices[index], self.uniforms, &self.transform);
+                self.transformed[index] = Some(vertex);
+                Some(vertex)
+            }
+        }
+    }
+}
+
+// Splits drawing a mesh into one call per sub-mesh, with the sub-mesh material
+// set in the uniforms. Returns the range of the index buffer each call draws;
+// indices outside every sub-mesh are drawn without a material
+pub fn draw_calls(mesh: &Mesh, uniforms: &Uniforms) -> Vec<(Range<usize>, Uniforms)> {
+    let with_material = |material: Option<usize>| Uniforms {
+        material: material.and_then(|material| mesh.materials.get(material)).cloned(),
+        ..uniforms.clone()
+    };
+    let mut submeshes: Vec<_> = mesh.submeshes.iter().collect();
+    submeshes.sort_by_key(|submesh| submesh.first_index);
+
+    let mut calls = Vec::new();
+    let mut next = 0;
+    for submesh in submeshes {
+        let start = submesh.first_index.clamp(next, mesh.indices.len());
+        let end = (submesh.first_index + submesh.index_count).clamp(start, mesh.indices.len());
+        if start > next {
+            calls.push((next..start, with_material(None)));
+        }
+        if end > start {
+            calls.push((start..end, with_material(submesh.material)));
+        }
+        next = end;
+    }
+    if next < mesh.indices.len() {
+        calls.push((next..mesh.indices.len(), with_material(None)));
+    }
+    calls
+}
+
+// Primitive assembly for the draw calls of a mesh: hands the three transformed
+// vertices of every triangle to `f`, together with the index of its draw call
+// and the stats being collected. The calls share one vertex cache, so a vertex
+// used by several sub-meshes is shaded once; the vertex stage gets `uniforms`,
+// as the sub-mesh materials only matter to the fragment stage. Triangles with
+// an index out of range are skipped
+pub fn assemble_triangles<S: Shader>(mesh: &Mesh, calls: &[(Range<usize>, Uniforms)], uniforms: &Uniforms, shader: &S, stats: &mut RenderStats, mut f: impl FnMut(&Vertex, &Vertex, &Vertex, usize, &mut RenderStats)) {
+    let mut cache = VertexCache::new(&mesh.vertices, uniforms, shader);
+    for (call, (range, _)) in calls.iter().enumerate() {
+        for triangle in mesh.indices[range.clone()].chunks_exact(3) {
+            let [i1, i2, i3] = [triangle[0], triangle[1], triangle[2]];
+            let (Some(v1), Some(v2), Some(v3)) = (cache.fetch(i1), cache.fetch(i2), cache.fetch(i3)) else {
+                continue;
+            };
+            f(&v1, &v2, &v3, call, stats);
+        }
+    }
+
+    stats.vertices_shaded += cache.misses;
+    stats.vertex_cache_hits += cache.hits;
+}
+
+// Draws an indexed mesh on the calling thread
+pub fn draw<S: Shader>(mesh: &Mesh, uniforms: &Uniforms, framebuffer: &mut Framebuffer, shader: &S, stats: &mut RenderStats) {
+    let calls = draw_calls(mesh, uniforms);
+    assemble_triangles(mesh, &calls, uniforms, shader, stats, |v1, v2, v3, call, stats| {
+        triangle(v1, v2, v3, &calls[call].1, framebuffer, shader, stats);
+    });
+}
+
+// Copy of a mesh with the shader's displacement applied to every vertex, e.g.
+// to export the surface as it is rendered. Normals are kept as they are
+pub fn bake_displacement<S: Shader>(mesh: &Mesh, uniforms: &Uniforms, shader: &S) -> Mesh {
+    let mut baked = mesh.clone();
+    for vertex in baked.vertices.iter_mut() {
+        vertex.position = shader.displace(vertex, uniforms);
+    }
+    baked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::Fragment;
+    use crate::mesh::{Material, SubMesh};
+    use nalgebra_glm::Vec3;
+    use raylib::prelude::Color;
+    use std::cell::Cell;
+
+    // Counts the vertex stage runs through its displacement hook
+    struct Counting {
+        vertices: Cell<usize>,
+    }
+
+    impl Shader for Counting {
+        fn displace(&self, vertex: &Vertex, _uniforms: &Uniforms) -> Vec3 {
+            self.vertices.set(self.vertices.get() + 1);
+            vertex.position
+        }
+
+        fn fragment_shader(&self, _fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            Color::WHITE
+        }
+    }
+
+    // Unit square made of two triangles over four vertices
+    fn quad(indices: Vec<u32>) -> Mesh {
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        Mesh::new(corners.iter().map(|&(x, y)| Vertex::new(Vec3::new(x, y, 0.0), Vec3::z(), Color::WHITE)).collect(), indices)
+    }
+
+    // Assembles the whole mesh, returning the corners of the triangles handed out and the stats
+    fn assemble(mesh: &Mesh, shader: &Counting) -> (Vec<[Vec3; 3]>, RenderStats) {
+        let uniforms = Uniforms::new();
+        let mut stats = RenderStats::default();
+        let mut triangles = Vec::new();
+        assemble_triangles(mesh, &draw_calls(mesh, &uniforms), &uniforms, shader, &mut stats, |v1, v2, v3, _, _| {
+            triangles.push([v1.position, v2.position, v3.position]);
+        });
+        (triangles, stats)
+    }
+
+    #[test]
+    fn shades_shared_vertices_once() {
+        let shader = Counting { vertices: Cell::new(0) };
+        let mesh = quad(vec![0, 1, 2, 0, 2, 3]);
+        let (triangles, stats) = assemble(&mesh, &shader);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1], [0, 2, 3].map(|index| mesh.vertices[index].position));
+        assert_eq!((stats.vertices_shaded, stats.vertex_cache_hits), (4, 2));
+        assert_eq!(shader.vertices.get(), 4);
+    }
+
+    #[test]
+    fn skips_indices_past_the_end() {
+        let shader = Counting { vertices: Cell::new(0) };
+        let mesh = quad(vec![0, 1, 2]);
+        let uniforms = Uniforms::new();
+        let mut cache = VertexCache::new(&mesh.vertices, &uniforms, &shader);
+        assert!(cache.fetch(4).is_none());
+        assert!(cache.fetch(u32::MAX).is_none());
+        assert!(cache.fetch(3).is_some());
+        assert_eq!((cache.misses, cache.hits, shader.vertices.get()), (1, 0, 1));
+
+        // The triangle with the bad index is dropped, the others are drawn
+        let (triangles, _) = assemble(&quad(vec![0, 1, 2, 0, 2, 9, 0, 2, 3]), &shader);
+        assert_eq!(triangles.len(), 2);
+    }
+
+    #[test]
+    fn splits_draw_calls_per_sub_mesh() {
+        let mut mesh = quad(vec![0, 1, 2, 0, 2, 3, 0, 1, 2, 0, 2, 3, 0, 1, 2]);
+        mesh.materials = vec![Material::new("red"), Material::new("blue")];
+        let submesh = |name: &str, first_index, material| SubMesh { name: name.to_string(), first_index, index_count: 3, material };
+        // Out of order, with gaps between them and a material that does not exist
+        mesh.submeshes = vec![submesh("blue", 6, Some(1)), submesh("red", 0, Some(0)), submesh("missing", 9, Some(7))];
+
+        let mut uniforms = Uniforms::new();
+        uniforms.time = 2.5;
+        let calls = draw_calls(&mesh, &uniforms);
+        let ranges: Vec<Range<usize>> = calls.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, [0..3, 3..6, 6..9, 9..12, 12..15]);
+        let materials: Vec<Option<&str>> = calls.iter().map(|(_, uniforms)| uniforms.material.as_ref().map(|material| material.name.as_str())).collect();
+        assert_eq!(materials, [Some("red"), None, Some("blue"), None, None]);
+        assert!(calls.iter().all(|(_, call)| call.time == 2.5));
+
+        // Without sub-meshes the whole buffer is one call
+        let calls = draw_calls(&quad(vec![0, 1, 2, 0, 2, 3]), &uniforms);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, 0..6);
+    }
+}
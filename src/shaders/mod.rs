@@ -1,2 +1,38 @@
 pub mod star;
-pub mod noise;
\ No newline at end of file
+pub mod noise;
+
+use crate::fragment::Fragment;
+use crate::pipeline::VertexTransform;
+use crate::triangle::Uniforms;
+use crate::vertex::Vertex;
+use nalgebra_glm::{Vec3, Vec4};
+use raylib::prelude::Color;
+
+// Programmable stages of the pipeline. The rasterizer is generic over this
+// trait, so a new surface (planet, moon, debug view) only has to provide its
+// own shading instead of a copy of the rasterizer.
+pub trait Shader {
+    // Displacement hook of the vertex stage: returns the model-space position
+    // the vertex is moved to before it is transformed. No displacement by default
+    fn displace(&self, vertex: &Vertex, _uniforms: &Uniforms) -> Vec3 {
+        vertex.position
+    }
+
+    // Vertex stage: must fill `transformed_position` with the clip-space
+    // position. The default applies the displacement hook and then the usual
+    // model-view-projection transform.
+    fn vertex_shader(&self, vertex: &Vertex, uniforms: &Uniforms, transform: &VertexTransform) -> Vertex {
+        let displaced = self.displace(vertex, uniforms);
+        let position = Vec4::new(displaced.x, displaced.y, displaced.z, 1.0);
+
+        Vertex {
+            position: displaced,
+            transformed_position: transform.mvp_matrix * position,
+            transformed_normal: (transform.normal_matrix * vertex.normal).normalize(),
+            ..*vertex
+        }
+    }
+
+    // Fragment stage: returns the final color of an interpolated fragment
+    fn fragment_shader(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color;
+}
@@ -47,4 +47,51 @@ pub fn fbm_noise(p: (f32, f32), time: f32, octaves: usize, persistence: f32) ->
     }
 
     value / total_amplitude
-}
\ No newline at end of file
+}
+
+// Pseudo-random function for 4D noise, same hash as random2 with two more terms
+fn random4(v: (f32, f32, f32, f32)) -> f32 {
+    let dot = v.0 * 12.9898 + v.1 * 78.233 + v.2 * 37.719 + v.3 * 4.581;
+    let sin_val = dot.sin();
+    ((sin_val * 43758.547).fract() + 1.0) / 2.0 // Remap to [0, 1]
+}
+
+// 4D noise function, interpolating the 16 corners of the enclosing cell
+pub fn noise4d(p: (f32, f32, f32, f32)) -> f32 {
+    let p = [p.0, p.1, p.2, p.3];
+    let i = p.map(|x| x.floor());
+    let t = [0, 1, 2, 3].map(|axis| smooth_interpolation(p[axis] - i[axis]));
+
+    let mut res = 0.0;
+    for corner in 0..16 {
+        let offset = [0, 1, 2, 3].map(|axis| ((corner >> axis) & 1) as f32);
+        let weight = (0..4).map(|axis| if offset[axis] > 0.0 { t[axis] } else { 1.0 - t[axis] }).product::<f32>();
+        res += weight * random4((i[0] + offset[0], i[1] + offset[1], i[2] + offset[2], i[3] + offset[3]));
+    }
+    res
+}
+
+// fBm that repeats after `period` seconds: instead of drifting through the
+// noise, time goes around a circle in two extra dimensions. The circle is
+// sized so features change about as fast as with fbm_noise
+pub fn fbm_noise_looped(p: (f32, f32), time: f32, period: f32, octaves: usize, persistence: f32) -> f32 {
+    const DRIFT_SPEED: f32 = 1.8; // Speed through the noise of the first fbm_noise octave
+    let angle = std::f32::consts::TAU * crate::looping::phase(time, period);
+    let radius = DRIFT_SPEED * period / std::f32::consts::TAU;
+    let (circle_x, circle_y) = (radius * angle.cos(), radius * angle.sin());
+
+    let mut value = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut total_amplitude = 0.0;
+
+    for _ in 0..octaves {
+        let noise_input = (p.0 * frequency, p.1 * frequency, circle_x * frequency, circle_y * frequency);
+        value += noise4d(noise_input) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= persistence;
+        frequency *= 2.0;
+    }
+
+    value / total_amplitude
+}
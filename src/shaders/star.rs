@@ -1,4 +1,8 @@
-use crate::shaders::noise::{fbm_noise, noise2d};
+use crate::shaders::noise::{fbm_noise, fbm_noise_looped};
+use crate::looping::looped_angle;
+use crate::shaders::Shader;
+use crate::fragment::Fragment;
+use crate::triangle::Uniforms;
 use nalgebra_glm::Vec3;
 use raylib::prelude::Color;
 
@@ -25,9 +29,21 @@ impl Star {
         self.rotation += dt * 0.1; // Rotación lenta
     }
 
+    // Rotation to draw the star with at `time`. With a loop period it comes
+    // from the time instead of `update`, rounded to whole turns of the slower
+    // y axis per period. Periods shorter than about two minutes still get one
+    // turn, so short loops spin faster than the star normally does
+    pub fn rotation_at(&self, time: f32, loop_period: Option<f32>) -> f32 {
+        match loop_period {
+            Some(period) => 2.0 * looped_angle(time, 0.05, period, 1),
+            None => self.rotation,
+        }
+    }
+
     // Evaluate the star's surface properties at a given world position
     // This simulates the shader logic for calculating color and displacement
-    pub fn evaluate_at(&self, world_pos: &Vec3, _normal: &Vec3, time: f32) -> (Color, f32) {
+    // With a loop period, the color at `time` and `time + period` is the same
+    pub fn evaluate_at(&self, world_pos: &Vec3, _normal: &Vec3, time: f32, loop_period: Option<f32>) -> (Color, f32) {
         // Convert world position to a point on the sphere's surface relative to its center
         let local_pos = world_pos - self.position;
         let normalized_pos = local_pos.normalize();
@@ -39,7 +55,11 @@ impl Star {
         // --- Noise-based Displacement & Color ---
         // Sample noise on the sphere's surface for turbulence
         let noise_scale = 5.0; // Controls the size of the features
-        let turbulence_noise = fbm_noise((phi * noise_scale, theta * noise_scale), time, 4, 0.5);
+        let noise_pos = (phi * noise_scale, theta * noise_scale);
+        let turbulence_noise = match loop_period {
+            Some(period) => fbm_noise_looped(noise_pos, time, period, 4, 0.5),
+            None => fbm_noise(noise_pos, time, 4, 0.5),
+        };
 
         // Displace the radius based on noise (for animation effect)
         let displaced_radius = self.radius * (1.0 + turbulence_noise * 0.1); // 10% displacement
@@ -59,7 +79,11 @@ impl Star {
         let turbulence_intensity = (turbulence_noise + 1.0) * 0.5; // [0,1]
 
         // 3. Global Pulsation Effect (cyclic brightness change)
-        let pulsation = (time * 2.0).sin() * 0.5 + 0.5; // Oscillates between 0.0 and 1.0
+        let pulsation_angle = match loop_period {
+            Some(period) => looped_angle(time, 2.0, period, 1),
+            None => time * 2.0,
+        };
+        let pulsation = pulsation_angle.sin() * 0.5 + 0.5; // Oscillates between 0.0 and 1.0
 
         // 4. Combine effects for final color
         // Base color is orange, but modulated by turbulence and pulsation
@@ -87,4 +111,23 @@ impl Star {
         // Return the color and the displaced radius (for depth)
         (final_color, surface_distance)
     }
-}
\ No newline at end of file
+}
+
+impl Shader for Star {
+    fn fragment_shader(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let (color, _distance) = self.evaluate_at(&fragment.world_position, &fragment.normal, uniforms.time, uniforms.loop_period);
+
+        // The material of the sub-mesh tints the surface with its diffuse
+        // color and adds its emissive color on top
+        let Some(material) = &uniforms.material else {
+            return color;
+        };
+        let channel = |value: u8, diffuse: f32, emissive: f32| (value as f32 * diffuse + emissive * 255.0).clamp(0.0, 255.0) as u8;
+        Color::new(
+            channel(color.r, material.diffuse.x, material.emissive.x),
+            channel(color.g, material.diffuse.y, material.emissive.y),
+            channel(color.b, material.diffuse.z, material.emissive.z),
+            color.a,
+        )
+    }
+}
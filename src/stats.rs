@@ -0,0 +1,40 @@
+// stats.rs
+use crate::mesh::lod::MAX_LOD_LEVELS;
+use std::fmt;
+
+// Per-frame counters collected by the pipeline, shown as debug info
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    pub vertices_shaded: usize,      // Vertex shader invocations (post-transform cache misses)
+    pub vertex_cache_hits: usize,
+    pub triangles_submitted: usize,
+    pub triangles_clipped: usize,    // Completely outside the view frustum
+    pub triangles_culled: usize,     // Rejected by face culling
+    pub triangles_rasterized: usize,
+    pub objects_culled: usize,       // Entirely outside the view frustum, never transformed
+    pub objects_per_lod: [usize; MAX_LOD_LEVELS], // Objects drawn with each level of detail
+}
+
+impl RenderStats {
+    pub fn reset(&mut self) {
+        *self = RenderStats::default();
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let used_levels = self.objects_per_lod.iter().rposition(|&count| count > 0).map_or(1, |last| last + 1);
+        write!(
+            f,
+            "{} verts shaded ({} cache hits) - {} tris: {} drawn, {} culled, {} clipped - {} objects culled, per LOD {:?}",
+            self.vertices_shaded,
+            self.vertex_cache_hits,
+            self.triangles_submitted,
+            self.triangles_rasterized,
+            self.triangles_culled,
+            self.triangles_clipped,
+            self.objects_culled,
+            &self.objects_per_lod[..used_levels]
+        )
+    }
+}
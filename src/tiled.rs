@@ -0,0 +1,252 @@
+// tiled.rs
+// Tile-based binning rasterizer. Triangles go through the usual front end
+// (vertex stage, clipping, setup) on the calling thread, are binned into every
+// screen tile their bounding box touches, and the tiles are then shaded in
+// parallel. Tiles span the whole width of the framebuffer, so each one is a
+// contiguous run of rows and workers render straight into their own slice of
+// it. Bins keep submission order and the shading stage uses exact integer
+// edge functions, so the output is bit-identical to drawing the same triangles
+// one by one with `pipeline::draw`.
+//
+// Meshes are queued with `draw` and the frame is shaded by `flush`, so the
+// workers are spawned once per frame rather than once per mesh. A thread pool
+// would save little over that, and its workers could not borrow the frame.
+use crate::framebuffer::{Framebuffer, RenderTarget};
+use crate::mesh::Mesh;
+use crate::pipeline::{assemble_triangles, draw_calls};
+use crate::shaders::Shader;
+use crate::stats::RenderStats;
+use crate::triangle::{process_triangle, shade_triangle, ScreenTriangle, Uniforms};
+use std::sync::Mutex;
+use std::thread;
+
+pub const DEFAULT_TILE_SIZE: usize = 16; // Rows per tile
+
+pub struct TiledRasterizer {
+    pub tile_size: usize,
+    pub threads: usize,
+    // Draw calls queued since the last flush: their uniforms, and their
+    // screen triangles with the call each one comes from. Kept between frames
+    // so their storage is reused
+    calls: Vec<Uniforms>,
+    triangles: Vec<(ScreenTriangle, usize)>,
+    bins: Vec<Vec<usize>>,
+}
+
+impl TiledRasterizer {
+    pub fn new(tile_size: usize, threads: usize) -> Self {
+        TiledRasterizer {
+            tile_size: tile_size.max(1),
+            threads: threads.max(1),
+            calls: Vec::new(),
+            triangles: Vec::new(),
+            bins: Vec::new(),
+        }
+    }
+
+    // One worker per available core
+    pub fn with_available_threads(tile_size: usize) -> Self {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        TiledRasterizer::new(tile_size, threads)
+    }
+
+    // Queues an indexed mesh: runs the front end and keeps the screen
+    // triangles until `flush` shades them
+    pub fn draw<S: Shader>(&mut self, mesh: &Mesh, uniforms: &Uniforms, shader: &S, stats: &mut RenderStats) {
+        let calls = draw_calls(mesh, uniforms);
+        let (first_call, triangles) = (self.calls.len(), &mut self.triangles);
+        assemble_triangles(mesh, &calls, uniforms, shader, stats, |v1, v2, v3, call, stats| {
+            process_triangle(v1, v2, v3, &calls[call].1, stats, |screen_triangle| triangles.push((screen_triangle, first_call + call)));
+        });
+        self.calls.extend(calls.into_iter().map(|(_, uniforms)| uniforms));
+    }
+
+    // Shades everything queued since the last flush into the framebuffer, with
+    // the shader the meshes were drawn with
+    pub fn flush<S: Shader + Sync>(&mut self, framebuffer: &mut Framebuffer, shader: &S) {
+        if !self.triangles.is_empty() {
+            self.shade(framebuffer, shader);
+        }
+        self.calls.clear();
+        self.triangles.clear();
+    }
+
+    fn shade<S: Shader + Sync>(&mut self, framebuffer: &mut Framebuffer, shader: &S) {
+        // 1. Binning
+        let tile_size = self.tile_size;
+        let tile_count = framebuffer.height.div_ceil(tile_size);
+        self.bins.resize_with(tile_count, Vec::new);
+        self.bins.iter_mut().for_each(Vec::clear);
+
+        for (index, (triangle, _)) in self.triangles.iter().enumerate() {
+            let max_x = triangle.max_x.min(framebuffer.width);
+            let max_y = triangle.max_y.min(framebuffer.height);
+            if triangle.min_x >= max_x || triangle.min_y >= max_y {
+                continue;
+            }
+
+            for bin in &mut self.bins[triangle.min_y / tile_size..=(max_y - 1) / tile_size] {
+                bin.push(index);
+            }
+        }
+
+        // 2. Shade the tiles in parallel. Workers take the next tile that has
+        // triangles, with the rows of the framebuffer it covers
+        let (bins, triangles, calls) = (&self.bins, &self.triangles, &self.calls);
+        let (width, antialiasing) = (framebuffer.width, framebuffer.antialiasing());
+        let row_len = width * antialiasing.samples();
+        let (color, depth) = framebuffer.storage_mut();
+        let tiles = color
+            .chunks_mut(tile_size * row_len)
+            .zip(depth.chunks_mut(tile_size * row_len))
+            .enumerate()
+            .filter(|(tile, _)| !bins[*tile].is_empty());
+        let tiles = Mutex::new(tiles);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(tile_count) {
+                scope.spawn(|| loop {
+                    let Some((tile, (color, depth))) = tiles.lock().unwrap().next() else {
+                        break;
+                    };
+                    let mut target = RenderTarget {
+                        x: 0,
+                        y: tile * tile_size,
+                        width,
+                        height: color.len() / row_len,
+                        antialiasing,
+                        color,
+                        depth,
+                    };
+                    for &index in &bins[tile] {
+                        let (triangle, call) = &triangles[index];
+                        shade_triangle(triangle, &mut target, &calls[*call], shader);
+                    }
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::Fragment;
+    use crate::framebuffer::AntiAliasing;
+    use crate::matrix::{create_model_matrix, create_projection_matrix, create_viewport_matrix};
+    use crate::mesh::primitives;
+    use crate::pipeline;
+    use nalgebra_glm::Vec3;
+    use raylib::prelude::Color;
+
+    // Colors every fragment from its interpolated attributes, so any
+    // difference in coverage, depth or weights shows up in the output
+    struct Attributes;
+
+    impl Shader for Attributes {
+        fn fragment_shader(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u8;
+            let normal = fragment.normal * 0.5 + Vec3::new(0.5, 0.5, 0.5);
+            Color::new(channel(normal.x), channel(fragment.tex_coords.x), channel(fragment.tex_coords.y * normal.z), 255)
+        }
+    }
+
+    // Overlapping objects, one of them crossing the near plane and the
+    // screen borders
+    fn scene(width: usize, height: usize) -> Vec<(crate::mesh::Mesh, Uniforms)> {
+        let mut uniforms = Uniforms::new();
+        uniforms.view_matrix = nalgebra_glm::look_at(&Vec3::new(0.0, 0.0, 3.0), &Vec3::zeros(), &Vec3::y());
+        uniforms.projection_matrix = create_projection_matrix(1.0, width as f32 / height as f32, 0.1, 100.0);
+        uniforms.viewport_matrix = create_viewport_matrix(width as f32, height as f32);
+        let placed = |mesh, position, rotation| {
+            let uniforms = Uniforms { model_matrix: create_model_matrix(position, 1.0, rotation), ..uniforms.clone() };
+            (mesh, uniforms)
+        };
+
+        vec![
+            placed(primitives::uv_sphere(1.0, 24, 12), Vec3::new(-0.4, 0.1, 0.0), Vec3::new(0.3, 0.5, 0.0)),
+            placed(primitives::icosphere(0.8, 2), Vec3::new(0.5, -0.2, 0.4), Vec3::zeros()),
+            placed(primitives::cube(1.5), Vec3::new(0.9, 0.8, 2.2), Vec3::new(0.7, 0.2, 0.4)),
+        ]
+    }
+
+    // The scene drawn one triangle at a time, and with the tiled rasterizer.
+    // Both framebuffers are left unresolved
+    fn render(width: usize, height: usize, antialiasing: AntiAliasing, tile_size: usize, threads: usize) -> [(Framebuffer, RenderStats); 2] {
+        let shader = Attributes;
+        let mut expected = Framebuffer::new(width, height);
+        expected.set_antialiasing(antialiasing);
+        let mut expected_stats = RenderStats::default();
+        for (mesh, uniforms) in scene(width, height) {
+            pipeline::draw(&mesh, &uniforms, &mut expected, &shader, &mut expected_stats);
+        }
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        framebuffer.set_antialiasing(antialiasing);
+        let mut stats = RenderStats::default();
+        let mut rasterizer = TiledRasterizer::new(tile_size, threads);
+        for (mesh, uniforms) in scene(width, height) {
+            rasterizer.draw(&mesh, &uniforms, &shader, &mut stats);
+        }
+        rasterizer.flush(&mut framebuffer, &shader);
+        [(expected, expected_stats), (framebuffer, stats)]
+    }
+
+    // Compares the per-sample storage, then the resolved framebuffers
+    fn assert_matches(width: usize, height: usize, antialiasing: AntiAliasing, tile_size: usize, threads: usize) {
+        let [(mut expected, expected_stats), (mut framebuffer, stats)] = render(width, height, antialiasing, tile_size, threads);
+        let case = format!("{}x{}, {:?}, tiles of {} rows, {} threads", width, height, antialiasing, tile_size, threads);
+        let (expected_color, expected_depth) = expected.storage_mut();
+        assert!(expected_depth.iter().any(|depth| depth.is_finite()), "nothing drawn with {}", case);
+        let (expected_color, expected_depth) = (expected_color.to_vec(), expected_depth.to_vec());
+        let (color, depth) = framebuffer.storage_mut();
+        assert!(color == expected_color, "sample colors differ with {}", case);
+        assert!(depth == expected_depth, "sample depths differ with {}", case);
+
+        expected.resolve();
+        framebuffer.resolve();
+        assert!(framebuffer.buffer == expected.buffer, "colors differ with {}", case);
+        assert!(framebuffer.zbuffer == expected.zbuffer, "depths differ with {}", case);
+        assert_eq!(stats.triangles_rasterized, expected_stats.triangles_rasterized, "{}", case);
+    }
+
+    #[test]
+    fn matches_drawing_one_triangle_at_a_time() {
+        for antialiasing in [AntiAliasing::None, AntiAliasing::Ssaa2x2, AntiAliasing::Msaa4x] {
+            for (tile_size, threads) in [(1, 1), (7, 3), (DEFAULT_TILE_SIZE, 4), (64, 2), (200, 8)] {
+                assert_matches(83, 61, antialiasing, tile_size, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn shades_a_shorter_last_tile() {
+        // 61 rows leave a last tile of 13 rows, and of 4 rows
+        let (width, height) = (83, 61);
+        for tile_size in [DEFAULT_TILE_SIZE, 19] {
+            assert_ne!(height % tile_size, 0);
+            let [(expected, _), _] = render(width, height, AntiAliasing::None, tile_size, 1);
+            let last_tile = &expected.zbuffer[height / tile_size * tile_size * width..];
+            assert!(last_tile.iter().any(|depth| depth.is_finite()), "the scene misses the last tile");
+            assert_matches(width, height, AntiAliasing::None, tile_size, 2);
+        }
+    }
+
+    #[test]
+    fn spawns_no_more_workers_than_tiles() {
+        // Two tiles and a single one, with more threads than that
+        assert_matches(83, 61, AntiAliasing::None, 32, 8);
+        assert_matches(83, 61, AntiAliasing::None, 61, 4);
+    }
+
+    #[test]
+    fn offsets_tiles_by_the_samples_per_row() {
+        // With several samples per pixel, every tile starts tile_size rows of
+        // width * samples values further into the sample storage
+        for antialiasing in [AntiAliasing::Msaa4x, AntiAliasing::Ssaa4x4] {
+            for (tile_size, threads) in [(1, 4), (7, 3), (DEFAULT_TILE_SIZE, 2)] {
+                assert_matches(83, 61, antialiasing, tile_size, threads);
+            }
+        }
+    }
+}
@@ -1,119 +1,580 @@
 use crate::vertex::Vertex;
-use crate::shaders::star::Star;
-use crate::framebuffer::Framebuffer;
+use crate::fragment::Fragment;
+use crate::shaders::Shader;
+use crate::clipping::clip_triangle;
+use crate::framebuffer::{Framebuffer, RenderTarget, MAX_SAMPLES};
+use crate::stats::RenderStats;
+use crate::mesh::Material;
 use nalgebra_glm::{Mat4, Vec3, Vec4};
+use raylib::prelude::Color;
+
+// How vertex attributes are interpolated across a triangle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    PerspectiveCorrect, // Weights divided by w, correct in 3D
+    Affine,             // Plain screen-space weights, only kept for comparison
+}
+
+// Which faces are discarded before rasterization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+// Winding order (as seen on screen) of front-facing triangles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
 
 // Uniforms struct to pass data to rendering functions
 #[derive(Debug, Clone)]
 pub struct Uniforms {
     pub time: f32,
+    pub loop_period: Option<f32>, // Seconds after which the animation repeats exactly, see looping.rs
     pub model_matrix: Mat4,
     pub view_matrix: Mat4,
     pub projection_matrix: Mat4,
     pub viewport_matrix: Mat4,
+    pub interpolation: Interpolation,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub material: Option<Material>, // Material of the sub-mesh being drawn, set per draw call
 }
 
 impl Uniforms {
     pub fn new() -> Self {
         Uniforms {
             time: 0.0,
+            loop_period: None,
             model_matrix: Mat4::identity(),
             view_matrix: Mat4::identity(),
             projection_matrix: Mat4::identity(),
             viewport_matrix: Mat4::identity(),
+            interpolation: Interpolation::PerspectiveCorrect,
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            material: None,
         }
     }
 }
 
-// Rasterization function - renders a single triangle with the star shader
-pub fn triangle_3d_with_star_shader(v1: &Vertex, v2: &Vertex, v3: &Vertex, uniforms: &Uniforms, framebuffer: &mut Framebuffer, star: &Star) {
-    const WIDTH: usize = 800;
-    const HEIGHT: usize = 600;
+// Why a triangle did not make it to the shading stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Degenerate,
+    Culled,
+    Empty, // Entirely left of or above the screen
+}
+
+// A clipped triangle after screen-space setup, ready to be shaded
+#[derive(Debug, Clone)]
+pub struct ScreenTriangle {
+    vertices: [Vertex; 3],
+    depth: [f32; 3],
+    inv_w: (f32, f32, f32),
+    det: i64,
+    edges: [Edge; 3],
+    // Pixel bounding box, max exclusive. Not clamped to the screen's right and
+    // bottom borders, every render target clips it to its own rectangle
+    pub min_x: usize,
+    pub max_x: usize,
+    pub min_y: usize,
+    pub max_y: usize,
+}
+
+// Directed edge in sub-pixel coordinates, and whether the fill rule owns it
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    a: (i64, i64),
+    b: (i64, i64),
+    top_left: bool,
+}
+
+// Rendering function - clips a triangle whose vertices went through the vertex
+// stage against the view frustum and rasterizes whatever is left with the given shader
+pub fn triangle<S: Shader>(v1: &Vertex, v2: &Vertex, v3: &Vertex, uniforms: &Uniforms, framebuffer: &mut Framebuffer, shader: &S, stats: &mut RenderStats) {
+    let mut target = framebuffer.target();
+
+    process_triangle(v1, v2, v3, uniforms, stats, |screen_triangle| {
+        shade_triangle(&screen_triangle, &mut target, uniforms, shader);
+    });
+}
 
-    // 1. Transform vertices to clip space
-    let mvp_matrix = uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix;
-    let clip_v1 = mvp_matrix * Vec4::new(v1.position.x, v1.position.y, v1.position.z, 1.0);
-    let clip_v2 = mvp_matrix * Vec4::new(v2.position.x, v2.position.y, v2.position.z, 1.0);
-    let clip_v3 = mvp_matrix * Vec4::new(v3.position.x, v3.position.y, v3.position.z, 1.0);
+// Part of the pipeline shared by every rasterizer between primitive assembly
+// and shading: clipping and triangle setup. The vertices must already be in
+// clip space. Each resulting screen triangle is handed to `emit`
+pub fn process_triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex, uniforms: &Uniforms, stats: &mut RenderStats, mut emit: impl FnMut(ScreenTriangle)) {
+    stats.triangles_submitted += 1;
 
-    // 2. Perspective Division (NDC - Normalized Device Coordinates)
-    // Check for w=0 to avoid division by zero
-    if clip_v1.w == 0.0 || clip_v2.w == 0.0 || clip_v3.w == 0.0 {
-        // Skip this triangle if any vertex has w=0
+    // 1. Clipping: the result is a convex polygon, rasterized as a fan
+    let polygon = clip_triangle(v1, v2, v3);
+    if polygon.is_empty() {
+        stats.triangles_clipped += 1;
         return;
     }
+
+    // 2. Setup. Every triangle of the fan has the same winding, so if one of
+    // them is culled the whole polygon is. A polygon that covers no pixel
+    // counts as neither culled nor rasterized
+    let mut rasterized = false;
+    for i in 1..polygon.len().saturating_sub(1) {
+        match setup_triangle(&polygon[0], &polygon[i], &polygon[i + 1], uniforms) {
+            Ok(screen_triangle) => {
+                emit(screen_triangle);
+                rasterized = true;
+            }
+            Err(Rejection::Culled) => {
+                stats.triangles_culled += 1;
+                return;
+            }
+            Err(Rejection::Degenerate | Rejection::Empty) => {}
+        }
+    }
+    if rasterized {
+        stats.triangles_rasterized += 1;
+    }
+}
+
+// Decides from the screen-space signed area whether the triangle is discarded.
+// With y pointing down, counter-clockwise triangles have a negative area
+fn is_culled(det: i64, uniforms: &Uniforms) -> bool {
+    let counter_clockwise = det < 0;
+    let front_facing = match uniforms.front_face {
+        FrontFace::CounterClockwise => counter_clockwise,
+        FrontFace::Clockwise => !counter_clockwise,
+    };
+
+    match uniforms.cull_mode {
+        CullMode::None => false,
+        CullMode::Back => !front_facing,
+        CullMode::Front => front_facing,
+    }
+}
+
+// Triangle setup - projects a clipped triangle to the screen and prepares its
+// edge functions
+pub fn setup_triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex, uniforms: &Uniforms) -> Result<ScreenTriangle, Rejection> {
+    let clip_v1 = v1.transformed_position;
+    let clip_v2 = v2.transformed_position;
+    let clip_v3 = v3.transformed_position;
+
+    // Perspective Division (NDC - Normalized Device Coordinates)
+    // Clipping against the near plane guarantees w > 0 here
     let ndc_v1 = Vec3::new(clip_v1.x / clip_v1.w, clip_v1.y / clip_v1.w, clip_v1.z / clip_v1.w);
     let ndc_v2 = Vec3::new(clip_v2.x / clip_v2.w, clip_v2.y / clip_v2.w, clip_v2.z / clip_v2.w);
     let ndc_v3 = Vec3::new(clip_v3.x / clip_v3.w, clip_v3.y / clip_v3.w, clip_v3.z / clip_v3.w);
 
-    // 3. Viewport Transformation (Screen Space)
+    // 1/w of each vertex, used to make attribute interpolation perspective-correct
+    let inv_w = (1.0 / clip_v1.w, 1.0 / clip_v2.w, 1.0 / clip_v3.w);
+
+    // Viewport Transformation (Screen Space)
     let screen_v1 = uniforms.viewport_matrix * Vec4::new(ndc_v1.x, ndc_v1.y, ndc_v1.z, 1.0);
     let screen_v2 = uniforms.viewport_matrix * Vec4::new(ndc_v2.x, ndc_v2.y, ndc_v2.z, 1.0);
     let screen_v3 = uniforms.viewport_matrix * Vec4::new(ndc_v3.x, ndc_v3.y, ndc_v3.z, 1.0);
 
-    // 4. Rasterization Loop (Barycentric Coordinates)
-    // Convert to integers for pixel coordinates, but be careful of large values
-    let x1 = screen_v1.x as i32;
-    let y1 = screen_v1.y as i32;
-    let x2 = screen_v2.x as i32;
-    let y2 = screen_v2.y as i32;
-    let x3 = screen_v3.x as i32;
-    let y3 = screen_v3.y as i32;
-
-    // Determine the bounding box, clamped to screen dimensions
-    let min_x = x1.min(x2).min(x3).max(0).min(WIDTH as i32 - 1) as usize;
-    let max_x = (x1.max(x2).max(x3) + 1).max(0).min(WIDTH as i32) as usize;
-    let min_y = y1.min(y2).min(y3).max(0).min(HEIGHT as i32 - 1) as usize;
-    let max_y = (y1.max(y2).max(y3) + 1).max(0).min(HEIGHT as i32) as usize;
+    // Snap to the sub-pixel grid (fixed point) so that shared edges produce
+    // exactly the same edge functions in both triangles
+    let p1 = (snap(screen_v1.x), snap(screen_v1.y));
+    let p2 = (snap(screen_v2.x), snap(screen_v2.y));
+    let p3 = (snap(screen_v3.x), snap(screen_v3.y));
 
+    // Twice the signed area, its sign gives the winding in screen space
+    let det = edge_function(p1, p2, p3);
+    if det == 0 {
+        return Err(Rejection::Degenerate);
+    }
+
+    // Face culling
+    if is_culled(det, uniforms) {
+        return Err(Rejection::Culled);
+    }
+
+    // Determine the bounding box of the pixels the triangle touches (any sample
+    // of them may be covered). Clipping already keeps it on screen, up to rounding
+    let min_x = pixel_of(p1.0.min(p2.0).min(p3.0)).max(0);
+    let max_x = (pixel_of(p1.0.max(p2.0).max(p3.0)) + 1).max(0);
+    let min_y = pixel_of(p1.1.min(p2.1).min(p3.1)).max(0);
+    let max_y = (pixel_of(p1.1.max(p2.1).max(p3.1)) + 1).max(0);
+
+    if min_x >= max_x || min_y >= max_y {
+        return Err(Rejection::Empty);
+    }
+
+    // Top-left fill rule: a pixel center lying exactly on an edge belongs to the
+    // triangle only if that edge is a top or a left edge
+    let edge = |a: (i64, i64), b: (i64, i64)| Edge {
+        a,
+        b,
+        top_left: if det > 0 { is_top_left(a, b) } else { is_top_left(b, a) },
+    };
+
+    Ok(ScreenTriangle {
+        vertices: [*v1, *v2, *v3],
+        depth: [screen_v1.z, screen_v2.z, screen_v3.z],
+        inv_w,
+        det,
+        edges: [edge(p2, p3), edge(p3, p1), edge(p1, p2)],
+        min_x: min_x as usize,
+        max_x: max_x as usize,
+        min_y: min_y as usize,
+        max_y: max_y as usize,
+    })
+}
+
+// Shading stage - rasterizes the part of the triangle that falls inside the
+// render target. Edge functions are exact integers, so the result for a pixel
+// does not depend on how the screen is split into targets
+pub fn shade_triangle<S: Shader>(triangle: &ScreenTriangle, target: &mut RenderTarget, uniforms: &Uniforms, shader: &S) {
+    let min_x = triangle.min_x.max(target.x);
+    let max_x = triangle.max_x.min(target.x + target.width);
+    let min_y = triangle.min_y.max(target.y);
+    let max_y = triangle.max_y.min(target.y + target.height);
     if min_x >= max_x || min_y >= max_y {
-        // Degenerate or off-screen triangle
         return;
     }
 
-    // Pre-calculate edge function coefficients for barycentric coords
-    let det = (y2 - y3) * (x1 - x3) + (x3 - x2) * (y1 - y3);
-    if det == 0 { return; } // Degenerate triangle
+    let [v1, v2, v3] = &triangle.vertices;
+    let det = triangle.det;
+    let edges = &triangle.edges;
+    let covered = |e: [i64; 3]| {
+        (0..3).all(|i| {
+            let e = if det > 0 { e[i] } else { -e[i] };
+            e > 0 || (e == 0 && edges[i].top_left)
+        })
+    };
+    let barycentric = |e: [i64; 3]| (e[0] as f32 / det as f32, e[1] as f32 / det as f32, e[2] as f32 / det as f32);
+    let depth_at = |(w1, w2, w3): (f32, f32, f32)| w1 * triangle.depth[0] + w2 * triangle.depth[1] + w3 * triangle.depth[2];
+
+    // Runs the fragment stage at the point with the given edge function values
+    let shade = |e: [i64; 3]| {
+        // Interpolate the vertex attributes for shading
+        let (w1, w2, w3) = barycentric(e);
+        let weights = match uniforms.interpolation {
+            Interpolation::PerspectiveCorrect => perspective_weights((w1, w2, w3), triangle.inv_w),
+            Interpolation::Affine => (w1, w2, w3),
+        };
+        let mut fragment = Fragment::new();
+        interpolate_attributes(&mut fragment, v1, v2, v3, weights);
+
+        // Run the fragment stage of the shader
+        let color_raylib = shader.fragment_shader(&fragment, uniforms);
+
+        // Convert raylib Color to u32 for the framebuffer
+        ((color_raylib.r as u32) << 16) | ((color_raylib.g as u32) << 8) | (color_raylib.b as u32)
+    };
+
+    // Offsets of the edge functions from the pixel center to each sample
+    let positions = target.antialiasing.sample_positions();
+    let samples = positions.len();
+    let per_sample_shading = target.antialiasing.shades_per_sample();
+    let mut offsets = [[0; 3]; MAX_SAMPLES];
+    for (offset, &(sx, sy)) in offsets.iter_mut().zip(positions) {
+        let (dx, dy) = (snap(sx), snap(sy));
+        *offset = edges.map(|edge| -(edge.b.1 - edge.a.1) * dx + (edge.b.0 - edge.a.0) * dy);
+    }
+    let sample_offsets = &offsets[..samples];
+
+    // Edge functions at the first pixel center, and their increments per pixel
+    let origin = (min_x as i64 * SUBPIXEL_ONE + SUBPIXEL_HALF, min_y as i64 * SUBPIXEL_ONE + SUBPIXEL_HALF);
+    let mut row = edges.map(|edge| edge_function(edge.a, edge.b, origin));
+    let step_x = edges.map(|edge| -(edge.b.1 - edge.a.1) * SUBPIXEL_ONE);
+    let step_y = edges.map(|edge| (edge.b.0 - edge.a.0) * SUBPIXEL_ONE);
 
     for y in min_y..max_y {
+        let mut e = row;
         for x in min_x..max_x {
-            let px = x as i32;
-            let py = y as i32;
-
-            // Calculate barycentric coordinates (w1, w2, w3)
-            let w1 = ((y2 - y3) * (px - x3) + (x3 - x2) * (py - y3)) as f32 / det as f32;
-            let w2 = ((y3 - y1) * (px - x3) + (x1 - x3) * (py - y3)) as f32 / det as f32;
-            let w3 = 1.0 - w1 - w2;
-
-            // Check if point is inside the triangle
-            if w1 >= 0.0 && w2 >= 0.0 && w3 >= 0.0 {
-                // Interpolate Z-depth using barycentric coordinates
-                let z = w1 * screen_v1.z + w2 * screen_v2.z + w3 * screen_v3.z;
-
-                // Calculate buffer index safely
-                let buffer_index = y * WIDTH + x;
-                if buffer_index >= framebuffer.buffer.len() {
-                    // This should not happen with the clamped min/max, but good to check
+            let buffer_index = ((y - target.y) * target.width + (x - target.x)) * samples;
+
+            // With multisampling the pixel is shaded once, at its center if the
+            // center is covered and at the first covered sample otherwise
+            let mut pixel_color = None;
+
+            for (sample, offset) in sample_offsets.iter().enumerate() {
+                // Check if the sample is inside the triangle
+                let es = [e[0] + offset[0], e[1] + offset[1], e[2] + offset[2]];
+                if !covered(es) {
                     continue;
                 }
 
-                // Check Z-buffer
-                if z < framebuffer.zbuffer[buffer_index] {
-                    // Interpolate world position for shading
-                    let world_pos = w1 * v1.position + w2 * v2.position + w3 * v3.position;
-                    let normal = (w1 * v1.normal + w2 * v2.normal + w3 * v3.normal).normalize(); // Interpolate and normalize normal
+                // Interpolate Z-depth using barycentric coordinates and check the Z-buffer
+                let z = depth_at(barycentric(es));
+                if z >= target.depth[buffer_index + sample] {
+                    continue;
+                }
 
-                    // Evaluate the star shader at this fragment's world position
-                    let (color_raylib, _distance) = star.evaluate_at(&world_pos, &normal, uniforms.time);
+                let color = if per_sample_shading {
+                    shade(es)
+                } else {
+                    *pixel_color.get_or_insert_with(|| shade(if covered(e) { e } else { es }))
+                };
 
-                    // Convert raylib Color to u32 for the framebuffer
-                    let color_u32 = ((color_raylib.r as u32) << 16) | ((color_raylib.g as u32) << 8) | (color_raylib.b as u32);
+                // Write color and depth to the target
+                target.color[buffer_index + sample] = color;
+                target.depth[buffer_index + sample] = z;
+            }
 
-                    // Write color and depth to framebuffer
-                    framebuffer.buffer[buffer_index] = color_u32;
-                    framebuffer.zbuffer[buffer_index] = z;
-                }
+            for i in 0..3 {
+                e[i] += step_x[i];
             }
         }
+
+        for i in 0..3 {
+            row[i] += step_y[i];
+        }
     }
-}
\ No newline at end of file
+}
+
+// Screen positions are snapped to 1/256 of a pixel (8 bits of sub-pixel precision)
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+const SUBPIXEL_HALF: i64 = SUBPIXEL_ONE / 2;
+
+fn snap(value: f32) -> i64 {
+    (value * SUBPIXEL_ONE as f32).round() as i64
+}
+
+// Pixel containing the given sub-pixel coordinate
+fn pixel_of(coord: i64) -> i64 {
+    coord.div_euclid(SUBPIXEL_ONE)
+}
+
+// Edge function of p against the directed edge a -> b (twice the signed area of a, b, p)
+fn edge_function(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+// With positive winding and y pointing down, top edges run exactly
+// horizontally to the right and left edges run upwards
+fn is_top_left(a: (i64, i64), b: (i64, i64)) -> bool {
+    let dx = b.0 - a.0;
+    let dy = b.1 - a.1;
+    dy < 0 || (dy == 0 && dx > 0)
+}
+
+// Turns screen-space barycentric weights into perspective-correct ones:
+// attributes vary linearly in 1/w space, not in screen space
+fn perspective_weights(w: (f32, f32, f32), inv_w: (f32, f32, f32)) -> (f32, f32, f32) {
+    let p1 = w.0 * inv_w.0;
+    let p2 = w.1 * inv_w.1;
+    let p3 = w.2 * inv_w.2;
+    let sum = p1 + p2 + p3;
+    (p1 / sum, p2 / sum, p3 / sum)
+}
+
+// Interpolates every varying of the three vertices into the fragment
+fn interpolate_attributes(fragment: &mut Fragment, v1: &Vertex, v2: &Vertex, v3: &Vertex, (w1, w2, w3): (f32, f32, f32)) {
+    fragment.world_position = w1 * v1.position + w2 * v2.position + w3 * v3.position;
+    fragment.normal = (w1 * v1.normal + w2 * v2.normal + w3 * v3.normal).normalize(); // Interpolate and normalize normal
+    fragment.tex_coords = w1 * v1.tex_coords + w2 * v2.tex_coords + w3 * v3.tex_coords;
+
+    // Tangent direction is renormalized, the bitangent sign is kept as it is
+    let tangent = w1 * v1.tangent.xyz() + w2 * v2.tangent.xyz() + w3 * v3.tangent.xyz();
+    if tangent.norm() > 0.0 {
+        let tangent = tangent.normalize();
+        fragment.tangent = Vec4::new(tangent.x, tangent.y, tangent.z, v1.tangent.w);
+    }
+
+    let channel = |a: u8, b: u8, c: u8| (w1 * a as f32 + w2 * b as f32 + w3 * c as f32).round().clamp(0.0, 255.0) as u8;
+    fragment.color = Color::new(
+        channel(v1.color.r, v2.color.r, v3.color.r),
+        channel(v1.color.g, v2.color.g, v3.color.g),
+        channel(v1.color.b, v2.color.b, v3.color.b),
+        channel(v1.color.a, v2.color.a, v3.color.a),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+    use std::cell::Cell;
+
+    // Gives every fragment it shades a color of its own, and counts them
+    struct Counting {
+        fragments: Cell<u32>,
+    }
+
+    impl Shader for Counting {
+        fn fragment_shader(&self, _fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            let count = self.fragments.get() + 1;
+            self.fragments.set(count);
+            Color::new((count >> 16) as u8, (count >> 8) as u8, count as u8, 255)
+        }
+    }
+
+    // Vertex at a clip-space position with w = 1. With the identity viewport
+    // of `Uniforms::new` that is also its pixel position
+    fn at(x: f32, y: f32, z: f32) -> Vertex {
+        let mut vertex = Vertex::new(Vec3::new(x, y, z), Vec3::z(), Color::WHITE);
+        vertex.transformed_position = Vec4::new(x, y, z, 1.0);
+        vertex
+    }
+
+    // Shades the triangles one after the other, each nearer than the last so
+    // the depth test never hides a second write to a pixel. Returns the
+    // framebuffer and the number of fragments shaded
+    fn rasterize(triangles: &[[(f32, f32); 3]], width: usize, height: usize) -> (Framebuffer, u32) {
+        let mut framebuffer = Framebuffer::new(width, height);
+        let (uniforms, shader) = (Uniforms::new(), Counting { fragments: Cell::new(0) });
+        for (index, corners) in triangles.iter().enumerate() {
+            let z = 0.5 - index as f32 * 0.01;
+            let [v1, v2, v3] = corners.map(|(x, y)| at(x, y, z));
+            let screen_triangle = setup_triangle(&v1, &v2, &v3, &uniforms).unwrap();
+            shade_triangle(&screen_triangle, &mut framebuffer.target(), &uniforms, &shader);
+        }
+        (framebuffer, shader.fragments.get())
+    }
+
+    fn painted_pixels(framebuffer: &Framebuffer) -> Vec<(usize, usize)> {
+        (0..framebuffer.height)
+            .flat_map(|y| (0..framebuffer.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| framebuffer.buffer[y * framebuffer.width + x] != 0)
+            .collect()
+    }
+
+    #[test]
+    fn shared_edges_are_drawn_exactly_once() {
+        // A rectangle whose borders and diagonal all run through pixel
+        // centers, split along either diagonal and in both windings
+        let [a, b, c, d] = [(1.5, 1.5), (9.5, 1.5), (9.5, 7.5), (1.5, 7.5)];
+        for triangles in [[[a, b, c], [a, c, d]], [[a, c, b], [a, d, c]], [[a, b, d], [b, c, d]], [[b, d, c], [d, b, a]]] {
+            let (framebuffer, fragments) = rasterize(&triangles, 12, 10);
+            let painted = painted_pixels(&framebuffer);
+
+            // Top and left borders are in, bottom and right borders are out
+            let expected: Vec<(usize, usize)> = (1..7).flat_map(|y| (1..9).map(move |x| (x, y))).collect();
+            assert_eq!(painted, expected, "{:?}", triangles);
+            assert_eq!(fragments as usize, painted.len(), "a pixel was shaded twice with {:?}", triangles);
+        }
+    }
+
+    #[test]
+    fn fans_cover_their_center_exactly_once() {
+        // Eight triangles around the center of pixel (5, 5), with edges
+        // running through pixel centers in every direction
+        let ring = [(1.5, 1.5), (5.5, 0.5), (9.5, 1.5), (10.5, 5.5), (9.5, 9.5), (5.5, 10.5), (1.5, 9.5), (0.5, 5.5)];
+        let center = (5.5, 5.5);
+        let fan: Vec<[(f32, f32); 3]> = (0..ring.len()).map(|i| [center, ring[i], ring[(i + 1) % ring.len()]]).collect();
+
+        let (framebuffer, fragments) = rasterize(&fan, 12, 12);
+        let painted = painted_pixels(&framebuffer);
+        assert_eq!(fragments as usize, painted.len());
+        assert!(painted.contains(&(5, 5)));
+        // The whole inside of the octagon is covered
+        for (x, y) in [(2, 2), (5, 1), (8, 2), (9, 5), (8, 8), (5, 9), (2, 8), (1, 5), (4, 6), (6, 4)] {
+            assert!(painted.contains(&(x, y)), "pixel ({}, {}) not covered", x, y);
+        }
+    }
+
+    #[test]
+    fn pixel_centers_on_top_and_left_edges_are_covered() {
+        // Right triangle with a horizontal top edge and a vertical left edge
+        // through the centers of row 2 and column 3
+        let (framebuffer, _) = rasterize(&[[(3.5, 2.5), (9.5, 2.5), (3.5, 8.5)]], 12, 12);
+        let painted = painted_pixels(&framebuffer);
+        assert!(painted.contains(&(3, 2)) && painted.contains(&(8, 2)) && painted.contains(&(3, 7)));
+        assert!(painted.iter().all(|&(x, y)| x >= 3 && y >= 2));
+
+        // The same triangle flipped, so those edges become bottom and right ones
+        let (framebuffer, _) = rasterize(&[[(9.5, 8.5), (3.5, 8.5), (9.5, 2.5)]], 12, 12);
+        let painted = painted_pixels(&framebuffer);
+        assert!(!painted.is_empty());
+        assert!(painted.iter().all(|&(x, y)| x < 9 && y < 8));
+    }
+
+    #[test]
+    fn culls_by_winding_and_counts_it() {
+        // Clip-space triangles (w = 1) drawn through the whole front end; the
+        // viewport flips y, so the winding seen on screen is the one in NDC
+        let ccw = [(-0.5, -0.5), (0.5, -0.5), (0.0, 0.5)];
+        let cw = [(-0.5, -0.5), (0.0, 0.5), (0.5, -0.5)];
+        let cases = [
+            (CullMode::None, FrontFace::CounterClockwise, [true, true]),
+            (CullMode::Back, FrontFace::CounterClockwise, [true, false]),
+            (CullMode::Front, FrontFace::CounterClockwise, [false, true]),
+            (CullMode::Back, FrontFace::Clockwise, [false, true]),
+            (CullMode::Front, FrontFace::Clockwise, [true, false]),
+        ];
+
+        for (cull_mode, front_face, drawn) in cases {
+            let mut uniforms = Uniforms::new();
+            uniforms.viewport_matrix = crate::matrix::create_viewport_matrix(16.0, 16.0);
+            uniforms.cull_mode = cull_mode;
+            uniforms.front_face = front_face;
+
+            let mut stats = RenderStats::default();
+            for (corners, drawn) in [ccw, cw].into_iter().zip(drawn) {
+                let mut framebuffer = Framebuffer::new(16, 16);
+                let shader = Counting { fragments: Cell::new(0) };
+                let [v1, v2, v3] = corners.map(|(x, y)| at(x, y, 0.0));
+                triangle(&v1, &v2, &v3, &uniforms, &mut framebuffer, &shader, &mut stats);
+                assert_eq!(shader.fragments.get() > 0, drawn, "{:?} {:?} {:?}", cull_mode, front_face, corners);
+            }
+
+            let expected_drawn = drawn.iter().filter(|&&drawn| drawn).count();
+            assert_eq!(stats.triangles_submitted, 2);
+            assert_eq!(stats.triangles_rasterized, expected_drawn, "{:?} {:?}", cull_mode, front_face);
+            assert_eq!(stats.triangles_culled, 2 - expected_drawn, "{:?} {:?}", cull_mode, front_face);
+            assert_eq!(stats.triangles_clipped, 0);
+        }
+
+        // A triangle that has to be clipped into a polygon is culled once
+        let mut uniforms = Uniforms::new();
+        uniforms.viewport_matrix = crate::matrix::create_viewport_matrix(16.0, 16.0);
+        uniforms.cull_mode = CullMode::Back;
+        let mut stats = RenderStats::default();
+        let [v1, v2, v3] = [(-2.0, -2.0), (0.0, 2.0), (2.0, -2.0)].map(|(x, y)| at(x, y, 0.0));
+        triangle(&v1, &v2, &v3, &uniforms, &mut Framebuffer::new(16, 16), &Counting { fragments: Cell::new(0) }, &mut stats);
+        assert_eq!((stats.triangles_culled, stats.triangles_rasterized), (1, 0));
+    }
+
+    // Keeps the texture coordinates of the last fragment it shaded
+    struct TexCoords {
+        last: Cell<Vec2>,
+    }
+
+    impl Shader for TexCoords {
+        fn fragment_shader(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            self.last.set(fragment.tex_coords);
+            Color::WHITE
+        }
+    }
+
+    #[test]
+    fn interpolates_perspective_correctly() {
+        // Pixel (0, 0) of a 1x1 target sees a triangle with its corners at
+        // (0, 0), (2, 0) and (0, 2) on screen, but the second one 4 times as
+        // far away. The pixel center has screen weights 1/2, 1/4 and 1/4
+        let corners = [((0.0, 0.0), 1.0, (0.0, 0.0)), ((2.0, 0.0), 4.0, (1.0, 0.0)), ((0.0, 2.0), 1.0, (0.0, 1.0))];
+        let [v1, v2, v3] = corners.map(|((x, y), w, (u, v))| {
+            let mut vertex = at(x, y, 0.5);
+            vertex.transformed_position = Vec4::new(x * w, y * w, 0.5 * w, w);
+            vertex.tex_coords = Vec2::new(u, v);
+            vertex
+        });
+        let shade = |interpolation| {
+            let uniforms = Uniforms { interpolation, ..Uniforms::new() };
+            let shader = TexCoords { last: Cell::new(Vec2::new(-1.0, -1.0)) };
+            let screen_triangle = setup_triangle(&v1, &v2, &v3, &uniforms).unwrap();
+            shade_triangle(&screen_triangle, &mut Framebuffer::new(1, 1).target(), &uniforms, &shader);
+            shader.last.get()
+        };
+
+        // Attributes over w, divided by the interpolated 1/w
+        let weights = [0.5, 0.25, 0.25];
+        let inv_w = corners.map(|(_, w, _)| 1.0 / w);
+        let over_w = |attribute: fn(&(f32, f32)) -> f32| (0..3).map(|i| weights[i] * attribute(&corners[i].2) * inv_w[i]).sum::<f32>();
+        let one_over_w = (0..3).map(|i| weights[i] * inv_w[i]).sum::<f32>();
+        let expected = Vec2::new(over_w(|uv| uv.0) / one_over_w, over_w(|uv| uv.1) / one_over_w);
+        let affine = Vec2::new(0.25, 0.25);
+
+        let correct = shade(Interpolation::PerspectiveCorrect);
+        assert!((correct - expected).norm() < 1e-5, "{:?}", correct);
+        assert!((correct - affine).norm() > 0.1);
+        assert!((shade(Interpolation::Affine) - affine).norm() < 1e-5);
+    }
+}
@@ -20,6 +20,7 @@ pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
     pub tex_coords: Vec2,
+    pub tangent: Vec4, // Direction of increasing u, w is the bitangent sign (0 when unknown)
     pub color: Color,
     pub transformed_position: Vec4,
     pub transformed_normal: Vec3,
@@ -31,9 +32,31 @@ impl Vertex {
             position,
             normal,
             tex_coords: Vec2::new(0.0, 0.0),
+            tangent: Vec4::new(0.0, 0.0, 0.0, 0.0),
             color,
             transformed_position: Vec4::new(0.0, 0.0, 0.0, 1.0),
             transformed_normal: normal,
         }
     }
-}
\ No newline at end of file
+
+    // Linear interpolation between two vertices, used when clipping creates
+    // new vertices along an edge
+    pub fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+        let lerp_u8 = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+        Vertex {
+            position: self.position + (other.position - self.position) * t,
+            normal: self.normal + (other.normal - self.normal) * t,
+            tex_coords: self.tex_coords + (other.tex_coords - self.tex_coords) * t,
+            tangent: self.tangent + (other.tangent - self.tangent) * t,
+            color: Color::new(
+                lerp_u8(self.color.r, other.color.r),
+                lerp_u8(self.color.g, other.color.g),
+                lerp_u8(self.color.b, other.color.b),
+                lerp_u8(self.color.a, other.color.a),
+            ),
+            transformed_position: self.transformed_position + (other.transformed_position - self.transformed_position) * t,
+            transformed_normal: self.transformed_normal + (other.transformed_normal - self.transformed_normal) * t,
+        }
+    }
+}